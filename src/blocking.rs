@@ -0,0 +1,136 @@
+//! The registry of clients blocked on keys, as by BLPOP.
+//!
+//! A command that has nothing to return yet asks the keyspace to block its
+//! client (see `Keyspace::block`). The client's batch is parked here, the
+//! blocked command first, and the keyspace moves on to other clients; the
+//! session simply goes on waiting for its replies. Commands that may have
+//! given a blocked client something to work with signal the key as ready,
+//! and the keyspace then runs the blocked commands again, in the order
+//! their clients blocked. One that no longer blocks unblocks its client,
+//! and the rest of its batch runs. A client whose deadline passes first
+//! gets the reply its command gave when it blocked.
+
+use bytes::Bytes;
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::time::Instant;
+
+use crate::client::ClientId;
+use crate::keyspace::Batch;
+use crate::resp::Frame;
+
+/// A parked batch and what it is waiting for.
+pub struct Blocked {
+    /// The rest of the batch, after the blocked command.
+    pub batch: Batch,
+    /// The blocked command's arguments, for running it again.
+    pub command: Vec<Bytes>,
+    /// The database `keys` are in.
+    pub db: usize,
+    pub keys: Vec<Bytes>,
+    /// When to give up, if ever.
+    pub deadline: Option<Instant>,
+    /// What to reply if the deadline passes.
+    pub timeout_reply: Frame,
+}
+
+#[derive(Default)]
+pub struct BlockedClients {
+    clients: HashMap<ClientId, Blocked>,
+    /// The clients blocked on each key, in the order they blocked.
+    waiting: HashMap<(usize, Bytes), VecDeque<ClientId>>,
+    deadlines: BTreeSet<(Instant, ClientId)>,
+    /// Keys signalled since the blocked clients were last served, each once.
+    ready: VecDeque<(usize, Bytes)>,
+    ready_set: HashSet<(usize, Bytes)>,
+}
+
+impl BlockedClients {
+    pub fn block(&mut self, client: ClientId, blocked: Blocked) {
+        for key in &blocked.keys {
+            self.waiting
+                .entry((blocked.db, key.clone()))
+                .or_default()
+                .push_back(client);
+        }
+        if let Some(deadline) = blocked.deadline {
+            self.deadlines.insert((deadline, client));
+        }
+        self.clients.insert(client, blocked);
+    }
+
+    /// Removes a client from the registry, handing back its batch.
+    pub fn unblock(&mut self, client: ClientId) -> Option<Blocked> {
+        let blocked = self.clients.remove(&client)?;
+        for key in &blocked.keys {
+            let waiting_key = (blocked.db, key.clone());
+            if let Some(queue) = self.waiting.get_mut(&waiting_key) {
+                queue.retain(|&c| c != client);
+                if queue.is_empty() {
+                    self.waiting.remove(&waiting_key);
+                }
+            }
+        }
+        if let Some(deadline) = blocked.deadline {
+            self.deadlines.remove(&(deadline, client));
+        }
+        Some(blocked)
+    }
+
+    /// How many clients are blocked.
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn get(&self, client: ClientId) -> Option<&Blocked> {
+        self.clients.get(&client)
+    }
+
+    /// Notes that `key` in database `db` may now let blocked clients carry
+    /// on. Does nothing if nobody is blocked on it.
+    pub fn signal(&mut self, db: usize, key: &[u8]) {
+        let key = (db, Bytes::from(key));
+        if self.waiting.contains_key(&key) && self.ready_set.insert(key.clone()) {
+            self.ready.push_back(key);
+        }
+    }
+
+    /// Signals every key anyone is blocked on in database `db`, for when
+    /// all of its contents change at once.
+    pub fn signal_db(&mut self, db: usize) {
+        let keys: Vec<Bytes> = self
+            .waiting
+            .keys()
+            .filter(|(d, _)| *d == db)
+            .map(|(_, key)| key.clone())
+            .collect();
+        for key in keys {
+            self.signal(db, &key);
+        }
+    }
+
+    /// The next signalled key and the clients blocked on it, in order.
+    pub fn next_ready(&mut self) -> Option<Vec<ClientId>> {
+        while let Some(key) = self.ready.pop_front() {
+            self.ready_set.remove(&key);
+            if let Some(queue) = self.waiting.get(&key) {
+                return Some(queue.iter().cloned().collect());
+            }
+        }
+        None
+    }
+
+    /// The earliest deadline of any blocked client.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadlines.iter().next().map(|&(deadline, _)| deadline)
+    }
+
+    /// Clients whose deadline is at or before `now`, earliest first.
+    pub fn timed_out(&self, now: Instant) -> Vec<ClientId> {
+        self.deadlines
+            .iter()
+            .take_while(|&&(deadline, _)| deadline <= now)
+            .map(|&(_, client)| client)
+            .collect()
+    }
+}
@@ -0,0 +1,525 @@
+//! The per-connection state machine.
+//!
+//! A `CacheSession` owns one client socket. Each time it is polled it reads
+//! everything the socket has ready, decodes every complete command in the
+//! buffer, sends them to the keyspace service as one batch and writes all of
+//! their replies back in one go, so pipelining clients don't pay a round
+//! trip per command.
+//!
+//! Frames the keyspace pushes to the client on its own account, such as
+//! pub/sub messages, are written out between batches, so they never land in
+//! the middle of a batch's replies.
+
+use bytes::BytesMut;
+use futures::sync::{mpsc, oneshot};
+use futures::{task, try_ready};
+use tokio::codec::Decoder;
+use tokio::net::TcpStream;
+use tokio::prelude::*;
+use tokio::sync::watch;
+use tokio::timer::Delay;
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::client::{ClientClass, ClientId, Push};
+use crate::config::{Config, OutputBufferLimits};
+use crate::keyspace::{self, Request};
+use crate::ratelimit::Limiter;
+use crate::resp::{DecodeError, Frame, RespCodec};
+
+/// How much spare room to make in the read buffer before each read.
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Once this much has been read in one poll we stop and run what we have, so
+/// one fast sender can't keep a poll busy forever.
+const READ_BATCH_LIMIT: usize = 1024 * 1024;
+
+/// Both buffers grow as far as a single request or batch of replies needs.
+/// Once one has been emptied, it is dropped if it grew past this, so a
+/// single huge value doesn't pin that much memory for the rest of the
+/// connection.
+const IDLE_BUFFER_KEEP: usize = 64 * 1024;
+
+pub struct CacheSession {
+    id: ClientId,
+    addr: SocketAddr,
+    socket: TcpStream,
+    codec: RespCodec,
+    read_buf: BytesMut,
+    write_buf: BytesMut,
+    keyspace: keyspace::Handle,
+    /// The batch of commands currently with the keyspace, if any.
+    batch: Option<Batch>,
+    /// What the keyspace sends outside of replies.
+    pushed: mpsc::UnboundedReceiver<Push>,
+    limiter: Option<Arc<Mutex<Limiter>>>,
+    /// Follows the keyspace's record of the client, through `Push::Class`.
+    class: ClientClass,
+    output_limits: OutputBufferLimits,
+    /// Fires when `write_buf` has been over the soft output limit for as
+    /// long as the limit allows, while it stays over.
+    over_soft_limit: Option<Delay>,
+    /// Set while the client is over its bandwidth allowance; no reads happen
+    /// until it fires.
+    throttle: Option<Delay>,
+    /// Fires when the client has gone `timeout` seconds without sending a
+    /// command. `None` when idle clients are kept forever.
+    idle: Option<(Duration, Delay)>,
+    /// Flips to true when the server starts shutting down.
+    shutdown: watch::Receiver<bool>,
+    /// Set once shutdown was seen, or the client sent QUIT or something we
+    /// can't parse past: nothing more is read, and the session ends as soon
+    /// as what was already read has been answered.
+    draining: bool,
+    eof: bool,
+}
+
+/// Replies for one batch, in the order its requests arrived. `None` slots are
+/// filled from the keyspace's replies; `Some` slots were answered locally,
+/// for malformed or rate-limited requests.
+struct Batch {
+    /// The request, until the keyspace's queue has room for it.
+    unsent: Option<Request>,
+    replies: oneshot::Receiver<Vec<Frame>>,
+    slots: Vec<Option<Frame>>,
+}
+
+impl CacheSession {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        config: &Config,
+        id: ClientId,
+        addr: SocketAddr,
+        socket: TcpStream,
+        keyspace: keyspace::Handle,
+        limiter: Option<Arc<Mutex<Limiter>>>,
+        pushed: mpsc::UnboundedReceiver<Push>,
+        shutdown: watch::Receiver<bool>,
+    ) -> CacheSession {
+        CacheSession {
+            id,
+            addr,
+            socket,
+            codec: RespCodec,
+            read_buf: BytesMut::new(),
+            write_buf: BytesMut::new(),
+            keyspace,
+            batch: None,
+            pushed,
+            limiter,
+            class: ClientClass::Normal,
+            output_limits: config.output_buffer_limits,
+            over_soft_limit: None,
+            throttle: None,
+            idle: match config.timeout {
+                0 => None,
+                secs => {
+                    let timeout = Duration::from_secs(secs);
+                    Some((timeout, Delay::new(Instant::now() + timeout)))
+                }
+            },
+            shutdown,
+            draining: false,
+            eof: false,
+        }
+    }
+
+    /// Reads whatever the socket has ready into `read_buf`.
+    fn fill_read_buf(&mut self) -> io::Result<()> {
+        if let Some(ref mut throttle) = self.throttle {
+            match throttle.poll().map_err(io::Error::other)? {
+                Async::Ready(()) => {}
+                Async::NotReady => return Ok(()),
+            }
+        }
+        self.throttle = None;
+
+        let mut batch = 0;
+        while !self.eof && !self.draining {
+            // While a batch is with the keyspace we read only to notice the
+            // client hanging up, which matters when its command has blocked;
+            // past a batch's worth, leave the rest in the socket.
+            if self.batch.is_some() && self.read_buf.len() >= READ_BATCH_LIMIT {
+                break;
+            }
+            if batch >= READ_BATCH_LIMIT {
+                // Come back for the rest once this batch has been handled.
+                task::current().notify();
+                break;
+            }
+
+            self.read_buf.reserve(READ_CHUNK);
+            let n = match AsyncRead::read_buf(&mut self.socket, &mut self.read_buf)? {
+                Async::Ready(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Async::Ready(n) => n,
+                Async::NotReady => break,
+            };
+            batch += n;
+
+            // Charge what we just read against the bandwidth allowance, and
+            // if the client is over it, hold off reading any more.
+            if let Some(ref limiter) = self.limiter {
+                let wait = limiter.lock().unwrap().consume_bytes(n);
+                if wait > Default::default() {
+                    let mut throttle = Delay::new(Instant::now() + wait);
+                    // Poll once so the timer wakes us when it fires.
+                    if let Async::NotReady = throttle.poll().map_err(io::Error::other)? {
+                        self.throttle = Some(throttle);
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes every complete command in `read_buf` and hands them to the
+    /// keyspace as one batch. Returns false if there was nothing to send.
+    ///
+    /// Nothing after a QUIT is read; the session closes once its reply is
+    /// written.
+    ///
+    /// A malformed request is answered with a protocol error in its place.
+    /// If the decoder could skip past it, the commands after it still run;
+    /// otherwise the session stops reading and closes once the replies so
+    /// far are written.
+    fn start_batch(&mut self) -> io::Result<bool> {
+        let mut commands = Vec::new();
+        let mut slots = Vec::new();
+        loop {
+            match self.codec.decode(&mut self.read_buf) {
+                Ok(Some(args)) => {
+                    // Only the name: arguments can be values or passwords
+                    // that don't belong in a log.
+                    println!(
+                        "{} {}: {:?} with {} arguments",
+                        self.id,
+                        self.addr,
+                        String::from_utf8_lossy(&args[0]),
+                        args.len() - 1
+                    );
+                    if self.allow_command() {
+                        let quit = args[0].eq_ignore_ascii_case(b"quit");
+                        commands.push(args);
+                        slots.push(None);
+                        // Its reply is the last thing the client gets.
+                        if quit {
+                            self.draining = true;
+                            self.read_buf.clear();
+                            break;
+                        }
+                    } else {
+                        println!("{} rate limit exceeded, refusing command", self.id);
+                        slots.push(Some(Frame::Error("ERR rate limit exceeded".to_string())));
+                    }
+                }
+                Ok(None) => break,
+                Err(DecodeError::Io(e)) => return Err(e),
+                Err(e) => {
+                    println!("{} {}", self.id, e);
+                    slots.push(Some(e.reply()));
+                    if let DecodeError::Fatal(_) = e {
+                        self.draining = true;
+                        self.read_buf.clear();
+                        break;
+                    }
+                }
+            }
+        }
+        if self.read_buf.is_empty() {
+            release_if_oversized(&mut self.read_buf);
+        }
+
+        if slots.is_empty() {
+            return Ok(false);
+        }
+        if let Some((timeout, ref mut idle)) = self.idle {
+            idle.reset(Instant::now() + timeout);
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let request = Request {
+            client: self.id,
+            commands,
+            reply: tx,
+        };
+        self.batch = Some(Batch {
+            unsent: Some(request),
+            replies: rx,
+            slots,
+        });
+        Ok(true)
+    }
+
+    /// Drives the outstanding batch: sends it once the keyspace has room,
+    /// then queues its replies in `write_buf` once they arrive. Returns true
+    /// when no batch is outstanding any more.
+    fn poll_batch(&mut self) -> io::Result<bool> {
+        let done = match self.batch {
+            None => return Ok(true),
+            Some(ref mut batch) => {
+                if let Some(request) = batch.unsent.take() {
+                    if let AsyncSink::NotReady(request) =
+                        self.keyspace.start_send(request).map_err(service_gone)?
+                    {
+                        batch.unsent = Some(request);
+                        return Ok(false);
+                    }
+                }
+                self.keyspace.poll_complete().map_err(service_gone)?;
+
+                match batch.replies.poll().map_err(service_gone)? {
+                    Async::Ready(replies) => replies,
+                    Async::NotReady => return Ok(false),
+                }
+            }
+        };
+
+        let batch = self.batch.take().unwrap();
+        let mut replies = done.into_iter();
+        for slot in batch.slots {
+            let reply = match slot {
+                Some(reply) => reply,
+                // Short only if the client hung up while blocked.
+                None => match replies.next() {
+                    Some(reply) => reply,
+                    None => break,
+                },
+            };
+            reply.encode(&mut self.write_buf);
+        }
+        if let Some((timeout, ref mut idle)) = self.idle {
+            idle.reset(Instant::now() + timeout);
+        }
+        Ok(true)
+    }
+
+    /// Queues whatever frames the keyspace has pushed in `write_buf`, where
+    /// they count against the output buffer limit like replies do, and
+    /// picks up any change to the client's class.
+    fn poll_pushed(&mut self) -> io::Result<()> {
+        while let Async::Ready(Some(push)) = self.pushed.poll().map_err(service_gone)? {
+            match push {
+                Push::Frame(frame) => frame.encode(&mut self.write_buf),
+                Push::Class(class) => self.class = class,
+            }
+        }
+        Ok(())
+    }
+
+    fn allow_command(&self) -> bool {
+        match self.limiter {
+            Some(ref limiter) => limiter.lock().unwrap().allow_command(),
+            None => true,
+        }
+    }
+
+    /// Picks up a pending shutdown notice, if any.
+    fn poll_shutdown(&mut self) {
+        if self.draining {
+            return;
+        }
+        while let Ok(Async::Ready(Some(value))) = self.shutdown.poll_ref() {
+            if *value {
+                self.draining = true;
+                break;
+            }
+        }
+    }
+
+    /// Whether the client has been idle for longer than the timeout.
+    fn timed_out(&mut self) -> io::Result<bool> {
+        match self.idle {
+            Some((_, ref mut idle)) => Ok(idle.poll().map_err(io::Error::other)?.is_ready()),
+            None => Ok(false),
+        }
+    }
+
+    /// Whether the replies still waiting in `write_buf` have outgrown the
+    /// client's output buffer limit. Going over the soft limit arms a timer,
+    /// so a client that stays over is closed even if it sends nothing more.
+    fn over_output_limit(&mut self) -> io::Result<bool> {
+        let limit = self.output_limits.get(self.class);
+        let queued = self.write_buf.len();
+        if limit.hard > 0 && queued > limit.hard {
+            return Ok(true);
+        }
+        if limit.soft > 0 && queued > limit.soft {
+            let timer = self.over_soft_limit.get_or_insert_with(|| {
+                Delay::new(Instant::now() + Duration::from_secs(limit.soft_seconds))
+            });
+            return Ok(timer.poll().map_err(io::Error::other)?.is_ready());
+        }
+        self.over_soft_limit = None;
+        Ok(false)
+    }
+
+    /// Writes out as much of `write_buf` as the socket will take.
+    fn flush(&mut self) -> Poll<(), io::Error> {
+        while !self.write_buf.is_empty() {
+            let n = try_ready!(self.socket.poll_write(&self.write_buf));
+            if n == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write reply to socket",
+                ));
+            }
+            self.write_buf.advance(n);
+        }
+        release_if_oversized(&mut self.write_buf);
+        self.socket.poll_flush()
+    }
+}
+
+fn service_gone<E>(_: E) -> io::Error {
+    io::Error::other("keyspace service stopped")
+}
+
+fn release_if_oversized(buf: &mut BytesMut) {
+    if buf.is_empty() && buf.capacity() > IDLE_BUFFER_KEEP {
+        *buf = BytesMut::new();
+    }
+}
+
+impl Future for CacheSession {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<(), io::Error> {
+        self.poll_shutdown();
+
+        // Only one batch is with the keyspace at a time, which keeps replies
+        // in the order the commands arrived.
+        while self.poll_batch()? {
+            self.poll_pushed()?;
+            self.fill_read_buf()?;
+            if !self.start_batch()? {
+                break;
+            }
+        }
+
+        if self.batch.is_some() && !self.eof {
+            self.fill_read_buf()?;
+            if self.eof {
+                self.keyspace.hung_up(self.id);
+            }
+        }
+
+        // A client waiting on the keyspace isn't idle, and nor is a
+        // subscriber, which only listens.
+        if self.batch.is_none() && self.class != ClientClass::Pubsub && self.timed_out()? {
+            println!("{} idle timeout, closing", self.id);
+            return Ok(Async::Ready(()));
+        }
+
+        let flushed = self.flush()?.is_ready();
+
+        // A client that reads its replies slower than it asks for them would
+        // otherwise have us buffer without bound.
+        if self.over_output_limit()? {
+            println!(
+                "{} output buffer limit reached ({} bytes queued), closing",
+                self.id,
+                self.write_buf.len()
+            );
+            return Ok(Async::Ready(()));
+        }
+
+        // Once the client has hung up (or we're shutting down) and everything
+        // it sent has been answered, the session is over.
+        if flushed && (self.eof || self.draining) && self.batch.is_none() {
+            return Ok(Async::Ready(()));
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::Events;
+    use std::io::{Read, Write};
+    use std::net;
+    use std::thread;
+    use tokio::reactor::Handle;
+    use tokio::runtime::Runtime;
+
+    /// Runs a keyspace and one session per connection on `runtime`, and
+    /// returns the client ends of `clients` connections, with the shutdown
+    /// sender the sessions watch.
+    fn serve(
+        runtime: &mut Runtime,
+        config: Config,
+        clients: usize,
+    ) -> (Vec<net::TcpStream>, watch::Sender<bool>) {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (keyspace, service) = keyspace::service(16, Events::default(), Arc::default());
+        runtime.spawn(service);
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        let mut streams = Vec::new();
+        for _ in 0..clients {
+            let client = net::TcpStream::connect(addr).unwrap();
+            client
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let (socket, peer) = listener.accept().unwrap();
+            let id = ClientId::next();
+            let (push, pushed) = mpsc::unbounded();
+            keyspace.connected(id, peer, addr, push);
+            let (config, keyspace, shutdown_rx) =
+                (config.clone(), keyspace.clone(), shutdown_rx.clone());
+            runtime.spawn(future::lazy(move || {
+                let socket = TcpStream::from_std(socket, &Handle::default()).unwrap();
+                CacheSession::new(
+                    &config,
+                    id,
+                    peer,
+                    socket,
+                    keyspace,
+                    None,
+                    pushed,
+                    shutdown_rx,
+                )
+                .map_err(|_| ())
+            }));
+            streams.push(client);
+        }
+        (streams, shutdown)
+    }
+
+    /// Sends `command` and reads one chunk of reply, empty once the server
+    /// has closed the connection.
+    fn send(client: &mut net::TcpStream, command: &[u8]) -> Vec<u8> {
+        client.write_all(command).unwrap();
+        let mut buf = [0; 256];
+        let n = client.read(&mut buf).unwrap_or(0);
+        buf[..n].to_vec()
+    }
+
+    #[test]
+    fn subscribers_are_not_closed_as_idle() {
+        let mut runtime = Runtime::new().unwrap();
+        let config = Config {
+            timeout: 1,
+            ..Config::default()
+        };
+        let (mut clients, _shutdown) = serve(&mut runtime, config, 2);
+
+        let reply = send(&mut clients[0], b"SUBSCRIBE news\r\n");
+        assert_eq!(reply, b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+        assert_eq!(send(&mut clients[1], b"PING\r\n"), b"+PONG\r\n");
+        thread::sleep(Duration::from_millis(2500));
+
+        let reply = send(&mut clients[0], b"PING\r\n");
+        assert_eq!(reply, b"*2\r\n$4\r\npong\r\n$0\r\n\r\n");
+        let mut buf = [0; 16];
+        assert_eq!(clients[1].read(&mut buf).unwrap_or(0), 0);
+    }
+}
@@ -0,0 +1,207 @@
+//! Per-connection identity.
+
+use bytes::Bytes;
+use futures::sync::mpsc;
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::resp::Frame;
+
+/// A process-unique ID handed to every accepted connection. It prefixes each
+/// log line about the connection, so grepping for it reconstructs a session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientId(pub u64);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+impl ClientId {
+    pub fn next() -> ClientId {
+        ClientId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cid={}", self.0)
+    }
+}
+
+/// The kind of client a connection is acting as. Each class gets its own
+/// output buffer limits, since a subscriber legitimately receives far more
+/// than it sends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClientClass {
+    Normal,
+    Replica,
+    Pubsub,
+}
+
+impl FromStr for ClientClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "normal" => Ok(ClientClass::Normal),
+            // Redis still accepts the old name.
+            "replica" | "slave" => Ok(ClientClass::Replica),
+            "pubsub" => Ok(ClientClass::Pubsub),
+            _ => Err(format!("unknown client class '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for ClientClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ClientClass::Normal => "normal",
+            ClientClass::Replica => "replica",
+            ClientClass::Pubsub => "pubsub",
+        })
+    }
+}
+
+/// What the keyspace sends a client's session besides replies. The
+/// session picks these up between batches.
+#[derive(Debug, PartialEq)]
+pub enum Push {
+    /// A frame to write out, such as a pub/sub message.
+    Frame(Frame),
+    /// The client's class changed, and with it, its output buffer limits.
+    Class(ClientClass),
+}
+
+/// What the server knows about a connected client, as reported by CLIENT
+/// LIST and CLIENT INFO.
+pub struct ClientInfo {
+    pub id: ClientId,
+    pub addr: SocketAddr,
+    pub local_addr: SocketAddr,
+    /// Changed with `set_class`, so the session hears of it.
+    pub class: ClientClass,
+    /// Set with CLIENT SETNAME.
+    pub name: Option<String>,
+    /// The database chosen with SELECT.
+    pub db: usize,
+    pub connected_at: Instant,
+    pub last_interaction: Instant,
+    /// The most recent command, lowercase, with its subcommand if it has
+    /// one, e.g. `client|list`.
+    pub last_command: String,
+    /// What the client's session needs to hear about outside of replies.
+    pub push: mpsc::UnboundedSender<Push>,
+    /// Set by MULTI until EXEC or DISCARD.
+    pub transaction: Option<Transaction>,
+}
+
+/// The commands a client has queued since MULTI.
+#[derive(Default)]
+pub struct Transaction {
+    pub commands: Vec<Vec<Bytes>>,
+    /// Set when a command was refused while queueing, so that EXEC runs
+    /// none of them.
+    pub aborted: bool,
+}
+
+/// What CLIENT TRACKING turned on for a client.
+pub struct Tracking {
+    /// The client that hears of invalidated keys.
+    pub redirect: ClientId,
+    /// Only keys starting with one of these are tracked; every key is when
+    /// there are none.
+    pub prefixes: Vec<Bytes>,
+}
+
+impl ClientInfo {
+    pub fn new(
+        id: ClientId,
+        addr: SocketAddr,
+        local_addr: SocketAddr,
+        push: mpsc::UnboundedSender<Push>,
+    ) -> ClientInfo {
+        let now = Instant::now();
+        ClientInfo {
+            id,
+            addr,
+            local_addr,
+            class: ClientClass::Normal,
+            name: None,
+            db: 0,
+            connected_at: now,
+            last_interaction: now,
+            last_command: "NULL".to_string(),
+            push,
+            transaction: None,
+        }
+    }
+
+    /// Changes the client's class, and tells its session so it applies the
+    /// new class's output buffer limits.
+    pub fn set_class(&mut self, class: ClientClass) {
+        if self.class != class {
+            self.class = class;
+            // The session may have ended already.
+            let _ = self.push.unbounded_send(Push::Class(class));
+        }
+    }
+
+    /// One line of CLIENT LIST, in Redis' `field=value` format.
+    pub fn describe(&self) -> String {
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db={} cmd={}",
+            self.id.0,
+            self.addr,
+            self.local_addr,
+            self.name.as_deref().unwrap_or(""),
+            self.connected_at.elapsed().as_secs(),
+            self.last_interaction.elapsed().as_secs(),
+            self.flags(),
+            self.db,
+            self.last_command
+        )
+    }
+
+    /// The same as `describe`, as a JSON object.
+    pub fn describe_json(&self) -> String {
+        format!(
+            "{{\"id\":{},\"addr\":{},\"laddr\":{},\"name\":{},\"age\":{},\"idle\":{},\"flags\":{},\"db\":{},\"cmd\":{}}}",
+            self.id.0,
+            json_string(&self.addr.to_string()),
+            json_string(&self.local_addr.to_string()),
+            json_string(self.name.as_deref().unwrap_or("")),
+            self.connected_at.elapsed().as_secs(),
+            self.last_interaction.elapsed().as_secs(),
+            json_string(self.flags()),
+            self.db,
+            json_string(&self.last_command)
+        )
+    }
+
+    /// CLIENT LIST's flags: the client's class, as a letter.
+    fn flags(&self) -> &'static str {
+        match self.class {
+            ClientClass::Normal => "N",
+            ClientClass::Replica => "S",
+            ClientClass::Pubsub => "P",
+        }
+    }
+}
+
+/// `s` as a JSON string, quoted and escaped.
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,744 @@
+//! Commands that treat string values as arrays of bits.
+//!
+//! Bits are numbered from the most significant bit of the first byte, as
+//! in Redis. Writing past the end of a string grows it with zero bytes, up
+//! to the 512MB limit on string values.
+
+use bytes::{Bytes, BytesMut};
+
+use std::mem;
+
+use super::{error, index_range, lossy, parse_int, syntax_error, wrong_type, Command};
+use crate::client::ClientId;
+use crate::db::Value;
+use crate::keyspace::Keyspace;
+use crate::notify::Events;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "setbit",
+        arity: 4,
+        subcommands: false,
+        handler: setbit,
+    },
+    Command {
+        name: "getbit",
+        arity: 3,
+        subcommands: false,
+        handler: getbit,
+    },
+    Command {
+        name: "bitcount",
+        arity: -2,
+        subcommands: false,
+        handler: bitcount,
+    },
+    Command {
+        name: "bitpos",
+        arity: -3,
+        subcommands: false,
+        handler: bitpos,
+    },
+    Command {
+        name: "bitop",
+        arity: -4,
+        subcommands: false,
+        handler: bitop,
+    },
+    Command {
+        name: "bitfield",
+        arity: -2,
+        subcommands: false,
+        handler: bitfield,
+    },
+    Command {
+        name: "bitfield_ro",
+        arity: -2,
+        subcommands: false,
+        handler: bitfield,
+    },
+];
+
+/// The largest string a bit offset may reach into.
+const MAX_STRING_LEN: u64 = 512 * 1024 * 1024;
+
+/// The string at `key`, or `None` if there is no such key. A key holding
+/// another type is an error reply.
+fn string(ks: &mut Keyspace, key: &[u8]) -> Result<Option<Bytes>, Frame> {
+    match ks.db().get(key) {
+        None => Ok(None),
+        Some(value) => value.as_string().map(Some).ok_or_else(wrong_type),
+    }
+}
+
+/// Runs `f` on the string at `key`, grown with zero bytes to at least `len`
+/// bytes, and stores what it leaves. A missing key gets a new string; an
+/// existing one keeps its expiry. The string is changed in place unless
+/// something else still shares its bytes.
+fn modify<R, F: FnOnce(&mut [u8]) -> R>(
+    ks: &mut Keyspace,
+    key: &Bytes,
+    len: usize,
+    f: F,
+) -> Result<R, Frame> {
+    if !ks.db().contains(key) {
+        ks.db().insert(key.clone(), Value::String(Bytes::new()));
+    }
+    let value = ks.db().get_mut(key).unwrap();
+    let current = match *value {
+        Value::String(ref mut bytes) => mem::take(bytes),
+        Value::Int(n) => Bytes::from(n.to_string()),
+        _ => return Err(wrong_type()),
+    };
+    let mut bytes = current
+        .try_mut()
+        .unwrap_or_else(|shared| BytesMut::from(&shared[..]));
+    if bytes.len() < len {
+        bytes.resize(len, 0);
+    }
+    let result = f(&mut bytes);
+    *value = Value::String(bytes.freeze());
+    Ok(result)
+}
+
+/// Parses a bit offset, which must fall within the longest string allowed.
+fn parse_offset(arg: &[u8]) -> Result<u64, Frame> {
+    match parse_int(arg) {
+        Ok(n) if n >= 0 && (n as u64) < MAX_STRING_LEN * 8 => Ok(n as u64),
+        _ => Err(error("ERR bit offset is not an integer or out of range")),
+    }
+}
+
+fn bit_at(bytes: &[u8], offset: u64) -> bool {
+    let byte = bytes.get((offset / 8) as usize).cloned().unwrap_or(0);
+    byte & (0x80 >> (offset % 8)) != 0
+}
+
+/// SETBIT key offset value
+///
+/// Replies with the bit's previous value.
+fn setbit(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let offset = match parse_offset(&args[2]) {
+        Ok(offset) => offset,
+        Err(e) => return e,
+    };
+    let on = match &args[3][..] {
+        b"0" => false,
+        b"1" => true,
+        _ => return error("ERR bit is not an integer or out of range"),
+    };
+    let len = (offset / 8) as usize + 1;
+    let result = modify(ks, &args[1], len, |bytes| {
+        let old = bit_at(bytes, offset);
+        let mask = 0x80 >> (offset % 8);
+        let byte = &mut bytes[(offset / 8) as usize];
+        if on {
+            *byte |= mask;
+        } else {
+            *byte &= !mask;
+        }
+        old
+    });
+    match result {
+        Ok(old) => {
+            ks.notify(Events::STRING, "setbit", &args[1]);
+            Frame::Integer(old as i64)
+        }
+        Err(e) => e,
+    }
+}
+
+/// GETBIT key offset
+///
+/// Bits past the end of the string, or of a missing key, are 0.
+fn getbit(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let offset = match parse_offset(&args[2]) {
+        Ok(offset) => offset,
+        Err(e) => return e,
+    };
+    match string(ks, &args[1]) {
+        Ok(bytes) => Frame::Integer(bytes.is_some_and(|bytes| bit_at(&bytes, offset)) as i64),
+        Err(e) => e,
+    }
+}
+
+/// A BITCOUNT or BITPOS range: start and end indexes, counting bytes or
+/// with BIT, bits. Negative indexes count back from the end.
+struct BitRange {
+    start: i64,
+    end: i64,
+    bits: bool,
+}
+
+impl BitRange {
+    /// Parses `start end [BYTE | BIT]`, where `end` may be left out to mean
+    /// the end of the string.
+    fn parse(args: &[Bytes]) -> Result<BitRange, Frame> {
+        let start = match args.first() {
+            Some(start) => parse_int(start)?,
+            None => 0,
+        };
+        let end = match args.get(1) {
+            Some(end) => parse_int(end)?,
+            None => -1,
+        };
+        let bits = match args.get(2) {
+            None => false,
+            Some(unit) if unit.eq_ignore_ascii_case(b"BYTE") => false,
+            Some(unit) if unit.eq_ignore_ascii_case(b"BIT") => true,
+            Some(_) => return Err(syntax_error()),
+        };
+        Ok(BitRange { start, end, bits })
+    }
+
+    /// The inclusive range of bits this covers in a string of `len` bytes,
+    /// or `None` if that's empty.
+    fn resolve(&self, len: usize) -> Option<(u64, u64)> {
+        if self.bits {
+            index_range(self.start, self.end, len * 8)
+                .map(|(start, end)| (start as u64, end as u64))
+        } else {
+            index_range(self.start, self.end, len)
+                .map(|(start, end)| (start as u64 * 8, end as u64 * 8 + 7))
+        }
+    }
+}
+
+/// How many bits are set from bit `first` through bit `last`.
+fn count_bits(bytes: &[u8], first: u64, last: u64) -> u64 {
+    let (first_byte, last_byte) = ((first / 8) as usize, (last / 8) as usize);
+    let mut count: u64 = bytes[first_byte..=last_byte]
+        .iter()
+        .map(|byte| byte.count_ones() as u64)
+        .sum();
+    // Take back the bits before `first` and after `last`.
+    let before = !(0xffu32 >> (first % 8)) as u8;
+    let after = (0xffu32 >> (last % 8 + 1)) as u8;
+    count -= (bytes[first_byte] & before).count_ones() as u64;
+    count -= (bytes[last_byte] & after).count_ones() as u64;
+    count
+}
+
+/// BITCOUNT key [start end [BYTE | BIT]]
+fn bitcount(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    if args.len() == 3 || args.len() > 5 {
+        return syntax_error();
+    }
+    let range = match BitRange::parse(&args[2..]) {
+        Ok(range) => range,
+        Err(e) => return e,
+    };
+    let bytes = match string(ks, &args[1]) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Frame::Integer(0),
+        Err(e) => return e,
+    };
+    match range.resolve(bytes.len()) {
+        Some((first, last)) => Frame::Integer(count_bits(&bytes, first, last) as i64),
+        None => Frame::Integer(0),
+    }
+}
+
+/// BITPOS key bit [start [end [BYTE | BIT]]]
+///
+/// Replies with the position of the first bit set to `bit` in the range,
+/// or -1 if there is none. Looking for a 0 without giving an end treats
+/// the string as padded with zeros, so finds the bit just past its end
+/// rather than -1.
+fn bitpos(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let target = match &args[2][..] {
+        b"0" => false,
+        b"1" => true,
+        _ => return error("ERR The bit argument must be 1 or 0."),
+    };
+    if args.len() > 6 {
+        return syntax_error();
+    }
+    let range = match BitRange::parse(&args[3..]) {
+        Ok(range) => range,
+        Err(e) => return e,
+    };
+    let bytes = match string(ks, &args[1]) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Frame::Integer(if target { -1 } else { 0 }),
+        Err(e) => return e,
+    };
+    let (first, last) = match range.resolve(bytes.len()) {
+        Some(range) => range,
+        None => return Frame::Integer(-1),
+    };
+
+    // Whole bytes with none of the bits we want can be skipped at once.
+    let skip = if target { 0x00 } else { 0xff };
+    let mut pos = first;
+    while pos <= last {
+        if pos % 8 == 0 && pos + 7 <= last && bytes[(pos / 8) as usize] == skip {
+            pos += 8;
+            continue;
+        }
+        if bit_at(&bytes, pos) == target {
+            return Frame::Integer(pos as i64);
+        }
+        pos += 1;
+    }
+    let end_given = args.len() > 4;
+    if !target && !end_given {
+        return Frame::Integer(bytes.len() as i64 * 8);
+    }
+    Frame::Integer(-1)
+}
+
+/// BITOP AND | OR | XOR | NOT destkey key [key ...]
+///
+/// Stores the result of combining the strings at the keys byte by byte,
+/// replying with its length. Shorter strings and missing keys count as
+/// padded with zero bytes. NOT takes a single key. An empty result deletes
+/// the destination.
+fn bitop(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let op = lossy(&args[1]).to_ascii_lowercase();
+    let sources = &args[3..];
+    match op.as_str() {
+        "and" | "or" | "xor" => {}
+        "not" if sources.len() == 1 => {}
+        "not" => return error("ERR BITOP NOT must be called with a single source key."),
+        _ => return syntax_error(),
+    }
+    let mut strings = Vec::with_capacity(sources.len());
+    for key in sources {
+        match string(ks, key) {
+            Ok(bytes) => strings.push(bytes.unwrap_or_default()),
+            Err(e) => return e,
+        }
+    }
+
+    let len = strings.iter().map(Bytes::len).max().unwrap_or(0);
+    let result: Vec<u8> = (0..len)
+        .map(|i| {
+            let mut bytes = strings.iter().map(|s| s.get(i).cloned().unwrap_or(0));
+            let first = bytes.next().unwrap();
+            match op.as_str() {
+                "and" => bytes.fold(first, |acc, b| acc & b),
+                "or" => bytes.fold(first, |acc, b| acc | b),
+                "xor" => bytes.fold(first, |acc, b| acc ^ b),
+                _ => !first,
+            }
+        })
+        .collect();
+
+    if result.is_empty() {
+        if ks.db().remove(&args[2]).is_some() {
+            ks.notify(Events::GENERIC, "del", &args[2]);
+        }
+    } else {
+        ks.db()
+            .insert(args[2].clone(), Value::String(Bytes::from(result)));
+        ks.notify(Events::STRING, "set", &args[2]);
+    }
+    Frame::Integer(len as i64)
+}
+
+/// An integer field of a BITFIELD: signed or not, 1 to 64 bits wide. Only
+/// signed fields may be 64 bits, so every value fits in an i64.
+#[derive(Clone, Copy)]
+struct FieldType {
+    signed: bool,
+    bits: u32,
+}
+
+impl FieldType {
+    /// Parses a type like "i8" or "u16".
+    fn parse(arg: &[u8]) -> Result<FieldType, Frame> {
+        let invalid = || {
+            error("ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.")
+        };
+        let signed = match arg.first().map(u8::to_ascii_lowercase) {
+            Some(b'i') => true,
+            Some(b'u') => false,
+            _ => return Err(invalid()),
+        };
+        let bits = std::str::from_utf8(&arg[1..])
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .ok_or_else(invalid)?;
+        let max = if signed { 64 } else { 63 };
+        if bits < 1 || bits > max {
+            return Err(invalid());
+        }
+        Ok(FieldType { signed, bits })
+    }
+
+    fn min(&self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(&self) -> i128 {
+        if self.signed {
+            (1 << (self.bits - 1)) - 1
+        } else {
+            (1 << self.bits) - 1
+        }
+    }
+
+    /// Parses a field offset: a bit offset, or with a '#' prefix, a count
+    /// of fields of this type.
+    fn parse_offset(&self, arg: &[u8]) -> Result<u64, Frame> {
+        let out_of_range = || error("ERR bit offset is not an integer or out of range");
+        let offset = match arg.first() {
+            Some(b'#') => parse_int(&arg[1..]).map(|n| n.checked_mul(self.bits as i64)),
+            _ => parse_int(arg).map(Some),
+        };
+        match offset {
+            Ok(Some(n)) if n >= 0 && n as u64 + self.bits as u64 <= MAX_STRING_LEN * 8 => {
+                Ok(n as u64)
+            }
+            _ => Err(out_of_range()),
+        }
+    }
+
+    fn get(&self, bytes: &[u8], offset: u64) -> i128 {
+        let mut value: u64 = 0;
+        for i in 0..self.bits as u64 {
+            value = (value << 1) | bit_at(bytes, offset + i) as u64;
+        }
+        let value = value as i128;
+        if self.signed && value > self.max() {
+            value - (1 << self.bits)
+        } else {
+            value
+        }
+    }
+
+    fn set(&self, bytes: &mut [u8], offset: u64, value: i128) {
+        for i in 0..self.bits as u64 {
+            let pos = offset + i;
+            let mask = 0x80 >> (pos % 8);
+            let byte = &mut bytes[(pos / 8) as usize];
+            if (value >> (self.bits as u64 - 1 - i)) & 1 == 1 {
+                *byte |= mask;
+            } else {
+                *byte &= !mask;
+            }
+        }
+    }
+
+    /// Fits `value` into the field as `overflow` says to, or `None` if it
+    /// says not to write it at all.
+    fn fit(&self, value: i128, overflow: Overflow) -> Option<i128> {
+        if value >= self.min() && value <= self.max() {
+            return Some(value);
+        }
+        match overflow {
+            Overflow::Fail => None,
+            Overflow::Sat if value > self.max() => Some(self.max()),
+            Overflow::Sat => Some(self.min()),
+            Overflow::Wrap => {
+                let wrapped = value & ((1 << self.bits) - 1);
+                if wrapped > self.max() {
+                    Some(wrapped - (1 << self.bits))
+                } else {
+                    Some(wrapped)
+                }
+            }
+        }
+    }
+}
+
+/// What BITFIELD does with a SET or INCRBY that doesn't fit its field.
+#[derive(Clone, Copy)]
+enum Overflow {
+    /// Keep the low bits, so values wrap around.
+    Wrap,
+    /// Clamp to the field's minimum or maximum.
+    Sat,
+    /// Leave the field alone and reply nil.
+    Fail,
+}
+
+enum FieldOp {
+    Get,
+    Set(i64),
+    IncrBy(i64),
+}
+
+/// BITFIELD key [GET type offset] [SET type offset value]
+///   [INCRBY type offset increment] [OVERFLOW WRAP | SAT | FAIL] ...
+/// and BITFIELD_RO key [GET type offset ...]
+///
+/// Runs the operations in order, replying with one integer for each GET,
+/// SET or INCRBY: the field's value, its old value, or its new value. An
+/// OVERFLOW applies to the SETs and INCRBYs after it; WRAP is the default.
+fn bitfield(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let read_only = args[0].eq_ignore_ascii_case(b"bitfield_ro");
+    let mut ops = Vec::new();
+    let mut overflow = Overflow::Wrap;
+    let mut write_end = None;
+    let mut i = 2;
+    while i < args.len() {
+        let op = lossy(&args[i]).to_ascii_lowercase();
+        let operands = match op.as_str() {
+            "get" => 2,
+            "set" | "incrby" => 3,
+            "overflow" => 1,
+            _ => return syntax_error(),
+        };
+        if i + operands >= args.len() {
+            return syntax_error();
+        }
+        if read_only && op != "get" {
+            return error("ERR BITFIELD_RO only supports the GET subcommand");
+        }
+        if op == "overflow" {
+            overflow = match lossy(&args[i + 1]).to_ascii_lowercase().as_str() {
+                "wrap" => Overflow::Wrap,
+                "sat" => Overflow::Sat,
+                "fail" => Overflow::Fail,
+                _ => return error("ERR Invalid OVERFLOW type specified"),
+            };
+            i += 2;
+            continue;
+        }
+
+        let field = match FieldType::parse(&args[i + 1]) {
+            Ok(field) => field,
+            Err(e) => return e,
+        };
+        let offset = match field.parse_offset(&args[i + 2]) {
+            Ok(offset) => offset,
+            Err(e) => return e,
+        };
+        let op = match op.as_str() {
+            "get" => FieldOp::Get,
+            _ => {
+                let n = match parse_int(&args[i + 3]) {
+                    Ok(n) => n,
+                    Err(e) => return e,
+                };
+                let end = offset + field.bits as u64;
+                write_end = write_end.max(Some(end));
+                if op == "set" {
+                    FieldOp::Set(n)
+                } else {
+                    FieldOp::IncrBy(n)
+                }
+            }
+        };
+        ops.push((field, offset, op, overflow));
+        i += 1 + operands;
+    }
+
+    let run = |bytes: &mut [u8]| -> Vec<Frame> {
+        let mut replies = Vec::with_capacity(ops.len());
+        for &(field, offset, ref op, overflow) in &ops {
+            let current = field.get(bytes, offset);
+            let (new, reply) = match *op {
+                FieldOp::Get => (None, Some(current)),
+                FieldOp::Set(value) => match field.fit(value as i128, overflow) {
+                    Some(new) => (Some(new), Some(current)),
+                    None => (None, None),
+                },
+                FieldOp::IncrBy(by) => match field.fit(current + by as i128, overflow) {
+                    Some(new) => (Some(new), Some(new)),
+                    None => (None, None),
+                },
+            };
+            if let Some(new) = new {
+                field.set(bytes, offset, new);
+            }
+            replies.push(reply.map_or(Frame::Null, |n| Frame::Integer(n as i64)));
+        }
+        replies
+    };
+
+    let replies = match write_end {
+        Some(end) => modify(ks, &args[1], end.div_ceil(8) as usize, run),
+        // Only GETs, so nothing to create or grow either.
+        None => string(ks, &args[1]).map(|bytes| {
+            let bytes = bytes.unwrap_or_default();
+            ops.iter()
+                .map(|&(field, offset, _, _)| Frame::Integer(field.get(&bytes, offset) as i64))
+                .collect()
+        }),
+    };
+    match replies {
+        Ok(replies) => {
+            if write_end.is_some() {
+                ks.notify(Events::STRING, "setbit", &args[1]);
+            }
+            Frame::Array(replies)
+        }
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A BITFIELD reply: integers, with `None` for nil.
+    fn replies(values: &[Option<i64>]) -> Frame {
+        Frame::Array(
+            values
+                .iter()
+                .map(|value| value.map_or(Frame::Null, Frame::Integer))
+                .collect(),
+        )
+    }
+
+    fn bitfield(ks: &mut Keyspace, client: ClientId, ops: &str) -> Frame {
+        let mut args = vec!["bitfield", "k"];
+        args.extend(ops.split_whitespace());
+        ks.command(client, &args)
+    }
+
+    #[test]
+    fn widest_fields() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        assert_eq!(
+            bitfield(
+                &mut ks,
+                client,
+                "set i64 0 9223372036854775807 incrby i64 0 1"
+            ),
+            replies(&[Some(0), Some(i64::MIN)])
+        );
+        assert_eq!(
+            bitfield(
+                &mut ks,
+                client,
+                "overflow sat incrby i64 0 -1 overflow fail incrby i64 0 -1"
+            ),
+            replies(&[Some(i64::MIN), None])
+        );
+        assert_eq!(
+            bitfield(
+                &mut ks,
+                client,
+                "set u63 64 9223372036854775807 get u63 64 incrby u63 64 1"
+            ),
+            replies(&[Some(0), Some(i64::MAX), Some(0)])
+        );
+        assert_eq!(
+            bitfield(
+                &mut ks,
+                client,
+                "overflow sat incrby u63 64 -1 incrby u63 64 -1"
+            ),
+            replies(&[Some(0), Some(0)])
+        );
+        for invalid in &["u64", "i65", "i0", "x8", "u"] {
+            assert!(matches!(
+                bitfield(&mut ks, client, &format!("get {} 0", invalid)),
+                Frame::Error(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn overflow_on_set() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        assert_eq!(
+            bitfield(
+                &mut ks,
+                client,
+                "set u8 0 256 get u8 0 set i8 8 128 get i8 8"
+            ),
+            replies(&[Some(0), Some(0), Some(0), Some(-128)])
+        );
+        assert_eq!(
+            bitfield(
+                &mut ks,
+                client,
+                "overflow sat set u8 0 300 get u8 0 set u8 0 -1 get u8 0"
+            ),
+            replies(&[Some(0), Some(255), Some(255), Some(0)])
+        );
+        assert_eq!(
+            bitfield(
+                &mut ks,
+                client,
+                "set u8 0 7 overflow fail set u8 0 256 get u8 0"
+            ),
+            replies(&[Some(0), None, Some(7)])
+        );
+    }
+
+    #[test]
+    fn overflow_on_incrby() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        let ops = "incrby u2 100 1 overflow sat incrby u2 102 1 overflow fail incrby u2 104 1";
+        for expected in &[[1, 1, 1], [2, 2, 2], [3, 3, 3]] {
+            let expected: Vec<_> = expected.iter().map(|&n| Some(n)).collect();
+            assert_eq!(bitfield(&mut ks, client, ops), replies(&expected));
+        }
+        assert_eq!(
+            bitfield(&mut ks, client, ops),
+            replies(&[Some(0), Some(3), None])
+        );
+        assert_eq!(
+            bitfield(&mut ks, client, "incrby i8 0 -129"),
+            replies(&[Some(127)])
+        );
+    }
+
+    #[test]
+    fn field_offsets() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        assert_eq!(
+            bitfield(
+                &mut ks,
+                client,
+                "set u8 #1 255 get u8 8 get u4 #3 get u4 #4"
+            ),
+            replies(&[Some(0), Some(255), Some(15), Some(0)])
+        );
+        assert_eq!(
+            ks.command(client, &["get", "k"]),
+            Frame::Bulk(Bytes::from(&[0, 255][..]))
+        );
+        assert!(matches!(
+            bitfield(&mut ks, client, "get u8 #-1"),
+            Frame::Error(_)
+        ));
+    }
+
+    #[test]
+    fn offsets_stay_within_the_longest_string() {
+        let out_of_range = Err(error("ERR bit offset is not an integer or out of range"));
+        let last_bit = MAX_STRING_LEN * 8 - 1;
+        assert_eq!(parse_offset(last_bit.to_string().as_bytes()), Ok(last_bit));
+        assert_eq!(
+            parse_offset((last_bit + 1).to_string().as_bytes()),
+            out_of_range
+        );
+        assert_eq!(parse_offset(b"-1"), out_of_range);
+
+        let u8 = FieldType {
+            signed: false,
+            bits: 8,
+        };
+        let last_byte = MAX_STRING_LEN * 8 - 8;
+        assert_eq!(
+            u8.parse_offset(last_byte.to_string().as_bytes()),
+            Ok(last_byte)
+        );
+        assert_eq!(
+            u8.parse_offset((last_byte + 1).to_string().as_bytes()),
+            out_of_range
+        );
+        let last_field = format!("#{}", MAX_STRING_LEN - 1);
+        assert_eq!(u8.parse_offset(last_field.as_bytes()), Ok(last_byte));
+        let past_last = format!("#{}", MAX_STRING_LEN);
+        assert_eq!(u8.parse_offset(past_last.as_bytes()), out_of_range);
+        assert_eq!(u8.parse_offset(b"#9223372036854775807"), out_of_range);
+        assert_eq!(u8.parse_offset(b"-1"), out_of_range);
+    }
+}
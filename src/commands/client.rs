@@ -0,0 +1,286 @@
+//! The CLIENT command family, answered from the keyspace's client registry.
+
+use bytes::Bytes;
+
+use super::{error, lossy, ok, syntax_error, wrong_arity, Command};
+use crate::client::{ClientClass, ClientId, ClientInfo, Tracking};
+use crate::keyspace::Keyspace;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[Command {
+    name: "client",
+    arity: -2,
+    subcommands: true,
+    handler: client,
+}];
+
+/// CLIENT ID | INFO [JSON] | LIST | GETNAME | SETNAME | TRACKING | GETREDIR
+///
+/// With JSON, INFO replies with the client as a JSON object, and LIST with
+/// an array of them.
+fn client(ks: &mut Keyspace, client: ClientId, args: &[Bytes]) -> Frame {
+    let sub = lossy(&args[1]).to_ascii_lowercase();
+    let info = &ks.clients[&client];
+
+    match (sub.as_str(), args.len()) {
+        ("id", 2) => Frame::Integer(client.0 as i64),
+        ("info", 2) => Frame::Bulk(format!("{}\n", info.describe()).into()),
+        ("info", 3) if args[2].eq_ignore_ascii_case(b"JSON") => {
+            Frame::Bulk(info.describe_json().into())
+        }
+        ("getname", 2) => match info.name {
+            Some(ref name) => Frame::Bulk(name.clone().into()),
+            None => Frame::Null,
+        },
+        ("setname", 3) => {
+            let name = &args[2];
+            // Names show up in CLIENT LIST, space separated.
+            if name.iter().any(|&c| !(b'!'..=b'~').contains(&c)) {
+                return error(
+                    "ERR Client names cannot contain spaces, newlines or special characters.",
+                );
+            }
+            let info = ks.clients.get_mut(&client).unwrap();
+            info.name = match name.len() {
+                0 => None,
+                _ => Some(lossy(name)),
+            };
+            ok()
+        }
+        ("list", _) => list(ks, &args[2..]),
+        ("tracking", n) if n > 2 => tracking(ks, client, &args[2..]),
+        ("getredir", 2) => match ks.tracking.get(&client) {
+            Some(tracking) => Frame::Integer(tracking.redirect.0 as i64),
+            None => Frame::Integer(-1),
+        },
+        ("id", _)
+        | ("info", _)
+        | ("getname", _)
+        | ("setname", _)
+        | ("tracking", _)
+        | ("getredir", _) => wrong_arity(&format!("client|{}", sub)),
+        _ => error(format!(
+            "ERR unknown subcommand '{}'. Try CLIENT HELP.",
+            lossy(&args[1])
+        )),
+    }
+}
+
+/// CLIENT LIST [TYPE normal|replica|pubsub] [ID id [id ...]] [JSON]
+fn list(ks: &Keyspace, args: &[Bytes]) -> Frame {
+    let json = args
+        .last()
+        .is_some_and(|arg| arg.eq_ignore_ascii_case(b"JSON"));
+    let args = if json { &args[..args.len() - 1] } else { args };
+    let mut class = None;
+    let mut ids = None;
+    match args.first().map(|a| lossy(a).to_ascii_lowercase()) {
+        None => {}
+        Some(ref opt) if opt == "type" && args.len() == 2 => {
+            match lossy(&args[1]).parse::<ClientClass>() {
+                Ok(c) => class = Some(c),
+                Err(_) => {
+                    return error(format!("ERR Unknown client type '{}'", lossy(&args[1])));
+                }
+            }
+        }
+        Some(ref opt) if opt == "id" && args.len() > 1 => {
+            let mut wanted = Vec::new();
+            for arg in &args[1..] {
+                match lossy(arg).parse::<u64>() {
+                    Ok(id) if id > 0 => wanted.push(ClientId(id)),
+                    _ => return error("ERR Invalid client ID"),
+                }
+            }
+            ids = Some(wanted);
+        }
+        Some(_) => return syntax_error(),
+    }
+
+    let mut clients: Vec<&ClientInfo> = ks
+        .clients
+        .values()
+        .filter(|info| class.is_none_or(|class| info.class == class))
+        .filter(|info| ids.as_ref().is_none_or(|ids| ids.contains(&info.id)))
+        .collect();
+    clients.sort_by_key(|info| info.id);
+
+    if json {
+        let clients: Vec<String> = clients.iter().map(|info| info.describe_json()).collect();
+        return Frame::Bulk(format!("[{}]", clients.join(",")).into());
+    }
+    let mut list = String::new();
+    for info in clients {
+        list.push_str(&info.describe());
+        list.push('\n');
+    }
+    Frame::Bulk(list.into())
+}
+
+/// CLIENT TRACKING ON|OFF [REDIRECT id] [BCAST] [PREFIX prefix ...]
+///
+/// Only broadcasting mode is supported: the client hears of every change
+/// to a key with one of its prefixes. Redis's default mode remembers the
+/// keys each command read, which needs key specs the command table doesn't
+/// have. And only with REDIRECT, since invalidations can only be delivered
+/// as pub/sub messages to another client: without RESP3 there are no push
+/// messages on the tracking client's own connection. OPTIN, OPTOUT and
+/// NOLOOP aren't supported either; the first two only make sense without
+/// BCAST, and NOLOOP would need to know which client made each change.
+fn tracking(ks: &mut Keyspace, client: ClientId, args: &[Bytes]) -> Frame {
+    let on = match lossy(&args[0]).to_ascii_lowercase().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return syntax_error(),
+    };
+    let mut redirect = None;
+    let mut bcast = false;
+    let mut prefixes = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        match lossy(&args[i]).to_ascii_lowercase().as_str() {
+            "redirect" if i + 1 < args.len() => {
+                let id = match lossy(&args[i + 1]).parse::<u64>() {
+                    Ok(id) => ClientId(id),
+                    Err(_) => return error("ERR Invalid client ID"),
+                };
+                if on && !ks.clients.contains_key(&id) {
+                    return error("ERR The client ID you want redirect to does not exist");
+                }
+                redirect = Some(id);
+                i += 1;
+            }
+            "bcast" => bcast = true,
+            "prefix" if i + 1 < args.len() => {
+                prefixes.push(args[i + 1].clone());
+                i += 1;
+            }
+            "optin" | "optout" | "noloop" => {
+                return error(format!(
+                    "ERR {} is not supported",
+                    lossy(&args[i]).to_ascii_uppercase()
+                ));
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+
+    if !on {
+        ks.tracking.remove(&client);
+        return ok();
+    }
+    if !bcast {
+        if !prefixes.is_empty() {
+            return error("ERR PREFIX option requires BCAST mode to be enabled");
+        }
+        return error("ERR Only BCAST tracking is supported");
+    }
+    let redirect = match redirect {
+        Some(redirect) => redirect,
+        None => return error("ERR Tracking needs REDIRECT, as there is no RESP3"),
+    };
+    ks.tracking.insert(client, Tracking { redirect, prefixes });
+    ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Push;
+    use futures::Stream;
+
+    #[test]
+    fn tracking_broadcasts_invalidations_to_the_redirect_client() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        let (redirect, pushes) = ks.test_client();
+        let id = redirect.0.to_string();
+        assert_eq!(
+            ks.command(client, &["client", "getredir"]),
+            Frame::Integer(-1)
+        );
+        assert_eq!(
+            ks.command(client, &["client", "tracking", "on", "redirect", &id]),
+            error("ERR Only BCAST tracking is supported")
+        );
+        assert_eq!(
+            ks.command(client, &["client", "tracking", "on", "bcast"]),
+            error("ERR Tracking needs REDIRECT, as there is no RESP3")
+        );
+        assert_eq!(
+            ks.command(
+                client,
+                &["client", "tracking", "on", "redirect", "999", "bcast"]
+            ),
+            error("ERR The client ID you want redirect to does not exist")
+        );
+        let on = [
+            "client", "tracking", "on", "redirect", &id, "bcast", "prefix", "user:",
+        ];
+        assert_eq!(ks.command(client, &on), ok());
+        assert_eq!(
+            ks.command(client, &["client", "getredir"]),
+            Frame::Integer(redirect.0 as i64)
+        );
+        ks.command(redirect, &["subscribe", "__redis__:invalidate"]);
+
+        ks.command(client, &["set", "user:1", "a"]);
+        ks.command(client, &["set", "other", "a"]);
+        ks.command(client, &["del", "user:1"]);
+        ks.command(client, &["flushdb"]);
+        assert_eq!(ks.command(client, &["client", "tracking", "off"]), ok());
+        ks.command(client, &["set", "user:1", "a"]);
+
+        // Dropping the client ends what it was pushed.
+        ks.clients.remove(&redirect);
+        let pushed: Vec<Push> = pushes.wait().map(Result::unwrap).collect();
+        let invalidated = |keys: Frame| {
+            Push::Frame(Frame::Array(vec![
+                Frame::Bulk(Bytes::from("message")),
+                Frame::Bulk(Bytes::from("__redis__:invalidate")),
+                keys,
+            ]))
+        };
+        let key = Frame::Array(vec![Frame::Bulk(Bytes::from("user:1"))]);
+        assert_eq!(
+            pushed,
+            vec![
+                Push::Class(ClientClass::Pubsub),
+                invalidated(key.clone()),
+                invalidated(key),
+                invalidated(Frame::NullArray),
+            ]
+        );
+    }
+
+    #[test]
+    fn client_info_and_list_as_json() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        let (other, _) = ks.test_client();
+        ks.command(client, &["client", "setname", "w\\\"x"]);
+        ks.command(other, &["select", "2"]);
+
+        let expected = format!(
+            r#"{{"id":{},"addr":"127.0.0.1:6379","laddr":"127.0.0.1:6379","name":"w\\\"x","age":0,"idle":0,"flags":"N","db":0,"cmd":"client|info"}}"#,
+            client.0
+        );
+        assert_eq!(
+            ks.command(client, &["client", "info", "json"]),
+            Frame::Bulk(expected.into())
+        );
+
+        let id = other.0.to_string();
+        let listed = ks.command(client, &["client", "list", "id", &id, "JSON"]);
+        let expected = format!(
+            r#"[{{"id":{},"addr":"127.0.0.1:6379","laddr":"127.0.0.1:6379","name":"","age":0,"idle":0,"flags":"N","db":2,"cmd":"select"}}]"#,
+            other.0
+        );
+        assert_eq!(listed, Frame::Bulk(expected.into()));
+        assert_eq!(
+            ks.command(client, &["client", "list", "type", "pubsub", "json"]),
+            Frame::Bulk("[]".into())
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Commands about the connection itself: PING, SELECT, QUIT and RESET.
+
+use bytes::Bytes;
+
+use super::{ok, wrong_arity, Command};
+use crate::client::{ClientClass, ClientId};
+use crate::keyspace::Keyspace;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "ping",
+        arity: -1,
+        subcommands: false,
+        handler: ping,
+    },
+    Command {
+        name: "select",
+        arity: 2,
+        subcommands: false,
+        handler: select,
+    },
+    Command {
+        name: "quit",
+        arity: -1,
+        subcommands: false,
+        handler: quit,
+    },
+    Command {
+        name: "reset",
+        arity: 1,
+        subcommands: false,
+        handler: reset,
+    },
+];
+
+/// PING [message]
+///
+/// In subscribed mode, replies with an array of "pong" and the message, or
+/// an empty string without one.
+fn ping(ks: &mut Keyspace, client: ClientId, args: &[Bytes]) -> Frame {
+    if args.len() > 2 {
+        return wrong_arity("ping");
+    }
+    if ks.clients[&client].class == ClientClass::Pubsub {
+        return Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"pong")),
+            Frame::Bulk(args.get(1).cloned().unwrap_or_default()),
+        ]);
+    }
+    match args.get(1) {
+        None => Frame::Simple("PONG".to_string()),
+        Some(message) => Frame::Bulk(message.clone()),
+    }
+}
+
+/// SELECT index
+fn select(ks: &mut Keyspace, client: ClientId, args: &[Bytes]) -> Frame {
+    let db = match ks.db_index(&args[1]) {
+        Ok(db) => db,
+        Err(e) => return e,
+    };
+    ks.clients.get_mut(&client).unwrap().db = db;
+    ks.selected = db;
+    ok()
+}
+
+/// QUIT
+///
+/// The session closes the connection once this reply is written, and
+/// doesn't read anything sent after it; see `CacheSession::start_batch`.
+fn quit(_: &mut Keyspace, _: ClientId, _: &[Bytes]) -> Frame {
+    ok()
+}
+
+/// RESET
+///
+/// Puts the connection back the way it was when it connected: no
+/// subscriptions, no transaction, and database 0 selected.
+fn reset(ks: &mut Keyspace, client: ClientId, _: &[Bytes]) -> Frame {
+    ks.unsubscribe_all(client);
+    let info = ks.clients.get_mut(&client).unwrap();
+    info.set_class(ClientClass::Normal);
+    info.transaction = None;
+    info.db = 0;
+    ks.selected = 0;
+    Frame::Simple("RESET".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simple(s: &str) -> Frame {
+        Frame::Simple(s.to_string())
+    }
+
+    #[test]
+    fn quit_and_reset_are_allowed_when_subscribed() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        ks.command(client, &["subscribe", "news"]);
+        ks.command(client, &["psubscribe", "n*"]);
+        ks.command(client, &["ssubscribe", "shard"]);
+        assert!(matches!(ks.command(client, &["get", "k"]), Frame::Error(_)));
+
+        assert_eq!(ks.command(client, &["quit"]), ok());
+        assert_eq!(ks.command(client, &["reset"]), simple("RESET"));
+        assert_eq!(ks.command(client, &["get", "k"]), Frame::Null);
+        assert_eq!(ks.channels.count(client), 0);
+        assert_eq!(ks.patterns.count(client), 0);
+        assert_eq!(ks.shard_channels.count(client), 0);
+    }
+
+    #[test]
+    fn reset_discards_transaction_and_selects_db_0() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        ks.command(client, &["select", "3"]);
+        ks.command(client, &["multi"]);
+        assert_eq!(ks.command(client, &["set", "k", "v"]), simple("QUEUED"));
+
+        assert_eq!(ks.command(client, &["reset"]), simple("RESET"));
+        assert!(matches!(ks.command(client, &["exec"]), Frame::Error(_)));
+        assert_eq!(ks.command(client, &["set", "k", "v"]), ok());
+        assert_eq!(ks.dbs[0].len(), 1);
+        assert_eq!(ks.dbs[3].len(), 0);
+    }
+
+    #[test]
+    fn refused_command_aborts_transaction() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        ks.command(client, &["multi"]);
+        assert_eq!(ks.command(client, &["set", "k", "v"]), simple("QUEUED"));
+        assert!(matches!(ks.command(client, &["set", "k"]), Frame::Error(_)));
+        assert!(matches!(ks.command(client, &["nosuch"]), Frame::Error(_)));
+        assert_eq!(ks.command(client, &["set", "j", "v"]), simple("QUEUED"));
+
+        assert_eq!(
+            ks.command(client, &["exec"]),
+            Frame::Error("EXECABORT Transaction discarded because of previous errors.".into())
+        );
+        assert_eq!(ks.dbs[0].len(), 0);
+        // The transaction is over either way.
+        assert_eq!(ks.command(client, &["set", "k", "v"]), ok());
+        assert_eq!(
+            ks.command(client, &["exec"]),
+            Frame::Error("ERR EXEC without MULTI".into())
+        );
+    }
+
+    #[test]
+    fn runtime_errors_dont_stop_transaction() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        ks.command(client, &["set", "s", "v"]);
+        ks.command(client, &["multi"]);
+        ks.command(client, &["set", "a", "1"]);
+        ks.command(client, &["lpush", "s", "x"]);
+        ks.command(client, &["set", "b", "2"]);
+
+        match ks.command(client, &["exec"]) {
+            Frame::Array(replies) => {
+                assert_eq!(replies.len(), 3);
+                assert_eq!(replies[0], ok());
+                assert!(matches!(&replies[1], Frame::Error(e) if e.starts_with("WRONGTYPE")));
+                assert_eq!(replies[2], ok());
+            }
+            other => panic!("not an array: {:?}", other),
+        }
+        assert_eq!(ks.command(client, &["get", "b"]), Frame::Bulk("2".into()));
+    }
+
+    #[test]
+    fn multi_cannot_be_nested() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        assert_eq!(ks.command(client, &["multi"]), ok());
+        ks.command(client, &["set", "k", "v"]);
+        assert_eq!(
+            ks.command(client, &["multi"]),
+            Frame::Error("ERR MULTI calls can not be nested".into())
+        );
+        // Refusing it leaves the transaction as it was.
+        assert_eq!(ks.command(client, &["exec"]), Frame::Array(vec![ok()]));
+        assert_eq!(
+            ks.command(client, &["discard"]),
+            Frame::Error("ERR DISCARD without MULTI".into())
+        );
+    }
+}
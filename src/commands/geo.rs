@@ -0,0 +1,539 @@
+//! Commands on geo positions, kept in sorted sets.
+//!
+//! Each member's score is the 52-bit geohash of its position (see
+//! `crate::geohash`), so a geo key is an ordinary sorted set as far as
+//! TYPE and the Z commands are concerned.
+
+use bytes::Bytes;
+
+use super::zset::{zset, zset_or_new};
+use super::{error, lossy, parse_float, parse_int, syntax_error, Command};
+use crate::client::ClientId;
+use crate::db::Value;
+use crate::geohash::{self, Shape};
+use crate::keyspace::Keyspace;
+use crate::notify::Events;
+use crate::resp::Frame;
+use crate::zset::{ScoreBound, ScoreRange, ZSet};
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "geoadd",
+        arity: -5,
+        subcommands: false,
+        handler: geoadd,
+    },
+    Command {
+        name: "geopos",
+        arity: -2,
+        subcommands: false,
+        handler: geopos,
+    },
+    Command {
+        name: "geodist",
+        arity: -4,
+        subcommands: false,
+        handler: geodist,
+    },
+    Command {
+        name: "geosearch",
+        arity: -7,
+        subcommands: false,
+        handler: geosearch,
+    },
+    Command {
+        name: "geosearchstore",
+        arity: -8,
+        subcommands: false,
+        handler: geosearch,
+    },
+];
+
+fn parse_unit(arg: &[u8]) -> Result<f64, Frame> {
+    geohash::unit(arg)
+        .ok_or_else(|| error("ERR unsupported unit provided. please use M, KM, FT, MI"))
+}
+
+/// Parses a longitude and latitude, which must be within what geohashes
+/// cover.
+fn parse_position(long: &[u8], lat: &[u8]) -> Result<(f64, f64), Frame> {
+    let (long, lat) = (parse_float(long)?, parse_float(lat)?);
+    if !geohash::valid(long, lat) {
+        return Err(error(format!(
+            "ERR invalid longitude,latitude pair {:.6},{:.6}",
+            long, lat
+        )));
+    }
+    Ok((long, lat))
+}
+
+/// A coordinate as Redis replies with one: 17 decimal places, less any
+/// trailing zeros.
+fn coordinate(x: f64) -> Frame {
+    let text = format!("{:.17}", x);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    Frame::Bulk(Bytes::from(text.to_string()))
+}
+
+fn position_reply(score: f64) -> Frame {
+    let (long, lat) = geohash::position(score as u64);
+    Frame::Array(vec![coordinate(long), coordinate(lat)])
+}
+
+fn distance_reply(meters: f64, unit: f64) -> Frame {
+    Frame::Bulk(Bytes::from(format!("{:.4}", meters / unit)))
+}
+
+/// GEOADD key [NX | XX] [CH] longitude latitude member
+///   [longitude latitude member ...]
+///
+/// Like ZADD, with positions for scores.
+fn geoadd(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let (mut nx, mut xx, mut ch) = (false, false, false);
+    let mut i = 2;
+    while i < args.len() {
+        match lossy(&args[i]).to_ascii_lowercase().as_str() {
+            "nx" => nx = true,
+            "xx" => xx = true,
+            "ch" => ch = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    let triples = &args[i..];
+    if triples.is_empty() || !triples.len().is_multiple_of(3) {
+        return syntax_error();
+    }
+    if nx && xx {
+        return error("ERR XX and NX options at the same time are not compatible");
+    }
+    let mut scores = Vec::with_capacity(triples.len() / 3);
+    for triple in triples.chunks(3) {
+        match parse_position(&triple[0], &triple[1]) {
+            Ok((long, lat)) => {
+                scores.push(geohash::encode(long, lat, geohash::MAX_STEPS).bits as f64)
+            }
+            Err(e) => return e,
+        }
+    }
+
+    let key = &args[1];
+    match zset(ks, key) {
+        Ok(None) if xx => return Frame::Integer(0),
+        Ok(_) => {}
+        Err(e) => return e,
+    }
+    let zset = zset_or_new(ks, key).unwrap();
+    let mut changed = 0;
+    let mut written = false;
+    for (triple, &score) in triples.chunks(3).zip(&scores) {
+        let member = &triple[2];
+        match zset.score(member) {
+            Some(_) if nx => {}
+            None if xx => {}
+            Some(current) => {
+                if current != score {
+                    zset.insert(member.clone(), score);
+                    changed += ch as i64;
+                    written = true;
+                }
+            }
+            None => {
+                zset.insert(member.clone(), score);
+                changed += 1;
+                written = true;
+            }
+        }
+    }
+    ks.signal_ready(key);
+    // GEOADD is ZADD underneath, and announced as one.
+    if written {
+        ks.notify(Events::ZSET, "zadd", key);
+    }
+    Frame::Integer(changed)
+}
+
+/// GEOPOS key [member ...]
+///
+/// Replies with each member's longitude and latitude, or nil for missing
+/// members.
+fn geopos(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let zset = match zset(ks, &args[1]) {
+        Ok(zset) => zset,
+        Err(e) => return e,
+    };
+    let positions = args[2..]
+        .iter()
+        .map(
+            |member| match zset.as_ref().and_then(|zset| zset.score(member)) {
+                Some(score) => position_reply(score),
+                None => Frame::NullArray,
+            },
+        )
+        .collect();
+    Frame::Array(positions)
+}
+
+/// GEODIST key member1 member2 [M | KM | FT | MI]
+///
+/// Replies with the distance between two members, in meters by default, or
+/// nil if either is missing.
+fn geodist(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let unit = match args.len() {
+        4 => 1.0,
+        5 => match parse_unit(&args[4]) {
+            Ok(unit) => unit,
+            Err(e) => return e,
+        },
+        _ => return syntax_error(),
+    };
+    let zset = match zset(ks, &args[1]) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return Frame::Null,
+        Err(e) => return e,
+    };
+    match (zset.score(&args[2]), zset.score(&args[3])) {
+        (Some(a), Some(b)) => {
+            let (long1, lat1) = geohash::position(a as u64);
+            let (long2, lat2) = geohash::position(b as u64);
+            distance_reply(geohash::distance(long1, lat1, long2, lat2), unit)
+        }
+        _ => Frame::Null,
+    }
+}
+
+/// Where a search is centred.
+enum Centre {
+    Member(Bytes),
+    Position(f64, f64),
+}
+
+/// GEOSEARCH key FROMMEMBER member | FROMLONLAT longitude latitude
+///   BYRADIUS radius unit | BYBOX width height unit [ASC | DESC]
+///   [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+/// and GEOSEARCHSTORE destination source ... [STOREDIST]
+///
+/// Finds the members within a circle or box around a member or position.
+/// Results are unordered unless ASC or DESC asks for them nearest or
+/// furthest first; COUNT keeps the nearest `count`, or with ANY, stops at
+/// the first `count` found. GEOSEARCHSTORE stores the results as a sorted
+/// set instead, with their positions as scores, or with STOREDIST, their
+/// distances, and replies with how many there were.
+fn geosearch(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let name = lossy(&args[0]);
+    let store = name.eq_ignore_ascii_case("geosearchstore");
+    let (source, options) = if store {
+        (&args[2], &args[3..])
+    } else {
+        (&args[1], &args[2..])
+    };
+
+    let mut centres = Vec::new();
+    let mut shapes = Vec::new();
+    let mut ascending = None;
+    let (mut count, mut any) = (None, false);
+    let (mut with_coord, mut with_dist, mut with_hash, mut store_dist) =
+        (false, false, false, false);
+    let mut i = 0;
+    while i < options.len() {
+        let option = lossy(&options[i]).to_ascii_lowercase();
+        let left = options.len() - i - 1;
+        match option.as_str() {
+            "frommember" if left >= 1 => {
+                centres.push(Centre::Member(options[i + 1].clone()));
+                i += 1;
+            }
+            "fromlonlat" if left >= 2 => {
+                match parse_position(&options[i + 1], &options[i + 2]) {
+                    Ok((long, lat)) => centres.push(Centre::Position(long, lat)),
+                    Err(e) => return e,
+                }
+                i += 2;
+            }
+            "byradius" if left >= 2 => {
+                let radius = match parse_float(&options[i + 1]) {
+                    Ok(radius) if radius < 0.0 => return error("ERR radius cannot be negative"),
+                    Ok(radius) => radius,
+                    Err(e) => return e,
+                };
+                let unit = match parse_unit(&options[i + 2]) {
+                    Ok(unit) => unit,
+                    Err(e) => return e,
+                };
+                shapes.push((Shape::Radius(radius * unit), unit));
+                i += 2;
+            }
+            "bybox" if left >= 3 => {
+                let (width, height) =
+                    match (parse_float(&options[i + 1]), parse_float(&options[i + 2])) {
+                        (Ok(w), Ok(h)) if w < 0.0 || h < 0.0 => {
+                            return error("ERR height or width cannot be negative")
+                        }
+                        (Ok(w), Ok(h)) => (w, h),
+                        (Err(e), _) | (_, Err(e)) => return e,
+                    };
+                let unit = match parse_unit(&options[i + 3]) {
+                    Ok(unit) => unit,
+                    Err(e) => return e,
+                };
+                shapes.push((
+                    Shape::Box {
+                        width: width * unit,
+                        height: height * unit,
+                    },
+                    unit,
+                ));
+                i += 3;
+            }
+            "asc" => ascending = Some(true),
+            "desc" => ascending = Some(false),
+            "count" if left >= 1 => {
+                match parse_int(&options[i + 1]) {
+                    Ok(n) if n > 0 => count = Some(n as usize),
+                    Ok(_) => return error("ERR COUNT must be > 0"),
+                    Err(e) => return e,
+                }
+                i += 1;
+                if options
+                    .get(i + 1)
+                    .is_some_and(|arg| arg.eq_ignore_ascii_case(b"ANY"))
+                {
+                    any = true;
+                    i += 1;
+                }
+            }
+            "withcoord" => with_coord = true,
+            "withdist" => with_dist = true,
+            "withhash" => with_hash = true,
+            "storedist" if store => store_dist = true,
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+    if centres.len() != 1 {
+        return error(format!(
+            "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for {}",
+            name
+        ));
+    }
+    if shapes.len() != 1 {
+        return error(format!(
+            "ERR exactly one of BYRADIUS and BYBOX can be specified for {}",
+            name
+        ));
+    }
+    if store && (with_coord || with_dist || with_hash) {
+        return error(format!(
+            "ERR STORE option in {} is not compatible with WITHDIST, WITHHASH and WITHCOORD options",
+            name
+        ));
+    }
+    let (shape, unit) = shapes[0];
+    // Keeping the nearest `count` means sorting them all first.
+    if count.is_some() && !any && ascending.is_none() {
+        ascending = Some(true);
+    }
+
+    let zset = match zset(ks, source) {
+        Ok(Some(zset)) => zset,
+        Ok(None) if store => {
+            if ks.db().remove(&args[1]).is_some() {
+                ks.notify(Events::GENERIC, "del", &args[1]);
+            }
+            return Frame::Integer(0);
+        }
+        Ok(None) => return Frame::Array(Vec::new()),
+        Err(e) => return e,
+    };
+    let centre = match centres[0] {
+        Centre::Member(ref member) => match zset.score(member) {
+            Some(score) => geohash::position(score as u64),
+            None => return error("ERR could not decode requested zset member"),
+        },
+        Centre::Position(long, lat) => (long, lat),
+    };
+
+    let limit = count.unwrap_or(usize::MAX);
+    let mut found: Vec<(Bytes, f64, f64)> = Vec::new();
+    'cells: for cell in geohash::search_cells(centre, &shape) {
+        let (min, max) = geohash::score_range(cell);
+        let range = ScoreRange {
+            min: ScoreBound {
+                score: min as f64,
+                exclusive: false,
+            },
+            max: ScoreBound {
+                score: max as f64,
+                exclusive: true,
+            },
+        };
+        for (member, score) in zset
+            .iter_from_score(&range, false)
+            .take_while(|&(_, score)| range.contains(score))
+        {
+            let (long, lat) = geohash::position(score as u64);
+            if let Some(distance) = shape.distance(centre, long, lat) {
+                found.push((member.clone(), distance, score));
+                if any && found.len() == limit {
+                    break 'cells;
+                }
+            }
+        }
+    }
+    match ascending {
+        Some(true) => found.sort_by(|a, b| a.1.total_cmp(&b.1)),
+        Some(false) => found.sort_by(|a, b| b.1.total_cmp(&a.1)),
+        None => {}
+    }
+    found.truncate(limit);
+
+    if store {
+        let len = found.len();
+        if found.is_empty() {
+            if ks.db().remove(&args[1]).is_some() {
+                ks.notify(Events::GENERIC, "del", &args[1]);
+            }
+        } else {
+            let mut results = ZSet::default();
+            for (member, distance, score) in found {
+                results.insert(member, if store_dist { distance / unit } else { score });
+            }
+            ks.db().insert(args[1].clone(), Value::ZSet(results));
+            ks.signal_ready(&args[1]);
+            ks.notify(Events::ZSET, "geosearchstore", &args[1]);
+        }
+        return Frame::Integer(len as i64);
+    }
+
+    let results = found
+        .into_iter()
+        .map(|(member, distance, score)| {
+            if !(with_dist || with_hash || with_coord) {
+                return Frame::Bulk(member);
+            }
+            let mut result = vec![Frame::Bulk(member)];
+            if with_dist {
+                result.push(distance_reply(distance, unit));
+            }
+            if with_hash {
+                result.push(Frame::Integer(score as i64));
+            }
+            if with_coord {
+                result.push(position_reply(score));
+            }
+            Frame::Array(result)
+        })
+        .collect();
+    Frame::Array(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(s: &str) -> Frame {
+        Frame::Bulk(Bytes::from(s.to_string()))
+    }
+
+    /// Redis' own example set.
+    fn sicily() -> (Keyspace, ClientId) {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        ks.command(
+            client,
+            &[
+                "geoadd",
+                "Sicily",
+                "13.361389",
+                "38.115556",
+                "Palermo",
+                "15.087269",
+                "37.502669",
+                "Catania",
+                "12.758489",
+                "38.788135",
+                "edge1",
+                "17.241510",
+                "38.788135",
+                "edge2",
+            ],
+        );
+        (ks, client)
+    }
+
+    #[test]
+    fn positions_and_distances_match_redis() {
+        let (mut ks, client) = sicily();
+        assert_eq!(
+            ks.command(
+                client,
+                &["geopos", "Sicily", "Palermo", "Catania", "nowhere"]
+            ),
+            Frame::Array(vec![
+                Frame::Array(vec![
+                    bulk("13.36138933897018433"),
+                    bulk("38.11555639549629859")
+                ]),
+                Frame::Array(vec![
+                    bulk("15.08726745843887329"),
+                    bulk("37.50266842333162032")
+                ]),
+                Frame::NullArray,
+            ])
+        );
+        assert_eq!(
+            ks.command(client, &["geodist", "Sicily", "Palermo", "Catania"]),
+            bulk("166274.1516")
+        );
+        assert_eq!(
+            ks.command(client, &["geodist", "Sicily", "Palermo", "Catania", "km"]),
+            bulk("166.2742")
+        );
+    }
+
+    #[test]
+    fn searches_match_redis() {
+        let (mut ks, client) = sicily();
+        assert_eq!(
+            ks.command(
+                client,
+                &[
+                    "geosearch",
+                    "Sicily",
+                    "fromlonlat",
+                    "15",
+                    "37",
+                    "byradius",
+                    "200",
+                    "km",
+                    "asc"
+                ],
+            ),
+            Frame::Array(vec![bulk("Catania"), bulk("Palermo")])
+        );
+        assert_eq!(
+            ks.command(
+                client,
+                &[
+                    "geosearch",
+                    "Sicily",
+                    "fromlonlat",
+                    "15",
+                    "37",
+                    "bybox",
+                    "400",
+                    "400",
+                    "km",
+                    "asc",
+                    "withdist",
+                ],
+            ),
+            Frame::Array(vec![
+                Frame::Array(vec![bulk("Catania"), bulk("56.4413")]),
+                Frame::Array(vec![bulk("Palermo"), bulk("190.4424")]),
+                Frame::Array(vec![bulk("edge2"), bulk("279.7403")]),
+                Frame::Array(vec![bulk("edge1"), bulk("279.7405")]),
+            ])
+        );
+    }
+}
@@ -0,0 +1,573 @@
+//! Commands on hash values.
+//!
+//! A hash is a `Dict` of fields, so HSCAN walks it with the same cursors
+//! SCAN uses on the keyspace. As with lists, a hash whose last field is
+//! removed is deleted. Fields may expire on their own; see `crate::hash`.
+
+use bytes::Bytes;
+use rand::seq::index;
+
+use super::{
+    error, format_human_float, ok, parse_float, parse_int, syntax_error, wrong_arity, wrong_type,
+    Command, ExpireIf, Scan,
+};
+use crate::client::ClientId;
+use crate::db::{self, now_ms, Value};
+use crate::hash::Hash;
+use crate::keyspace::Keyspace;
+use crate::notify::Events;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "hset",
+        arity: -4,
+        subcommands: false,
+        handler: hset,
+    },
+    Command {
+        name: "hmset",
+        arity: -4,
+        subcommands: false,
+        handler: hset,
+    },
+    Command {
+        name: "hget",
+        arity: 3,
+        subcommands: false,
+        handler: hget,
+    },
+    Command {
+        name: "hdel",
+        arity: -3,
+        subcommands: false,
+        handler: hdel,
+    },
+    Command {
+        name: "hexists",
+        arity: 3,
+        subcommands: false,
+        handler: hexists,
+    },
+    Command {
+        name: "hgetall",
+        arity: 2,
+        subcommands: false,
+        handler: hgetall,
+    },
+    Command {
+        name: "hkeys",
+        arity: 2,
+        subcommands: false,
+        handler: hgetall,
+    },
+    Command {
+        name: "hvals",
+        arity: 2,
+        subcommands: false,
+        handler: hgetall,
+    },
+    Command {
+        name: "hlen",
+        arity: 2,
+        subcommands: false,
+        handler: hlen,
+    },
+    Command {
+        name: "hincrby",
+        arity: 4,
+        subcommands: false,
+        handler: hincrby,
+    },
+    Command {
+        name: "hincrbyfloat",
+        arity: 4,
+        subcommands: false,
+        handler: hincrbyfloat,
+    },
+    Command {
+        name: "hscan",
+        arity: -3,
+        subcommands: false,
+        handler: hscan,
+    },
+    Command {
+        name: "hrandfield",
+        arity: -2,
+        subcommands: false,
+        handler: hrandfield,
+    },
+    Command {
+        name: "hexpire",
+        arity: -6,
+        subcommands: false,
+        handler: hexpire,
+    },
+    Command {
+        name: "hpexpire",
+        arity: -6,
+        subcommands: false,
+        handler: hexpire,
+    },
+    Command {
+        name: "hexpireat",
+        arity: -6,
+        subcommands: false,
+        handler: hexpire,
+    },
+    Command {
+        name: "hpexpireat",
+        arity: -6,
+        subcommands: false,
+        handler: hexpire,
+    },
+    Command {
+        name: "httl",
+        arity: -5,
+        subcommands: false,
+        handler: httl,
+    },
+    Command {
+        name: "hpttl",
+        arity: -5,
+        subcommands: false,
+        handler: httl,
+    },
+    Command {
+        name: "hpersist",
+        arity: -5,
+        subcommands: false,
+        handler: hpersist,
+    },
+];
+
+/// The hash at `key`, or `None` if there is no such key. A key holding
+/// another type is an error reply.
+fn hash<'a>(ks: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut Hash>, Frame> {
+    match ks.db().get_mut(key) {
+        None => Ok(None),
+        Some(Value::Hash(hash)) => Ok(Some(hash)),
+        Some(_) => Err(wrong_type()),
+    }
+}
+
+/// Like `hash`, but a missing key gets a new, empty hash.
+fn hash_or_new<'a>(ks: &'a mut Keyspace, key: &Bytes) -> Result<&'a mut Hash, Frame> {
+    if !ks.db().contains(key) {
+        ks.db().insert(key.clone(), Value::Hash(Hash::default()));
+    }
+    hash(ks, key).map(|hash| hash.unwrap())
+}
+
+/// HSET key field value [field value ...] and the older HMSET, which
+/// replies OK rather than with how many fields were added.
+fn hset(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    if !args.len().is_multiple_of(2) {
+        return wrong_arity(&String::from_utf8_lossy(&args[0]).to_ascii_lowercase());
+    }
+    let hash = match hash_or_new(ks, &args[1]) {
+        Ok(hash) => hash,
+        Err(e) => return e,
+    };
+    let added = args[2..]
+        .chunks(2)
+        .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()).is_none())
+        .count();
+    ks.notify(Events::HASH, "hset", &args[1]);
+    if args[0].eq_ignore_ascii_case(b"hmset") {
+        ok()
+    } else {
+        Frame::Integer(added as i64)
+    }
+}
+
+/// HGET key field
+fn hget(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match hash(ks, &args[1]) {
+        Ok(hash) => match hash.and_then(|hash| hash.get(&args[2])) {
+            Some(value) => Frame::Bulk(value.clone()),
+            None => Frame::Null,
+        },
+        Err(e) => e,
+    }
+}
+
+/// HDEL key field [field ...]
+fn hdel(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let key = &args[1];
+    let hash = match hash(ks, key) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return Frame::Integer(0),
+        Err(e) => return e,
+    };
+    let removed = args[2..]
+        .iter()
+        .filter(|field| hash.remove(field).is_some())
+        .count();
+    let empty = hash.is_empty();
+    if removed > 0 {
+        ks.notify(Events::HASH, "hdel", key);
+    }
+    if empty {
+        ks.db().remove(key);
+        ks.notify(Events::GENERIC, "del", key);
+    }
+    Frame::Integer(removed as i64)
+}
+
+/// HEXISTS key field
+fn hexists(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match hash(ks, &args[1]) {
+        Ok(hash) => Frame::Integer(hash.is_some_and(|hash| hash.contains_key(&args[2])) as i64),
+        Err(e) => e,
+    }
+}
+
+/// HGETALL key, HKEYS key and HVALS key
+///
+/// HGETALL replies with fields and values alternating in one flat array.
+fn hgetall(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let command = args[0].to_ascii_lowercase();
+    let hash = match hash(ks, &args[1]) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return Frame::Array(Vec::new()),
+        Err(e) => return e,
+    };
+    let mut elements = Vec::new();
+    for (field, value) in hash.iter() {
+        if &command[..] != b"hvals" {
+            elements.push(Frame::Bulk(field.clone()));
+        }
+        if &command[..] != b"hkeys" {
+            elements.push(Frame::Bulk(value.clone()));
+        }
+    }
+    Frame::Array(elements)
+}
+
+/// HLEN key
+fn hlen(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match hash(ks, &args[1]) {
+        Ok(hash) => Frame::Integer(hash.map_or(0, |hash| hash.len()) as i64),
+        Err(e) => e,
+    }
+}
+
+/// HINCRBY key field increment
+///
+/// A missing field counts as 0.
+fn hincrby(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let by = match parse_int(&args[3]) {
+        Ok(by) => by,
+        Err(e) => return e,
+    };
+    let hash = match hash_or_new(ks, &args[1]) {
+        Ok(hash) => hash,
+        Err(e) => return e,
+    };
+    let current = match hash.get(&args[2]) {
+        None => 0,
+        Some(value) => match db::parse_int(value) {
+            Some(n) => n,
+            None => return error("ERR hash value is not an integer"),
+        },
+    };
+    let n = match current.checked_add(by) {
+        Some(n) => n,
+        None => return error("ERR increment or decrement would overflow"),
+    };
+    hash.update(args[2].clone(), n.to_string().into());
+    ks.notify(Events::HASH, "hincrby", &args[1]);
+    Frame::Integer(n)
+}
+
+/// HINCRBYFLOAT key field increment
+fn hincrbyfloat(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let by = match parse_float(&args[3]) {
+        Ok(by) => by,
+        Err(e) => return e,
+    };
+    let hash = match hash_or_new(ks, &args[1]) {
+        Ok(hash) => hash,
+        Err(e) => return e,
+    };
+    let current = match hash.get(&args[2]) {
+        None => 0.0,
+        Some(value) => match parse_float(value) {
+            Ok(n) => n,
+            Err(_) => return error("ERR hash value is not a float"),
+        },
+    };
+    let n = current + by;
+    if !n.is_finite() {
+        return error("ERR increment would produce NaN or Infinity");
+    }
+    let text = Bytes::from(format_human_float(n));
+    hash.update(args[2].clone(), text.clone());
+    ks.notify(Events::HASH, "hincrbyfloat", &args[1]);
+    Frame::Bulk(text)
+}
+
+/// HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]
+fn hscan(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let scan = match Scan::parse("hscan", &args[2..]) {
+        Ok(scan) => scan,
+        Err(e) => return e,
+    };
+    let hash = match hash(ks, &args[1]) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return Scan::reply(0, Vec::new()),
+        Err(e) => return e,
+    };
+    let mut elements = Vec::new();
+    let cursor = scan.run(|cursor| {
+        let mut visited = 0;
+        let next = hash.scan(cursor, |field, value| {
+            visited += 1;
+            if scan.matches(field) {
+                elements.push(Frame::Bulk(field.clone()));
+                if !scan.novalues {
+                    elements.push(Frame::Bulk(value.clone()));
+                }
+            }
+        });
+        (next, visited)
+    });
+    Scan::reply(cursor, elements)
+}
+
+/// HRANDFIELD key [count [WITHVALUES]]
+///
+/// Without a count, replies with one random field, or nil if there is no
+/// hash. A positive count returns that many distinct fields, or all of
+/// them if there are fewer; a negative one returns exactly that many,
+/// possibly repeating some.
+fn hrandfield(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let count = match args.get(2).map(|arg| parse_int(arg)) {
+        None => None,
+        Some(Ok(count)) => Some(count),
+        Some(Err(e)) => return e,
+    };
+    let with_values = match args.len() {
+        2 | 3 => false,
+        4 if args[3].eq_ignore_ascii_case(b"WITHVALUES") => true,
+        _ => return syntax_error(),
+    };
+    let hash = match hash(ks, &args[1]) {
+        Ok(Some(hash)) => hash,
+        Ok(None) if count.is_some() => return Frame::Array(Vec::new()),
+        Ok(None) => return Frame::Null,
+        Err(e) => return e,
+    };
+
+    let count = match count {
+        Some(count) => count,
+        None => return Frame::Bulk(hash.random().unwrap().0.clone()),
+    };
+    // Don't let a huge negative count make us build a huge reply.
+    if count < 0 && count.unsigned_abs() > (i64::MAX as u64) / 2 {
+        return error("ERR value is out of range");
+    }
+
+    let picked: Vec<(&Bytes, &Bytes)> = if count < 0 {
+        (0..count.unsigned_abs())
+            .map(|_| hash.random().unwrap())
+            .collect()
+    } else if count as usize >= hash.len() {
+        hash.iter().collect()
+    } else {
+        let entries: Vec<_> = hash.iter().collect();
+        index::sample(&mut rand::thread_rng(), entries.len(), count as usize)
+            .into_iter()
+            .map(|i| entries[i])
+            .collect()
+    };
+
+    let mut elements = Vec::new();
+    for (field, value) in picked {
+        elements.push(Frame::Bulk(field.clone()));
+        if with_values {
+            elements.push(Frame::Bulk(value.clone()));
+        }
+    }
+    Frame::Array(elements)
+}
+
+/// The latest expiry time a field may have: Redis keeps them in 48 bits.
+const MAX_FIELD_EXPIRY: i64 = (1 << 48) - 1;
+
+/// Parses the FIELDS numfields field [field ...] the field expiry
+/// commands end with.
+fn parse_fields(args: &[Bytes]) -> Result<&[Bytes], Frame> {
+    if !args[0].eq_ignore_ascii_case(b"FIELDS") {
+        return Err(error(
+            "ERR Mandatory argument FIELDS is missing or not at the right position",
+        ));
+    }
+    match parse_int(&args[1]) {
+        Ok(n) if n <= 0 => Err(error("ERR Parameter `numFields` should be greater than 0")),
+        Ok(n) if n as usize == args.len() - 2 => Ok(&args[2..]),
+        _ => Err(error(
+            "ERR The `numfields` parameter must match the number of arguments",
+        )),
+    }
+}
+
+/// The reply the field expiry commands give for each field of a missing
+/// key.
+fn no_fields(fields: &[Bytes]) -> Frame {
+    Frame::Array(fields.iter().map(|_| Frame::Integer(-2)).collect())
+}
+
+/// HEXPIRE key seconds, HPEXPIRE key milliseconds, HEXPIREAT key
+/// unix-time-seconds and HPEXPIREAT key unix-time-milliseconds, each
+/// followed by [NX | XX | GT | LT] FIELDS numfields field [field ...]
+///
+/// Replies with an array, one element per field: -2 if there is no such
+/// field, 0 if the condition wasn't met, 1 if the expiry was set, or 2 if
+/// the time has already passed and the field was deleted. NX only sets an
+/// expiry on fields without one, XX on fields with one, and GT and LT only
+/// move it later or earlier, where no expiry counts as never.
+fn hexpire(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let command = args[0].to_ascii_lowercase();
+    let n = match parse_int(&args[2]) {
+        Ok(n) if n < 0 => return error("ERR invalid expire time, must be >= 0"),
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+    let ms = match &command[..] {
+        b"hexpire" | b"hexpireat" => n.checked_mul(1000),
+        _ => Some(n),
+    };
+    let now = now_ms();
+    let at = match &command[..] {
+        b"hexpire" | b"hpexpire" => ms.and_then(|ms| ms.checked_add(now as i64)),
+        _ => ms,
+    };
+    let at = match at {
+        Some(at) if at <= MAX_FIELD_EXPIRY => at as u64,
+        _ => {
+            return error(format!(
+                "ERR invalid expire time in '{}' command",
+                String::from_utf8_lossy(&command)
+            ))
+        }
+    };
+
+    let mut rest = &args[3..];
+    let mut condition = ExpireIf::default();
+    if condition.parse_option(&rest[0]) {
+        rest = &rest[1..];
+    }
+    if rest.len() < 2 {
+        return wrong_arity(&String::from_utf8_lossy(&command));
+    }
+    let fields = match parse_fields(rest) {
+        Ok(fields) => fields,
+        Err(e) => return e,
+    };
+
+    let key = &args[1];
+    let hash = match hash(ks, key) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return no_fields(fields),
+        Err(e) => return e,
+    };
+    let mut results = Vec::with_capacity(fields.len());
+    let (mut expiring, mut deleted) = (false, false);
+    for field in fields {
+        if !hash.contains_key(field) {
+            results.push(Frame::Integer(-2));
+            continue;
+        }
+        results.push(Frame::Integer(
+            if !condition.allows(hash.expiry(field), at) {
+                0
+            } else if at <= now {
+                hash.remove(field);
+                deleted = true;
+                2
+            } else {
+                hash.set_expiry(field, at);
+                expiring = true;
+                1
+            },
+        ));
+    }
+    let empty = hash.is_empty();
+    if expiring {
+        ks.notify(Events::HASH, "hexpire", key);
+    }
+    if deleted {
+        ks.notify(Events::HASH, "hdel", key);
+    }
+    if empty {
+        ks.db().remove(key);
+        ks.notify(Events::GENERIC, "del", key);
+    } else {
+        ks.db().watch_fields(key);
+    }
+    Frame::Array(results)
+}
+
+/// HTTL key FIELDS numfields field [field ...] and HPTTL likewise
+///
+/// Replies with an array of how long each field has left, or -2 if there
+/// is no such field, or -1 if it has no expiry.
+fn httl(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let millis = args[0].eq_ignore_ascii_case(b"hpttl");
+    let fields = match parse_fields(&args[2..]) {
+        Ok(fields) => fields,
+        Err(e) => return e,
+    };
+    let hash = match hash(ks, &args[1]) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return no_fields(fields),
+        Err(e) => return e,
+    };
+    let now = now_ms();
+    let ttls = fields
+        .iter()
+        .map(|field| match hash.expiry(field) {
+            _ if !hash.contains_key(field) => Frame::Integer(-2),
+            None => Frame::Integer(-1),
+            Some(at) => {
+                let left = at.saturating_sub(now);
+                Frame::Integer(if millis { left } else { (left + 500) / 1000 } as i64)
+            }
+        })
+        .collect();
+    Frame::Array(ttls)
+}
+
+/// HPERSIST key FIELDS numfields field [field ...]
+///
+/// Replies with an array, one element per field: -2 if there is no such
+/// field, -1 if it had no expiry, or 1 if its expiry was removed.
+fn hpersist(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let fields = match parse_fields(&args[2..]) {
+        Ok(fields) => fields,
+        Err(e) => return e,
+    };
+    let hash = match hash(ks, &args[1]) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return no_fields(fields),
+        Err(e) => return e,
+    };
+    let results: Vec<Frame> = fields
+        .iter()
+        .map(|field| {
+            Frame::Integer(if !hash.contains_key(field) {
+                -2
+            } else if hash.persist(field) {
+                1
+            } else {
+                -1
+            })
+        })
+        .collect();
+    if results.contains(&Frame::Integer(1)) {
+        ks.notify(Events::HASH, "hpersist", &args[1]);
+    }
+    Frame::Array(results)
+}
@@ -0,0 +1,443 @@
+//! Commands that work on keys of any type.
+
+use bytes::Bytes;
+
+use super::{error, lossy, ok, parse_int, syntax_error, wrong_arity, Command, ExpireIf, Scan};
+use crate::client::ClientId;
+use crate::db::{now_ms, Value};
+use crate::glob;
+use crate::keyspace::Keyspace;
+use crate::notify::Events;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "del",
+        arity: -2,
+        subcommands: false,
+        handler: del,
+    },
+    Command {
+        name: "unlink",
+        arity: -2,
+        subcommands: false,
+        handler: unlink,
+    },
+    Command {
+        name: "exists",
+        arity: -2,
+        subcommands: false,
+        handler: exists,
+    },
+    Command {
+        name: "keys",
+        arity: 2,
+        subcommands: false,
+        handler: keys,
+    },
+    Command {
+        name: "scan",
+        arity: -2,
+        subcommands: false,
+        handler: scan,
+    },
+    Command {
+        name: "type",
+        arity: 2,
+        subcommands: false,
+        handler: type_,
+    },
+    Command {
+        name: "object",
+        arity: -2,
+        subcommands: true,
+        handler: object,
+    },
+    Command {
+        name: "rename",
+        arity: 3,
+        subcommands: false,
+        handler: rename,
+    },
+    Command {
+        name: "renamenx",
+        arity: 3,
+        subcommands: false,
+        handler: rename,
+    },
+    Command {
+        name: "move",
+        arity: 3,
+        subcommands: false,
+        handler: move_,
+    },
+    Command {
+        name: "copy",
+        arity: -3,
+        subcommands: false,
+        handler: copy,
+    },
+    Command {
+        name: "expire",
+        arity: -3,
+        subcommands: false,
+        handler: expire,
+    },
+    Command {
+        name: "pexpire",
+        arity: -3,
+        subcommands: false,
+        handler: expire,
+    },
+    Command {
+        name: "expireat",
+        arity: -3,
+        subcommands: false,
+        handler: expire,
+    },
+    Command {
+        name: "pexpireat",
+        arity: -3,
+        subcommands: false,
+        handler: expire,
+    },
+    Command {
+        name: "ttl",
+        arity: 2,
+        subcommands: false,
+        handler: ttl,
+    },
+    Command {
+        name: "pttl",
+        arity: 2,
+        subcommands: false,
+        handler: ttl,
+    },
+    Command {
+        name: "expiretime",
+        arity: 2,
+        subcommands: false,
+        handler: expiretime,
+    },
+    Command {
+        name: "pexpiretime",
+        arity: 2,
+        subcommands: false,
+        handler: expiretime,
+    },
+    Command {
+        name: "persist",
+        arity: 2,
+        subcommands: false,
+        handler: persist,
+    },
+];
+
+/// DEL key [key ...]
+fn del(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let mut removed = 0;
+    for key in &args[1..] {
+        if ks.db().remove(key).is_some() {
+            ks.notify(Events::GENERIC, "del", key);
+            removed += 1;
+        }
+    }
+    Frame::Integer(removed)
+}
+
+/// UNLINK key [key ...]
+///
+/// Like DEL, but large values are dropped on the lazyfree thread, so
+/// unlinking a huge one doesn't hold up other clients.
+fn unlink(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let mut removed = 0;
+    for key in &args[1..] {
+        if let Some(value) = ks.db().remove(key) {
+            if value.is_large() {
+                ks.lazyfree.free(value);
+            }
+            ks.notify(Events::GENERIC, "del", key);
+            removed += 1;
+        }
+    }
+    Frame::Integer(removed)
+}
+
+/// EXISTS key [key ...]
+///
+/// A key named more than once is counted each time, as in Redis.
+fn exists(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let found = args[1..].iter().filter(|key| ks.db().contains(key)).count();
+    Frame::Integer(found as i64)
+}
+
+/// KEYS pattern
+fn keys(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let pattern = &args[1];
+    // Matching everything is common enough to skip the matcher for.
+    let all = &pattern[..] == b"*";
+    let keys = ks
+        .db()
+        .keys()
+        .filter(|key| all || glob::matches(pattern, key, false))
+        .map(|key| Frame::Bulk(key.clone()))
+        .collect();
+    Frame::Array(keys)
+}
+
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+fn scan(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let scan = match Scan::parse("scan", &args[1..]) {
+        Ok(scan) => scan,
+        Err(e) => return e,
+    };
+    let mut keys = Vec::new();
+    let cursor = scan.run(|cursor| {
+        let mut visited = 0;
+        let next = ks.db().scan(cursor, |key, value| {
+            visited += 1;
+            let wanted = scan.matches(key)
+                && scan
+                    .type_name
+                    .as_ref()
+                    .is_none_or(|t| &t[..] == value.type_name().as_bytes());
+            if wanted {
+                keys.push(Frame::Bulk(key.clone()));
+            }
+        });
+        (next, visited)
+    });
+    Scan::reply(cursor, keys)
+}
+
+/// TYPE key
+fn type_(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let name = ks.db().get(&args[1]).map_or("none", Value::type_name);
+    Frame::Simple(name.to_string())
+}
+
+const OBJECT_HELP: &[&str] = &[
+    "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "ENCODING <key>",
+    "    Return the kind of internal representation used in order to store the value",
+    "    associated with a <key>.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// OBJECT ENCODING key | HELP
+fn object(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let sub = lossy(&args[1]).to_ascii_lowercase();
+    match (sub.as_str(), args.len()) {
+        ("encoding", 3) => match ks.db().get(&args[2]) {
+            Some(value) => Frame::Bulk(value.encoding().into()),
+            None => Frame::Null,
+        },
+        ("help", 2) => Frame::Array(
+            OBJECT_HELP
+                .iter()
+                .map(|line| Frame::Simple(line.to_string()))
+                .collect(),
+        ),
+        ("encoding", _) | ("help", _) => wrong_arity(&format!("object|{}", sub)),
+        _ => error(format!(
+            "ERR unknown subcommand '{}'. Try OBJECT HELP.",
+            lossy(&args[1])
+        )),
+    }
+}
+
+/// RENAME key newkey and RENAMENX key newkey
+///
+/// The value keeps its expiry, and whatever `newkey` held is replaced.
+/// RENAMENX leaves an existing `newkey` alone and replies 0.
+fn rename(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let nx = args[0].eq_ignore_ascii_case(b"renamenx");
+    let (src, dst) = (&args[1], &args[2]);
+    if !ks.db().contains(src) {
+        return error("ERR no such key");
+    }
+    if src == dst {
+        return if nx { Frame::Integer(0) } else { ok() };
+    }
+    if nx && ks.db().contains(dst) {
+        return Frame::Integer(0);
+    }
+
+    let (value, at) = ks.db().take(src).unwrap();
+    ks.db().insert_with_expiry(dst.clone(), value, at);
+    ks.signal_ready(dst);
+    ks.notify(Events::GENERIC, "rename_from", src);
+    ks.notify(Events::GENERIC, "rename_to", dst);
+    if nx {
+        Frame::Integer(1)
+    } else {
+        ok()
+    }
+}
+
+/// MOVE key db
+///
+/// Moves the key, expiry and all, to another database. Replies 0 if there
+/// is no such key or the other database already has one by that name.
+fn move_(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let to = match ks.db_index(&args[2]) {
+        Ok(to) => to,
+        Err(e) => return e,
+    };
+    if to == ks.selected {
+        return error("ERR source and destination objects are the same");
+    }
+    let key = &args[1];
+    if !ks.db().contains(key) || ks.dbs[to].contains(key) {
+        return Frame::Integer(0);
+    }
+    let (value, at) = ks.db().take(key).unwrap();
+    ks.dbs[to].insert_with_expiry(key.clone(), value, at);
+    ks.blocked.signal(to, key);
+    ks.notify(Events::GENERIC, "move_from", key);
+    ks.notify_db(to, Events::GENERIC, "move_to", key);
+    Frame::Integer(1)
+}
+
+/// COPY source destination [DB destination-db] [REPLACE]
+///
+/// The copy gets the source's expiry. Replies 0 without copying if there is
+/// no source, or if the destination exists and REPLACE wasn't given.
+fn copy(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let mut replace = false;
+    let mut to = ks.selected;
+    let mut i = 3;
+    while i < args.len() {
+        match (&args[i].to_ascii_uppercase()[..], args.get(i + 1)) {
+            (b"REPLACE", _) => replace = true,
+            (b"DB", Some(db)) => {
+                to = match ks.db_index(db) {
+                    Ok(db) => db,
+                    Err(e) => return e,
+                };
+                i += 1;
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+
+    let (src, dst) = (&args[1], &args[2]);
+    if src == dst && to == ks.selected {
+        return error("ERR source and destination objects are the same");
+    }
+    let value = match ks.db().get(src) {
+        Some(value) => value.clone(),
+        None => return Frame::Integer(0),
+    };
+    let at = ks.db().expiry(src).flatten();
+    if !replace && ks.dbs[to].contains(dst) {
+        return Frame::Integer(0);
+    }
+    ks.dbs[to].insert_with_expiry(dst.clone(), value, at);
+    ks.blocked.signal(to, dst);
+    ks.notify_db(to, Events::GENERIC, "copy_to", dst);
+    Frame::Integer(1)
+}
+
+/// EXPIRE key seconds, PEXPIRE key milliseconds, and the EXPIREAT and
+/// PEXPIREAT forms taking a unix time, each followed by
+/// [NX | XX | GT | LT]
+///
+/// A time that has already passed deletes the key. NX only sets an expiry
+/// on a key without one, XX on a key with one, and GT and LT only move it
+/// later or earlier, where no expiry counts as never. Replies 1 if the
+/// expiry was set, or 0 if there is no such key or the condition wasn't
+/// met.
+fn expire(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let command = args[0].to_ascii_lowercase();
+    let invalid = || {
+        error(format!(
+            "ERR invalid expire time in '{}' command",
+            String::from_utf8_lossy(&command)
+        ))
+    };
+    let n = match parse_int(&args[2]) {
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+
+    let ms = match &command[..] {
+        b"expire" | b"expireat" => n.checked_mul(1000),
+        _ => Some(n),
+    };
+    let at = match &command[..] {
+        b"expire" | b"pexpire" => ms.and_then(|ms| ms.checked_add(now_ms() as i64)),
+        _ => ms,
+    };
+    let at = match at {
+        Some(at) => at,
+        None => return invalid(),
+    };
+
+    let mut condition = ExpireIf::default();
+    for option in &args[3..] {
+        if !condition.parse_option(option) {
+            return error(format!("ERR Unsupported option {}", lossy(option)));
+        }
+    }
+    if let Err(e) = condition.check() {
+        return e;
+    }
+
+    let key = &args[1];
+    let current = match ks.db().expiry(key) {
+        Some(current) => current,
+        None => return Frame::Integer(0),
+    };
+    // Before 1970 is as good as now for deleting the key.
+    if !condition.allows(current, at.max(0) as u64) {
+        return Frame::Integer(0);
+    }
+    if at <= now_ms() as i64 {
+        ks.db().remove(key);
+        ks.notify(Events::GENERIC, "del", key);
+    } else {
+        ks.db().set_expiry(key, at as u64);
+        ks.notify(Events::GENERIC, "expire", key);
+    }
+    Frame::Integer(1)
+}
+
+/// TTL key and PTTL key: -2 if there is no such key, -1 if it has no
+/// expiry.
+fn ttl(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let millis = args[0].eq_ignore_ascii_case(b"pttl");
+    match ks.db().expiry(&args[1]) {
+        None => Frame::Integer(-2),
+        Some(None) => Frame::Integer(-1),
+        Some(Some(at)) => {
+            let left = at.saturating_sub(now_ms());
+            Frame::Integer(if millis { left } else { (left + 500) / 1000 } as i64)
+        }
+    }
+}
+
+/// EXPIRETIME key and PEXPIRETIME key
+///
+/// Replies with the unix time the key expires at, in seconds or
+/// milliseconds, or -2 if there is no such key, or -1 if it has no
+/// expiry.
+fn expiretime(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let millis = args[0].eq_ignore_ascii_case(b"pexpiretime");
+    match ks.db().expiry(&args[1]) {
+        None => Frame::Integer(-2),
+        Some(None) => Frame::Integer(-1),
+        Some(Some(at)) => Frame::Integer(if millis { at } else { at / 1000 } as i64),
+    }
+}
+
+/// PERSIST key
+fn persist(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    if !ks.db().persist(&args[1]) {
+        return Frame::Integer(0);
+    }
+    ks.notify(Events::GENERIC, "persist", &args[1]);
+    Frame::Integer(1)
+}
@@ -0,0 +1,660 @@
+//! Commands on list values.
+//!
+//! A list is a `VecDeque`, so pushing and popping at either end is cheap.
+//! As in Redis, a list that has been emptied is deleted rather than kept.
+
+use bytes::Bytes;
+
+use std::collections::VecDeque;
+
+use super::{
+    error, index_range, ok, parse_int, parse_timeout, syntax_error, wrong_arity, wrong_type,
+    Command, MultiPop,
+};
+use crate::client::ClientId;
+use crate::db::Value;
+use crate::keyspace::Keyspace;
+use crate::notify::Events;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "lpush",
+        arity: -3,
+        subcommands: false,
+        handler: push,
+    },
+    Command {
+        name: "rpush",
+        arity: -3,
+        subcommands: false,
+        handler: push,
+    },
+    Command {
+        name: "lpop",
+        arity: -2,
+        subcommands: false,
+        handler: pop,
+    },
+    Command {
+        name: "rpop",
+        arity: -2,
+        subcommands: false,
+        handler: pop,
+    },
+    Command {
+        name: "blpop",
+        arity: -3,
+        subcommands: false,
+        handler: bpop,
+    },
+    Command {
+        name: "brpop",
+        arity: -3,
+        subcommands: false,
+        handler: bpop,
+    },
+    Command {
+        name: "lmpop",
+        arity: -4,
+        subcommands: false,
+        handler: mpop,
+    },
+    Command {
+        name: "blmpop",
+        arity: -5,
+        subcommands: false,
+        handler: mpop,
+    },
+    Command {
+        name: "blmove",
+        arity: 6,
+        subcommands: false,
+        handler: blmove,
+    },
+    Command {
+        name: "lmove",
+        arity: 5,
+        subcommands: false,
+        handler: lmove,
+    },
+    Command {
+        name: "rpoplpush",
+        arity: 3,
+        subcommands: false,
+        handler: lmove,
+    },
+    Command {
+        name: "brpoplpush",
+        arity: 4,
+        subcommands: false,
+        handler: blmove,
+    },
+    Command {
+        name: "llen",
+        arity: 2,
+        subcommands: false,
+        handler: llen,
+    },
+    Command {
+        name: "lrange",
+        arity: 4,
+        subcommands: false,
+        handler: lrange,
+    },
+    Command {
+        name: "lindex",
+        arity: 3,
+        subcommands: false,
+        handler: lindex,
+    },
+    Command {
+        name: "lpos",
+        arity: -3,
+        subcommands: false,
+        handler: lpos,
+    },
+    Command {
+        name: "lset",
+        arity: 4,
+        subcommands: false,
+        handler: lset,
+    },
+    Command {
+        name: "linsert",
+        arity: 5,
+        subcommands: false,
+        handler: linsert,
+    },
+    Command {
+        name: "lrem",
+        arity: 4,
+        subcommands: false,
+        handler: lrem,
+    },
+    Command {
+        name: "ltrim",
+        arity: 4,
+        subcommands: false,
+        handler: ltrim,
+    },
+];
+
+/// The list at `key`, or `None` if there is no such key. A key holding
+/// another type is an error reply.
+fn list<'a>(ks: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut VecDeque<Bytes>>, Frame> {
+    match ks.db().get_mut(key) {
+        None => Ok(None),
+        Some(Value::List(list)) => Ok(Some(list)),
+        Some(_) => Err(wrong_type()),
+    }
+}
+
+/// Like `list`, but a missing key gets a new, empty list.
+fn list_or_new<'a>(ks: &'a mut Keyspace, key: &Bytes) -> Result<&'a mut VecDeque<Bytes>, Frame> {
+    if !ks.db().contains(key) {
+        ks.db().insert(key.clone(), Value::List(VecDeque::new()));
+    }
+    list(ks, key).map(|list| list.unwrap())
+}
+
+/// Deletes `key` if it holds a list that has been emptied.
+fn remove_if_empty(ks: &mut Keyspace, key: &[u8]) {
+    if matches!(ks.db().get(key), Some(Value::List(list)) if list.is_empty()) {
+        ks.db().remove(key);
+        ks.notify(Events::GENERIC, "del", key);
+    }
+}
+
+/// The keyspace event for popping from one end of a list.
+fn pop_event(left: bool) -> &'static str {
+    if left {
+        "lpop"
+    } else {
+        "rpop"
+    }
+}
+
+/// LPUSH key element [element ...] and RPUSH key element [element ...]
+///
+/// Elements are pushed one at a time, so LPUSH leaves them in reverse
+/// order. Replies with the list's new length.
+fn push(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let left = args[0].eq_ignore_ascii_case(b"lpush");
+    let list = match list_or_new(ks, &args[1]) {
+        Ok(list) => list,
+        Err(e) => return e,
+    };
+    for element in &args[2..] {
+        if left {
+            list.push_front(element.clone());
+        } else {
+            list.push_back(element.clone());
+        }
+    }
+    let len = list.len();
+    ks.signal_ready(&args[1]);
+    ks.notify(Events::LIST, if left { "lpush" } else { "rpush" }, &args[1]);
+    Frame::Integer(len as i64)
+}
+
+/// LPOP key [count] and RPOP key [count]
+///
+/// Without a count, replies with the element or nil. With one, replies with
+/// an array of up to that many elements, or a nil array if there is no
+/// list.
+fn pop(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let left = args[0].eq_ignore_ascii_case(b"lpop");
+    let count = match args.len() {
+        2 => None,
+        3 => match parse_int(&args[2]) {
+            Ok(n) if n >= 0 => Some(n as usize),
+            Ok(_) => return error("ERR value is out of range, must be positive"),
+            Err(e) => return e,
+        },
+        _ => return wrong_arity(if left { "lpop" } else { "rpop" }),
+    };
+
+    let key = &args[1];
+    let list = match list(ks, key) {
+        Ok(Some(list)) => list,
+        Ok(None) if count.is_some() => return Frame::NullArray,
+        Ok(None) => return Frame::Null,
+        Err(e) => return e,
+    };
+    let mut popped = Vec::new();
+    for _ in 0..count.unwrap_or(1).min(list.len()) {
+        let element = if left {
+            list.pop_front()
+        } else {
+            list.pop_back()
+        };
+        popped.extend(element.map(Frame::Bulk));
+    }
+    if !popped.is_empty() {
+        ks.notify(Events::LIST, pop_event(left), key);
+    }
+    remove_if_empty(ks, key);
+
+    match count {
+        Some(_) => Frame::Array(popped),
+        None => popped.pop().unwrap_or(Frame::Null),
+    }
+}
+
+/// BLPOP key [key ...] timeout and BRPOP key [key ...] timeout
+///
+/// Pops from the first of the lists that isn't empty, replying with its
+/// key and the element. If they are all empty, blocks until one of them
+/// isn't, or replies with a nil array once the timeout passes.
+fn bpop(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let left = args[0].eq_ignore_ascii_case(b"blpop");
+    let (keys, timeout) = args[1..].split_at(args.len() - 2);
+    let timeout = match parse_timeout(&timeout[0]) {
+        Ok(timeout) => timeout,
+        Err(e) => return e,
+    };
+
+    for key in keys {
+        let list = match list(ks, key) {
+            Ok(Some(list)) => list,
+            Ok(None) => continue,
+            Err(e) => return e,
+        };
+        let element = if left {
+            list.pop_front()
+        } else {
+            list.pop_back()
+        };
+        ks.notify(Events::LIST, pop_event(left), key);
+        remove_if_empty(ks, key);
+        return Frame::Array(vec![
+            Frame::Bulk(key.clone()),
+            Frame::Bulk(element.unwrap()),
+        ]);
+    }
+    ks.block(keys.to_vec(), timeout);
+    Frame::NullArray
+}
+
+/// LMPOP numkeys key [key ...] <LEFT | RIGHT> [COUNT count] and BLMPOP
+/// timeout numkeys key [key ...] <LEFT | RIGHT> [COUNT count]
+///
+/// Pops up to `count` elements, by default one, from the first of the
+/// lists that isn't empty, replying with its key and an array of them, or
+/// with a nil array if they are all empty. BLMPOP blocks until one isn't,
+/// as BLPOP does.
+fn mpop(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let blocking = args[0].eq_ignore_ascii_case(b"blmpop");
+    let timeout = match blocking.then(|| parse_timeout(&args[1])) {
+        None => None,
+        Some(Ok(timeout)) => Some(timeout),
+        Some(Err(e)) => return e,
+    };
+    let pop = match MultiPop::parse(&args[1 + blocking as usize..], ["left", "right"]) {
+        Ok(pop) => pop,
+        Err(e) => return e,
+    };
+
+    for key in pop.keys {
+        let list = match list(ks, key) {
+            Ok(Some(list)) => list,
+            Ok(None) => continue,
+            Err(e) => return e,
+        };
+        let n = pop.count.min(list.len());
+        let elements = if pop.first {
+            list.drain(..n).map(Frame::Bulk).collect()
+        } else {
+            (0..n)
+                .filter_map(|_| list.pop_back())
+                .map(Frame::Bulk)
+                .collect()
+        };
+        ks.notify(Events::LIST, pop_event(pop.first), key);
+        remove_if_empty(ks, key);
+        return Frame::Array(vec![Frame::Bulk(key.clone()), Frame::Array(elements)]);
+    }
+    if let Some(timeout) = timeout {
+        ks.block(pop.keys.to_vec(), timeout);
+    }
+    Frame::NullArray
+}
+
+/// Parses a LEFT or RIGHT argument, as whether it is LEFT.
+fn parse_end(arg: &[u8]) -> Result<bool, Frame> {
+    match &arg.to_ascii_uppercase()[..] {
+        b"LEFT" => Ok(true),
+        b"RIGHT" => Ok(false),
+        _ => Err(syntax_error()),
+    }
+}
+
+/// Pops an element from one end of the list at `src` and pushes it onto
+/// one end of the list at `dst`, which may be the same list. Returns the
+/// element, or `None` if there is nothing at `src`.
+fn move_element(
+    ks: &mut Keyspace,
+    src: &Bytes,
+    dst: &Bytes,
+    from_left: bool,
+    to_left: bool,
+) -> Result<Option<Bytes>, Frame> {
+    if list(ks, src)?.is_none() {
+        return Ok(None);
+    }
+    // Check the destination before changing anything.
+    list(ks, dst)?;
+
+    let src_list = list(ks, src)?.unwrap();
+    let element = if from_left {
+        src_list.pop_front()
+    } else {
+        src_list.pop_back()
+    }
+    .unwrap();
+    let dst_list = list_or_new(ks, dst)?;
+    if to_left {
+        dst_list.push_front(element.clone());
+    } else {
+        dst_list.push_back(element.clone());
+    }
+    ks.notify(Events::LIST, if to_left { "lpush" } else { "rpush" }, dst);
+    ks.notify(Events::LIST, pop_event(from_left), src);
+    remove_if_empty(ks, src);
+    ks.signal_ready(dst);
+    Ok(Some(element))
+}
+
+/// LMOVE source destination LEFT | RIGHT LEFT | RIGHT and RPOPLPUSH
+/// source destination, which is LMOVE ... RIGHT LEFT.
+///
+/// Pops an element from one end of `source` and pushes it onto one end of
+/// `destination` in a single step, so the element is never in neither
+/// list. The two may be the same list, which rotates it. Replies with the
+/// element, or nil if `source` is empty.
+fn lmove(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let (from_left, to_left) = match move_ends(args) {
+        Ok(ends) => ends,
+        Err(e) => return e,
+    };
+    match move_element(ks, &args[1], &args[2], from_left, to_left) {
+        Ok(Some(element)) => Frame::Bulk(element),
+        Ok(None) => Frame::Null,
+        Err(e) => e,
+    }
+}
+
+/// BLMOVE source destination LEFT | RIGHT LEFT | RIGHT timeout and
+/// BRPOPLPUSH source destination timeout
+///
+/// LMOVE that blocks while `source` is empty. Replies nil if the timeout
+/// passes.
+fn blmove(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let (from_left, to_left) = match move_ends(args) {
+        Ok(ends) => ends,
+        Err(e) => return e,
+    };
+    let timeout = match parse_timeout(&args[args.len() - 1]) {
+        Ok(timeout) => timeout,
+        Err(e) => return e,
+    };
+    match move_element(ks, &args[1], &args[2], from_left, to_left) {
+        Ok(Some(element)) => Frame::Bulk(element),
+        Ok(None) => {
+            ks.block(vec![args[1].clone()], timeout);
+            Frame::Null
+        }
+        Err(e) => e,
+    }
+}
+
+/// The ends an LMOVE-like command moves between, as whether each is the
+/// left one. The RPOPLPUSH forms always move from the right to the left.
+fn move_ends(args: &[Bytes]) -> Result<(bool, bool), Frame> {
+    let name = args[0].to_ascii_lowercase();
+    if name.ends_with(b"rpoplpush") {
+        return Ok((false, true));
+    }
+    Ok((parse_end(&args[3])?, parse_end(&args[4])?))
+}
+
+/// LLEN key
+fn llen(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match list(ks, &args[1]) {
+        Ok(list) => Frame::Integer(list.map_or(0, |list| list.len()) as i64),
+        Err(e) => e,
+    }
+}
+
+/// LRANGE key start stop
+fn lrange(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let (start, stop) = match (parse_int(&args[2]), parse_int(&args[3])) {
+        (Ok(start), Ok(stop)) => (start, stop),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    let list = match list(ks, &args[1]) {
+        Ok(Some(list)) => list,
+        Ok(None) => return Frame::Array(Vec::new()),
+        Err(e) => return e,
+    };
+    let elements = match index_range(start, stop, list.len()) {
+        Some((start, stop)) => list
+            .range(start..=stop)
+            .map(|element| Frame::Bulk(element.clone()))
+            .collect(),
+        None => Vec::new(),
+    };
+    Frame::Array(elements)
+}
+
+/// Resolves a possibly negative index into a list of `len` elements.
+fn index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    if index < 0 || index >= len as i64 {
+        None
+    } else {
+        Some(index as usize)
+    }
+}
+
+/// LINDEX key index
+fn lindex(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let i = match parse_int(&args[2]) {
+        Ok(i) => i,
+        Err(e) => return e,
+    };
+    match list(ks, &args[1]) {
+        Ok(Some(list)) => match index(i, list.len()) {
+            Some(i) => Frame::Bulk(list[i].clone()),
+            None => Frame::Null,
+        },
+        Ok(None) => Frame::Null,
+        Err(e) => e,
+    }
+}
+
+/// LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]
+///
+/// Replies with the index of the first element equal to `element`, or nil
+/// if there is none. RANK skips to the rank-th match, counting back from
+/// the tail if negative; COUNT replies with up to that many matches as an
+/// array, or with 0 all of them; MAXLEN looks at no more than that many
+/// elements.
+fn lpos(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let mut rank = 1;
+    let mut count = None;
+    let mut max_len = 0;
+    for option in args[3..].chunks(2) {
+        let n = match option {
+            [_, n] => match parse_int(n) {
+                Ok(n) => n,
+                Err(e) => return e,
+            },
+            _ => return syntax_error(),
+        };
+        let option = option[0].to_ascii_lowercase();
+        match &option[..] {
+            b"rank" if n == 0 => {
+                return error("ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list")
+            }
+            b"rank" if n == i64::MIN => return error("ERR value is out of range"),
+            b"rank" => rank = n,
+            b"count" if n < 0 => return error("ERR COUNT can't be negative"),
+            b"count" => count = Some(n as usize),
+            b"maxlen" if n < 0 => return error("ERR MAXLEN can't be negative"),
+            b"maxlen" => max_len = n as usize,
+            _ => return syntax_error(),
+        }
+    }
+
+    let list = match list(ks, &args[1]) {
+        Ok(list) => list,
+        Err(e) => return e,
+    };
+    let len = list.as_ref().map_or(0, |list| list.len());
+    let looked_at = if max_len == 0 { len } else { max_len.min(len) };
+    let indexes: Box<dyn Iterator<Item = usize>> = if rank > 0 {
+        Box::new(0..looked_at)
+    } else {
+        Box::new((len - looked_at..len).rev())
+    };
+    let matches = indexes
+        .filter(|&i| list.as_ref().is_some_and(|list| list[i] == args[2]))
+        .skip(rank.unsigned_abs() as usize - 1);
+    match count {
+        Some(count) => {
+            let count = if count == 0 { usize::MAX } else { count };
+            Frame::Array(
+                matches
+                    .take(count)
+                    .map(|i| Frame::Integer(i as i64))
+                    .collect(),
+            )
+        }
+        None => matches
+            .map(|i| Frame::Integer(i as i64))
+            .next()
+            .unwrap_or(Frame::Null),
+    }
+}
+
+/// LSET key index element
+fn lset(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let i = match parse_int(&args[2]) {
+        Ok(i) => i,
+        Err(e) => return e,
+    };
+    let list = match list(ks, &args[1]) {
+        Ok(Some(list)) => list,
+        Ok(None) => return error("ERR no such key"),
+        Err(e) => return e,
+    };
+    match index(i, list.len()) {
+        Some(i) => {
+            list[i] = args[3].clone();
+            ks.notify(Events::LIST, "lset", &args[1]);
+            ok()
+        }
+        None => error("ERR index out of range"),
+    }
+}
+
+/// LINSERT key BEFORE | AFTER pivot element
+///
+/// Inserts next to the first occurrence of `pivot`. Replies with the new
+/// length, -1 if `pivot` wasn't found, or 0 if there is no list.
+fn linsert(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let after = match &args[2].to_ascii_uppercase()[..] {
+        b"BEFORE" => false,
+        b"AFTER" => true,
+        _ => return syntax_error(),
+    };
+    let list = match list(ks, &args[1]) {
+        Ok(Some(list)) => list,
+        Ok(None) => return Frame::Integer(0),
+        Err(e) => return e,
+    };
+    match list.iter().position(|element| element == &args[3]) {
+        Some(i) => {
+            list.insert(if after { i + 1 } else { i }, args[4].clone());
+            let len = list.len();
+            ks.notify(Events::LIST, "linsert", &args[1]);
+            Frame::Integer(len as i64)
+        }
+        None => Frame::Integer(-1),
+    }
+}
+
+/// LREM key count element
+///
+/// Removes up to `count` occurrences of `element`, searching from the head,
+/// or from the tail if `count` is negative. A count of 0 removes them all.
+fn lrem(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let count = match parse_int(&args[2]) {
+        Ok(count) => count,
+        Err(e) => return e,
+    };
+    let key = &args[1];
+    let list = match list(ks, key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return Frame::Integer(0),
+        Err(e) => return e,
+    };
+
+    let limit = match count {
+        0 => usize::MAX,
+        n => n.unsigned_abs() as usize,
+    };
+    let element = &args[3];
+    // Removing from the tail is removing from the head after skipping all
+    // but the last `limit` occurrences.
+    let mut skip = 0;
+    if count < 0 {
+        let found = list.iter().filter(|e| *e == element).count();
+        skip = found.saturating_sub(limit);
+    }
+    let mut removed = 0;
+    list.retain(|e| {
+        if e != element || removed == limit {
+            return true;
+        }
+        if skip > 0 {
+            skip -= 1;
+            return true;
+        }
+        removed += 1;
+        false
+    });
+    if removed > 0 {
+        ks.notify(Events::LIST, "lrem", key);
+    }
+    remove_if_empty(ks, key);
+    Frame::Integer(removed as i64)
+}
+
+/// LTRIM key start stop
+///
+/// Keeps only the elements from `start` to `stop`, inclusive.
+fn ltrim(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let (start, stop) = match (parse_int(&args[2]), parse_int(&args[3])) {
+        (Ok(start), Ok(stop)) => (start, stop),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    let key = &args[1];
+    let list = match list(ks, key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return ok(),
+        Err(e) => return e,
+    };
+    match index_range(start, stop, list.len()) {
+        Some((start, stop)) => {
+            list.truncate(stop + 1);
+            list.drain(..start);
+        }
+        None => list.clear(),
+    }
+    ks.notify(Events::LIST, "ltrim", key);
+    remove_if_empty(ks, key);
+    ok()
+}
@@ -0,0 +1,442 @@
+//! Command handlers, and the table the keyspace dispatches through.
+//!
+//! Each submodule covers one group of commands and exports a `COMMANDS`
+//! table describing them. A handler gets the whole keyspace, the client
+//! that sent the command and the command's arguments, name included, and
+//! returns the reply.
+
+use bytes::Bytes;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::client::ClientId;
+use crate::db;
+use crate::glob;
+use crate::keyspace::Keyspace;
+use crate::resp::Frame;
+
+mod bitmap;
+mod client;
+mod connection;
+mod geo;
+mod hash;
+mod keys;
+mod list;
+mod pubsub;
+mod server;
+mod set;
+mod sort;
+mod stream;
+mod string;
+mod transaction;
+mod zset;
+
+pub type Handler = fn(&mut Keyspace, ClientId, &[Bytes]) -> Frame;
+
+pub struct Command {
+    /// Lowercase, as shown in CLIENT LIST and error messages.
+    pub name: &'static str,
+    /// Number of arguments, counting the name. Negative means "at least"
+    /// that many, as in Redis' command table.
+    pub arity: i32,
+    /// Whether the first argument names a subcommand, e.g. CLIENT LIST.
+    pub subcommands: bool,
+    pub handler: Handler,
+}
+
+impl Command {
+    pub fn arity_ok(&self, args: usize) -> bool {
+        let args = args as i32;
+        if self.arity < 0 {
+            args >= -self.arity
+        } else {
+            args == self.arity
+        }
+    }
+
+    /// Whether the command is queued, rather than run, between MULTI and
+    /// EXEC.
+    pub fn queued_in_transaction(&self) -> bool {
+        !matches!(self.name, "multi" | "exec" | "discard" | "quit" | "reset")
+    }
+
+    /// Whether a client in subscribed mode may run the command.
+    pub fn allowed_when_subscribed(&self) -> bool {
+        matches!(
+            self.name,
+            "subscribe"
+                | "unsubscribe"
+                | "psubscribe"
+                | "punsubscribe"
+                | "ssubscribe"
+                | "sunsubscribe"
+                | "ping"
+                | "quit"
+                | "reset"
+        )
+    }
+}
+
+/// Every command, keyed by lowercase name.
+pub fn table() -> HashMap<&'static [u8], &'static Command> {
+    let groups = [
+        bitmap::COMMANDS,
+        client::COMMANDS,
+        connection::COMMANDS,
+        geo::COMMANDS,
+        hash::COMMANDS,
+        keys::COMMANDS,
+        list::COMMANDS,
+        pubsub::COMMANDS,
+        server::COMMANDS,
+        set::COMMANDS,
+        sort::COMMANDS,
+        stream::COMMANDS,
+        string::COMMANDS,
+        transaction::COMMANDS,
+        zset::COMMANDS,
+    ];
+    groups
+        .iter()
+        .flat_map(|group| group.iter())
+        .map(|command| (command.name.as_bytes(), command))
+        .collect()
+}
+
+pub fn ok() -> Frame {
+    Frame::Simple("OK".to_string())
+}
+
+pub fn error<S: Into<String>>(msg: S) -> Frame {
+    Frame::Error(msg.into())
+}
+
+pub fn syntax_error() -> Frame {
+    error("ERR syntax error")
+}
+
+pub fn wrong_arity(command: &str) -> Frame {
+    error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        command
+    ))
+}
+
+pub fn wrong_type() -> Frame {
+    error("WRONGTYPE Operation against a key holding the wrong kind of value")
+}
+
+/// Parses an integer argument, or produces the error reply for one that
+/// isn't. See `db::parse_int` for what counts.
+pub fn parse_int(arg: &[u8]) -> Result<i64, Frame> {
+    db::parse_int(arg).ok_or_else(|| error("ERR value is not an integer or out of range"))
+}
+
+/// Parses the timeout of a blocking command: seconds, possibly fractional,
+/// where 0 means to wait forever.
+pub fn parse_timeout(arg: &[u8]) -> Result<Option<Duration>, Frame> {
+    let secs = std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|f| f.is_finite())
+        .ok_or_else(|| error("ERR timeout is not a float or out of range"))?;
+    if secs < 0.0 {
+        return Err(error("ERR timeout is negative"));
+    }
+    match Duration::try_from_secs_f64(secs) {
+        Ok(timeout) if timeout.is_zero() => Ok(None),
+        Ok(timeout) => Ok(Some(timeout)),
+        Err(_) => Err(error("ERR timeout is out of range")),
+    }
+}
+
+/// Resolves a `start`/`stop` pair of indexes into a sequence of `len`
+/// elements, where negative indexes count back from the end, as LRANGE and
+/// friends take them. Returns the inclusive range they cover, clamped to
+/// the sequence, or `None` if it's empty.
+pub fn index_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        stop + len
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+/// Like `parse_int`, for floating point arguments. Infinities and NaN are
+/// refused.
+pub fn parse_float(arg: &[u8]) -> Result<f64, Frame> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|f| f.is_finite())
+        .ok_or_else(|| error("ERR value is not a valid float"))
+}
+
+/// Formats a float the way Redis replies with one: the shortest form that
+/// reads back as the same number, switching to an exponent for very large
+/// or very small magnitudes as `%.17g` would. Infinities, which only sorted
+/// set scores can be, are "inf" and "-inf".
+pub fn format_float(n: f64) -> String {
+    if n.is_infinite() {
+        return if n > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let exp = n.abs().log10().floor();
+    if n == 0.0 || (-4.0..17.0).contains(&exp) {
+        return n.to_string();
+    }
+    let s = format!("{:e}", n);
+    let (mantissa, exp) = s.split_at(s.find('e').unwrap());
+    let exp: i32 = exp[1..].parse().unwrap();
+    format!(
+        "{}e{}{:02}",
+        mantissa,
+        if exp < 0 { '-' } else { '+' },
+        exp.abs()
+    )
+}
+
+/// How many significant digits `format_human_float` keeps: as many as an
+/// f64 always holds, so the error of a sum like 0.1 + 0.2 doesn't show.
+const HUMAN_FLOAT_DIGITS: i32 = 15;
+
+/// How many decimals `format_human_float` writes at most, as `%.17Lf`
+/// does.
+const HUMAN_FLOAT_DECIMALS: i32 = 17;
+
+/// Formats a float the way INCRBYFLOAT and HINCRBYFLOAT reply with one,
+/// which is unlike `format_float`: never with an exponent, and with the
+/// trailing zeros of `%.17Lf` trimmed. Redis computes these in long double,
+/// whose extra precision absorbs the rounding error of the addition; here
+/// it's rounded away instead, to `HUMAN_FLOAT_DIGITS` significant digits.
+pub fn format_human_float(n: f64) -> String {
+    if n == 0.0 {
+        return "0".to_string();
+    }
+    let s = format!("{:e}", n.abs());
+    let exp: i32 = s[s.find('e').unwrap() + 1..].parse().unwrap();
+    let digits = HUMAN_FLOAT_DIGITS.min(HUMAN_FLOAT_DECIMALS + exp + 1);
+    if digits <= 0 {
+        return "0".to_string();
+    }
+
+    // Rounding can carry into another digit, so the exponent is taken
+    // again.
+    let s = format!("{:.*e}", digits as usize - 1, n.abs());
+    let (mantissa, exp) = s.split_at(s.find('e').unwrap());
+    let exp: i32 = exp[1..].parse().unwrap();
+    let digits = mantissa.replace('.', "");
+    let mut text = if exp < 0 {
+        format!("0.{}{}", "0".repeat((-exp - 1) as usize), digits)
+    } else if exp as usize + 1 >= digits.len() {
+        format!("{}{}", digits, "0".repeat(exp as usize + 1 - digits.len()))
+    } else {
+        let (int, frac) = digits.split_at(exp as usize + 1);
+        format!("{}.{}", int, frac)
+    };
+    if text.contains('.') {
+        let trimmed = text.trim_end_matches('0').trim_end_matches('.').len();
+        text.truncate(trimmed);
+    }
+    if n < 0.0 {
+        text.insert(0, '-');
+    }
+    text
+}
+
+/// An argument as text, for messages and for matching keywords.
+pub fn lossy(arg: &[u8]) -> String {
+    String::from_utf8_lossy(arg).into_owned()
+}
+
+/// How many buckets a scan visits for each element it was asked for before
+/// it gives up on filling COUNT, so a sparse table still replies promptly.
+const SCAN_BUCKETS_PER_ELEMENT: usize = 10;
+
+/// The arguments SCAN, HSCAN and the like share, from the cursor on.
+///
+/// COUNT is a hint of how many elements to look at, not to return: MATCH
+/// and the other filters apply after they are gathered, as in Redis, so a
+/// batch may well be empty before the scan is over.
+pub struct Scan {
+    pub cursor: u64,
+    pub pattern: Option<Bytes>,
+    pub count: usize,
+    /// SCAN's TYPE filter, lowercase.
+    pub type_name: Option<Vec<u8>>,
+    /// HSCAN's NOVALUES flag.
+    pub novalues: bool,
+}
+
+impl Scan {
+    /// Parses `cursor [MATCH pattern] [COUNT count]`, plus TYPE for SCAN
+    /// and NOVALUES for HSCAN.
+    pub fn parse(command: &str, args: &[Bytes]) -> Result<Scan, Frame> {
+        let cursor = std::str::from_utf8(&args[0])
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .ok_or_else(|| error("ERR invalid cursor"))?;
+        let mut scan = Scan {
+            cursor,
+            pattern: None,
+            count: 10,
+            type_name: None,
+            novalues: false,
+        };
+
+        let mut i = 1;
+        while i < args.len() {
+            let opt = args[i].to_ascii_uppercase();
+            if &opt[..] == b"NOVALUES" && command == "hscan" {
+                scan.novalues = true;
+                i += 1;
+                continue;
+            }
+            let value = args.get(i + 1).ok_or_else(syntax_error)?;
+            match &opt[..] {
+                b"MATCH" => scan.pattern = Some(value.clone()).filter(|p| &p[..] != b"*"),
+                b"COUNT" => match parse_int(value)? {
+                    n if n >= 1 => scan.count = n as usize,
+                    _ => return Err(syntax_error()),
+                },
+                b"TYPE" if command == "scan" => scan.type_name = Some(value.to_ascii_lowercase()),
+                _ => return Err(syntax_error()),
+            }
+            i += 2;
+        }
+        Ok(scan)
+    }
+
+    /// Whether `key` passes the MATCH pattern, if there is one.
+    pub fn matches(&self, key: &[u8]) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|p| glob::matches(p, key, false))
+    }
+
+    /// Drives the scan from `self.cursor`. `step` visits the bucket at the
+    /// cursor it's given, as `Dict::scan` does, and returns the next cursor
+    /// and how many elements it looked at. Returns the cursor the client
+    /// should carry on from.
+    pub fn run<F: FnMut(u64) -> (u64, usize)>(&self, mut step: F) -> u64 {
+        let mut cursor = self.cursor;
+        let mut visited = 0;
+        let mut buckets = self.count.saturating_mul(SCAN_BUCKETS_PER_ELEMENT);
+        loop {
+            let (next, n) = step(cursor);
+            cursor = next;
+            visited += n;
+            buckets -= 1;
+            if cursor == 0 || visited >= self.count || buckets == 0 {
+                return cursor;
+            }
+        }
+    }
+
+    /// The reply: the next cursor and the elements found.
+    pub fn reply(cursor: u64, elements: Vec<Frame>) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk(cursor.to_string().into()),
+            Frame::Array(elements),
+        ])
+    }
+}
+
+/// The arguments LMPOP, ZMPOP and their blocking forms share, from numkeys
+/// on: numkeys key [key ...] <end> [COUNT count], where the end is LEFT or
+/// RIGHT for lists and MIN or MAX for sorted sets.
+pub struct MultiPop<'a> {
+    pub keys: &'a [Bytes],
+    /// Whether to pop from the first of the two ends: LEFT or MIN.
+    pub first: bool,
+    pub count: usize,
+}
+
+impl<'a> MultiPop<'a> {
+    pub fn parse(args: &'a [Bytes], ends: [&str; 2]) -> Result<MultiPop<'a>, Frame> {
+        let numkeys = match parse_int(&args[0]) {
+            Ok(n) if n > 0 => n as usize,
+            _ => return Err(error("ERR numkeys should be greater than 0")),
+        };
+        if numkeys > args.len() - 2 {
+            return Err(syntax_error());
+        }
+        let keys = &args[1..=numkeys];
+        let first = match &args[numkeys + 1] {
+            end if end.eq_ignore_ascii_case(ends[0].as_bytes()) => true,
+            end if end.eq_ignore_ascii_case(ends[1].as_bytes()) => false,
+            _ => return Err(syntax_error()),
+        };
+        let count = match &args[numkeys + 2..] {
+            [] => 1,
+            [option, n] if option.eq_ignore_ascii_case(b"COUNT") => match parse_int(n) {
+                Ok(n) if n > 0 => n as usize,
+                _ => return Err(error("ERR count should be greater than 0")),
+            },
+            _ => return Err(syntax_error()),
+        };
+        Ok(MultiPop { keys, first, count })
+    }
+}
+
+/// The NX, XX, GT and LT options of EXPIRE and its relatives, saying when
+/// to replace an expiry time with a new one. Having no expiry counts as
+/// expiring later than any time.
+#[derive(Clone, Copy, Default)]
+pub struct ExpireIf {
+    /// Only if there is no expiry.
+    pub nx: bool,
+    /// Only if there is one.
+    pub xx: bool,
+    /// Only if the new time is later.
+    pub gt: bool,
+    /// Only if the new time is earlier.
+    pub lt: bool,
+}
+
+impl ExpireIf {
+    /// Sets the option `arg` names. Returns false if it doesn't name one.
+    pub fn parse_option(&mut self, arg: &[u8]) -> bool {
+        match &arg.to_ascii_lowercase()[..] {
+            b"nx" => self.nx = true,
+            b"xx" => self.xx = true,
+            b"gt" => self.gt = true,
+            b"lt" => self.lt = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Checks the options set can go together.
+    pub fn check(&self) -> Result<(), Frame> {
+        if self.nx && (self.xx || self.gt || self.lt) {
+            return Err(error(
+                "ERR NX and XX, GT or LT options at the same time are not compatible",
+            ));
+        }
+        if self.gt && self.lt {
+            return Err(error(
+                "ERR GT and LT options at the same time are not compatible",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether to replace the `current` expiry time with `at`.
+    pub fn allows(&self, current: Option<u64>, at: u64) -> bool {
+        !(self.nx && current.is_some()
+            || self.xx && current.is_none()
+            || self.gt && current.is_none_or(|current| at <= current)
+            || self.lt && current.is_some_and(|current| at >= current))
+    }
+}
@@ -0,0 +1,281 @@
+//! Pub/sub: SUBSCRIBE, PSUBSCRIBE and SSUBSCRIBE, their UNSUBSCRIBE
+//! counterparts, PUBLISH, SPUBLISH and PUBSUB.
+//!
+//! The subscribing commands reply once for each channel or pattern, each
+//! reply carrying how many subscriptions the client has left: channels and
+//! patterns together, or shard channels on their own. Messages reach
+//! subscribers through the keyspace's push channels; see
+//! `Keyspace::publish`.
+//!
+//! Shard channels work like the others, but are kept apart from them, so
+//! that in a cluster their messages need only go to the nodes serving the
+//! channel's slot. Without a cluster, that's every subscriber.
+//!
+//! While a client has any subscriptions it is in subscribed mode, where
+//! only these commands and PING are allowed, and PING replies as a message
+//! would arrive, so it can't be mistaken for a reply to anything else.
+
+use bytes::Bytes;
+
+use super::{error, lossy, Command};
+use crate::client::{ClientClass, ClientId};
+use crate::glob;
+use crate::keyspace::Keyspace;
+use crate::pubsub::Registry;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "subscribe",
+        arity: -2,
+        subcommands: false,
+        handler: subscribe,
+    },
+    Command {
+        name: "unsubscribe",
+        arity: -1,
+        subcommands: false,
+        handler: unsubscribe,
+    },
+    Command {
+        name: "psubscribe",
+        arity: -2,
+        subcommands: false,
+        handler: subscribe,
+    },
+    Command {
+        name: "punsubscribe",
+        arity: -1,
+        subcommands: false,
+        handler: unsubscribe,
+    },
+    Command {
+        name: "publish",
+        arity: 3,
+        subcommands: false,
+        handler: publish,
+    },
+    Command {
+        name: "ssubscribe",
+        arity: -2,
+        subcommands: false,
+        handler: subscribe,
+    },
+    Command {
+        name: "sunsubscribe",
+        arity: -1,
+        subcommands: false,
+        handler: unsubscribe,
+    },
+    Command {
+        name: "spublish",
+        arity: 3,
+        subcommands: false,
+        handler: publish,
+    },
+    Command {
+        name: "pubsub",
+        arity: -2,
+        subcommands: true,
+        handler: pubsub,
+    },
+];
+
+/// Whether the command in `args` is about shard channels.
+fn sharded(args: &[Bytes]) -> bool {
+    matches!(
+        &args[0].to_ascii_lowercase()[..],
+        b"ssubscribe" | b"sunsubscribe" | b"spublish"
+    )
+}
+
+/// How many subscriptions `client` has that count towards the reply to
+/// the command in `args`, and marks it as a pub/sub client in CLIENT LIST
+/// while it has any at all.
+fn subscriptions(ks: &mut Keyspace, client: ClientId, args: &[Bytes]) -> usize {
+    let global = ks.channels.count(client) + ks.patterns.count(client);
+    let shard = ks.shard_channels.count(client);
+    if let Some(info) = ks.clients.get_mut(&client) {
+        info.set_class(if global + shard > 0 {
+            ClientClass::Pubsub
+        } else {
+            ClientClass::Normal
+        });
+    }
+    if sharded(args) {
+        shard
+    } else {
+        global
+    }
+}
+
+/// The registry the command in `args` works on.
+fn registry<'a>(ks: &'a mut Keyspace, args: &[Bytes]) -> &'a mut Registry {
+    match &args[0].to_ascii_lowercase()[..] {
+        b"psubscribe" | b"punsubscribe" => &mut ks.patterns,
+        b"ssubscribe" | b"sunsubscribe" => &mut ks.shard_channels,
+        _ => &mut ks.channels,
+    }
+}
+
+/// One of the replies the subscribing commands give per channel or
+/// pattern; `kind` is the command's name.
+fn confirmation(kind: &[u8], channel: Option<Bytes>, count: usize) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(kind.to_ascii_lowercase())),
+        channel.map_or(Frame::Null, Frame::Bulk),
+        Frame::Integer(count as i64),
+    ])
+}
+
+/// SUBSCRIBE channel [channel ...], PSUBSCRIBE pattern [pattern ...] and
+///   SSUBSCRIBE shardchannel [shardchannel ...]
+fn subscribe(ks: &mut Keyspace, client: ClientId, args: &[Bytes]) -> Frame {
+    let mut replies = Vec::with_capacity(args.len() - 1);
+    for channel in &args[1..] {
+        registry(ks, args).subscribe(client, channel.clone());
+        let count = subscriptions(ks, client, args);
+        replies.push(confirmation(&args[0], Some(channel.clone()), count));
+    }
+    Frame::Many(replies)
+}
+
+/// UNSUBSCRIBE [channel [channel ...]], PUNSUBSCRIBE [pattern [pattern
+///   ...]] and SUNSUBSCRIBE [shardchannel [shardchannel ...]]
+///
+/// Without arguments, unsubscribes from all of the client's channels,
+/// patterns or shard channels.
+fn unsubscribe(ks: &mut Keyspace, client: ClientId, args: &[Bytes]) -> Frame {
+    let channels = match &args[1..] {
+        [] => registry(ks, args).subscriptions(client),
+        channels => channels.to_vec(),
+    };
+    if channels.is_empty() {
+        let count = subscriptions(ks, client, args);
+        return confirmation(&args[0], None, count);
+    }
+    let mut replies = Vec::with_capacity(channels.len());
+    for channel in channels {
+        registry(ks, args).unsubscribe(client, &channel);
+        let count = subscriptions(ks, client, args);
+        replies.push(confirmation(&args[0], Some(channel), count));
+    }
+    Frame::Many(replies)
+}
+
+/// PUBLISH channel message, and SPUBLISH shardchannel message
+///
+/// Replies with how many clients the message was sent to, counting a
+/// client once for each of its subscriptions that matched.
+fn publish(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let receivers = if sharded(args) {
+        ks.spublish(&args[1], &args[2])
+    } else {
+        ks.publish(&args[1], &args[2])
+    };
+    Frame::Integer(receivers as i64)
+}
+
+const PUBSUB_HELP: &[&str] = &[
+    "PUBSUB <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "CHANNELS [<pattern>]",
+    "    Return the currently active channels matching a <pattern> (default: '*').",
+    "NUMSUB [<channel> ...]",
+    "    Return the number of subscribers for the specified channels, excluding",
+    "    pattern subscriptions(default: no channels).",
+    "NUMPAT",
+    "    Return number of subscriptions to patterns.",
+    "SHARDCHANNELS [<pattern>]",
+    "    Return the currently active shard level channels matching a <pattern> (default: '*').",
+    "SHARDNUMSUB [<shardchannel> ...]",
+    "    Return the number of subscribers for the specified shard level channel(s)",
+    "HELP",
+    "    Print this help.",
+];
+
+/// PUBSUB CHANNELS [pattern] | NUMSUB [channel ...] | NUMPAT |
+///   SHARDCHANNELS [pattern] | SHARDNUMSUB [shardchannel ...] | HELP
+///
+/// NUMSUB counts only SUBSCRIBE's subscribers, and NUMPAT the patterns
+/// subscribed to, however many clients share each.
+fn pubsub(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let sub = lossy(&args[1]).to_ascii_lowercase();
+    match (sub.as_str(), args.len()) {
+        ("channels", 2 | 3) => channels(&ks.channels, args.get(2)),
+        ("shardchannels", 2 | 3) => channels(&ks.shard_channels, args.get(2)),
+        ("numsub", _) => numsub(&ks.channels, &args[2..]),
+        ("shardnumsub", _) => numsub(&ks.shard_channels, &args[2..]),
+        ("numpat", 2) => Frame::Integer(ks.patterns.channels().count() as i64),
+        ("help", 2) => Frame::Array(
+            PUBSUB_HELP
+                .iter()
+                .map(|line| Frame::Simple(line.to_string()))
+                .collect(),
+        ),
+        _ => error(format!(
+            "ERR unknown subcommand '{}'. Try PUBSUB HELP.",
+            lossy(&args[1])
+        )),
+    }
+}
+
+/// The reply to PUBSUB CHANNELS and SHARDCHANNELS: the registry's channels
+/// that match `pattern`, if one was given.
+fn channels(registry: &Registry, pattern: Option<&Bytes>) -> Frame {
+    Frame::Array(
+        registry
+            .channels()
+            .filter(|channel| pattern.is_none_or(|p| glob::matches(p, channel, false)))
+            .map(|channel| Frame::Bulk(channel.clone()))
+            .collect(),
+    )
+}
+
+/// The reply to PUBSUB NUMSUB and SHARDNUMSUB: each channel, and how many
+/// clients the registry has subscribed to it.
+fn numsub(registry: &Registry, channels: &[Bytes]) -> Frame {
+    Frame::Array(
+        channels
+            .iter()
+            .flat_map(|channel| {
+                let count = registry.subscribers(channel).count();
+                [Frame::Bulk(channel.clone()), Frame::Integer(count as i64)]
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Push;
+    use futures::Stream;
+
+    #[test]
+    fn session_hears_of_class_changes() {
+        let mut ks = Keyspace::testing();
+        let (client, pushed) = ks.test_client();
+        let (publisher, _) = ks.test_client();
+        ks.command(client, &["subscribe", "news"]);
+        ks.command(client, &["ssubscribe", "shard"]);
+        ks.command(publisher, &["publish", "news", "hi"]);
+        ks.command(client, &["unsubscribe"]);
+        ks.command(client, &["sunsubscribe"]);
+
+        // Dropping the client ends what it was pushed.
+        ks.clients.remove(&client);
+        let pushed: Vec<Push> = pushed.wait().map(Result::unwrap).collect();
+        assert_eq!(
+            pushed,
+            vec![
+                Push::Class(ClientClass::Pubsub),
+                Push::Frame(Frame::Array(vec![
+                    Frame::Bulk(Bytes::from("message")),
+                    Frame::Bulk(Bytes::from("news")),
+                    Frame::Bulk(Bytes::from("hi")),
+                ])),
+                Push::Class(ClientClass::Normal),
+            ]
+        );
+    }
+}
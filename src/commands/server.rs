@@ -0,0 +1,572 @@
+//! Commands on the keyspace as a whole, and INFO and MEMORY about the
+//! server.
+
+use bytes::Bytes;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+
+use super::{error, lossy, ok, parse_int, syntax_error, wrong_arity, Command, Scan};
+use crate::client::{ClientClass, ClientId};
+use crate::db::{Db, Value, TTL_BUCKETS};
+use crate::keyspace::Keyspace;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "dbsize",
+        arity: 1,
+        subcommands: false,
+        handler: dbsize,
+    },
+    Command {
+        name: "swapdb",
+        arity: 3,
+        subcommands: false,
+        handler: swapdb,
+    },
+    Command {
+        name: "flushdb",
+        arity: -1,
+        subcommands: false,
+        handler: flush,
+    },
+    Command {
+        name: "flushall",
+        arity: -1,
+        subcommands: false,
+        handler: flush,
+    },
+    Command {
+        name: "info",
+        arity: -1,
+        subcommands: false,
+        handler: info,
+    },
+    Command {
+        name: "memory",
+        arity: -2,
+        subcommands: true,
+        handler: memory,
+    },
+];
+
+/// DBSIZE
+///
+/// Keys that have expired but haven't been removed yet are counted, as in
+/// Redis.
+fn dbsize(ks: &mut Keyspace, _: ClientId, _: &[Bytes]) -> Frame {
+    Frame::Integer(ks.db().len() as i64)
+}
+
+/// SWAPDB index1 index2
+///
+/// Clients keep their selected database number, so each one sees the other
+/// database's keys from then on.
+fn swapdb(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let a = match ks.db_index(&args[1]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let b = match ks.db_index(&args[2]) {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+    ks.dbs.swap(a, b);
+    ks.blocked.signal_db(a);
+    ks.blocked.signal_db(b);
+    ok()
+}
+
+/// FLUSHDB [ASYNC | SYNC] and FLUSHALL [ASYNC | SYNC]
+///
+/// FLUSHDB empties the selected database, FLUSHALL every one of them.
+///
+/// Either way the keys are gone before the reply. ASYNC only moves freeing
+/// their memory to the lazyfree thread.
+fn flush(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let lazy = match args.get(1).map(|arg| arg.to_ascii_uppercase()) {
+        None => false,
+        Some(ref opt) if args.len() == 2 && &opt[..] == b"ASYNC" => true,
+        Some(ref opt) if args.len() == 2 && &opt[..] == b"SYNC" => false,
+        Some(_) => return syntax_error(),
+    };
+
+    ks.invalidate(None);
+    let old = if args[0].eq_ignore_ascii_case(b"flushall") {
+        let empty = ks.dbs.iter().map(|_| Db::default()).collect();
+        std::mem::replace(&mut ks.dbs, empty)
+    } else {
+        vec![std::mem::take(ks.db())]
+    };
+    if lazy {
+        ks.lazyfree.free(old);
+    }
+    ok()
+}
+
+/// INFO [section [section ...]] [JSON]
+///
+/// The clients, stats, keyspace and expiry sections exist so far. With no
+/// section, or "default", "all" or "everything", replies with every one;
+/// sections it doesn't know are left out, as in Redis. JSON replies with an
+/// object of sections by lowercase name instead, each an object of its
+/// fields, for tools that would rather not parse the text.
+fn info(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let mut wanted: Vec<String> = args[1..]
+        .iter()
+        .map(|arg| lossy(arg).to_ascii_lowercase())
+        .collect();
+    let json = wanted.iter().any(|arg| arg == "json");
+    wanted.retain(|arg| arg != "json");
+    let all = wanted.is_empty()
+        || wanted
+            .iter()
+            .any(|section| matches!(section.as_str(), "default" | "all" | "everything"));
+    let sections = info_sections(ks).into_iter().filter(|(name, _)| {
+        all || wanted
+            .iter()
+            .any(|section| section.eq_ignore_ascii_case(name))
+    });
+
+    let mut text = String::new();
+    if json {
+        let sections: Vec<String> = sections
+            .map(|(name, fields)| {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| format!("\"{}\":{}", field, value.json()))
+                    .collect();
+                format!("\"{}\":{{{}}}", name.to_ascii_lowercase(), fields.join(","))
+            })
+            .collect();
+        text = format!("{{{}}}", sections.join(","));
+    } else {
+        for (name, fields) in sections {
+            if !text.is_empty() {
+                text.push_str("\r\n");
+            }
+            text.push_str(&format!("# {}\r\n", name));
+            for (field, value) in fields {
+                text.push_str(&format!("{}:{}\r\n", field, value.text()));
+            }
+        }
+    }
+    Frame::Bulk(text.into())
+}
+
+/// An INFO field's value: a number, or for the lines about each database,
+/// several named numbers.
+enum InfoValue {
+    Number(u64),
+    Named(Vec<(String, u64)>),
+}
+
+impl InfoValue {
+    /// As INFO writes it: the number, or `name=n` pairs separated by
+    /// commas.
+    fn text(&self) -> String {
+        match *self {
+            InfoValue::Number(n) => n.to_string(),
+            InfoValue::Named(ref fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, n)| format!("{}={}", name, n))
+                    .collect();
+                fields.join(",")
+            }
+        }
+    }
+
+    fn json(&self) -> String {
+        match *self {
+            InfoValue::Number(n) => n.to_string(),
+            InfoValue::Named(ref fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, n)| format!("\"{}\":{}", name, n))
+                    .collect();
+                format!("{{{}}}", fields.join(","))
+            }
+        }
+    }
+}
+
+/// Every INFO section, by name, with its fields and their values.
+fn info_sections(ks: &Keyspace) -> Vec<(&'static str, Vec<(String, InfoValue)>)> {
+    let number = |name: &str, n: usize| (name.to_string(), InfoValue::Number(n as u64));
+    let pubsub_clients = ks
+        .clients
+        .values()
+        .filter(|info| info.class == ClientClass::Pubsub)
+        .count();
+
+    // Like Redis, only the databases with keys get a line.
+    let mut keyspace = Vec::new();
+    let mut expiry = Vec::new();
+    for (i, db) in ks.dbs.iter().enumerate().filter(|(_, db)| db.len() > 0) {
+        let ttls = db.ttl_stats();
+        keyspace.push((
+            format!("db{}", i),
+            InfoValue::Named(vec![
+                ("keys".to_string(), db.len() as u64),
+                ("expires".to_string(), ttls.expires as u64),
+                ("avg_ttl".to_string(), ttls.avg_ttl),
+            ]),
+        ));
+        if ttls.expires > 0 {
+            let buckets = TTL_BUCKETS
+                .iter()
+                .map(|(name, _)| format!("under_{}", name))
+                .chain(Some(format!(
+                    "over_{}",
+                    TTL_BUCKETS[TTL_BUCKETS.len() - 1].0
+                )));
+            expiry.push((
+                format!("db{}", i),
+                InfoValue::Named(
+                    buckets
+                        .zip(ttls.histogram.iter().map(|&n| n as u64))
+                        .collect(),
+                ),
+            ));
+        }
+    }
+
+    vec![
+        (
+            "Clients",
+            vec![
+                number(
+                    "connected_clients",
+                    ks.stats.connected_clients.load(Ordering::Relaxed),
+                ),
+                number("blocked_clients", ks.blocked.len()),
+                number("pubsub_clients", pubsub_clients),
+            ],
+        ),
+        (
+            "Stats",
+            vec![number(
+                "rejected_connections",
+                ks.stats.rejected_connections.load(Ordering::Relaxed),
+            )],
+        ),
+        ("Keyspace", keyspace),
+        ("Expiry", expiry),
+    ]
+}
+
+/// How many elements MEMORY USAGE measures of a collection unless told.
+const MEMORY_SAMPLES: usize = 5;
+
+const MEMORY_HELP: &[&str] = &[
+    "MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "USAGE <key> [SAMPLES <count>]",
+    "    Return memory in bytes used by <key> and its value. Nested values are",
+    "    sampled up to <count> times (default: 5, 0 means sample all).",
+    "BIGKEYS <cursor> [MATCH <pattern>] [COUNT <count>]",
+    "    Scan the keyspace from <cursor> like SCAN, and return the next cursor",
+    "    with the biggest key of each type found, by size and by memory.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// MEMORY USAGE key [SAMPLES count] | BIGKEYS cursor [MATCH pattern]
+///   [COUNT count] | HELP
+///
+/// Memory is `Value::memory_usage`'s estimate, not what the allocator
+/// handed out. BIGKEYS walks the keyspace a SCAN step at a time, so a big
+/// database never holds up other clients, and reports for each type the
+/// key it saw with the most elements (or for strings, bytes) and the one
+/// taking the most memory. As with SCAN, the caller carries on from the
+/// cursor until it is 0, keeping the biggest of what each call reports.
+fn memory(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let sub = lossy(&args[1]).to_ascii_lowercase();
+    match (sub.as_str(), args.len()) {
+        ("usage", 3) | ("usage", 5) => {
+            let samples = match args.get(3) {
+                None => MEMORY_SAMPLES,
+                Some(arg) if arg.eq_ignore_ascii_case(b"SAMPLES") => match parse_int(&args[4]) {
+                    Ok(n) if n >= 0 => n as usize,
+                    Ok(_) => return error("ERR SAMPLES count must be >= 0"),
+                    Err(e) => return e,
+                },
+                Some(_) => return syntax_error(),
+            };
+            match ks.db().get(&args[2]) {
+                Some(value) => Frame::Integer((args[2].len() + value.memory_usage(samples)) as i64),
+                None => Frame::Null,
+            }
+        }
+        ("bigkeys", n) if n >= 3 => bigkeys(ks, &args[2..]),
+        ("help", 2) => Frame::Array(
+            MEMORY_HELP
+                .iter()
+                .map(|line| Frame::Simple(line.to_string()))
+                .collect(),
+        ),
+        ("usage", _) | ("bigkeys", _) | ("help", _) => wrong_arity(&format!("memory|{}", sub)),
+        _ => error(format!(
+            "ERR unknown subcommand '{}'. Try MEMORY HELP.",
+            lossy(&args[1])
+        )),
+    }
+}
+
+/// The biggest keys of one type a BIGKEYS step came across.
+struct Biggest {
+    by_size: (Bytes, usize),
+    by_memory: (Bytes, usize),
+}
+
+/// MEMORY BIGKEYS, from the cursor on. Replies with the next cursor, then
+/// for each type found, ordered by name: the type, its biggest key and
+/// that key's size, then the key using the most memory and how much.
+fn bigkeys(ks: &mut Keyspace, args: &[Bytes]) -> Frame {
+    let scan = match Scan::parse("bigkeys", args) {
+        Ok(scan) => scan,
+        Err(e) => return e,
+    };
+    let mut biggest: BTreeMap<&'static str, Biggest> = BTreeMap::new();
+    let cursor = scan.run(|cursor| {
+        let mut visited = 0;
+        let next = ks.db().scan(cursor, |key, value: &Value| {
+            visited += 1;
+            if !scan.matches(key) {
+                return;
+            }
+            let size = value.size();
+            let memory = key.len() + value.memory_usage(MEMORY_SAMPLES);
+            let entry = biggest.entry(value.type_name()).or_insert_with(|| Biggest {
+                by_size: (key.clone(), size),
+                by_memory: (key.clone(), memory),
+            });
+            if size > entry.by_size.1 {
+                entry.by_size = (key.clone(), size);
+            }
+            if memory > entry.by_memory.1 {
+                entry.by_memory = (key.clone(), memory);
+            }
+        });
+        (next, visited)
+    });
+    let found = biggest
+        .into_iter()
+        .map(|(type_name, biggest)| {
+            Frame::Array(vec![
+                Frame::Bulk(type_name.into()),
+                Frame::Bulk(biggest.by_size.0),
+                Frame::Integer(biggest.by_size.1 as i64),
+                Frame::Bulk(biggest.by_memory.0),
+                Frame::Integer(biggest.by_memory.1 as i64),
+            ])
+        })
+        .collect();
+    Scan::reply(cursor, found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::Stats;
+
+    #[test]
+    fn info_reports_connection_stats() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        Stats::incr(&ks.stats.connected_clients);
+        Stats::incr(&ks.stats.rejected_connections);
+        Stats::incr(&ks.stats.rejected_connections);
+
+        assert_eq!(
+            ks.command(client, &["info", "stats"]),
+            Frame::Bulk(Bytes::from("# Stats\r\nrejected_connections:2\r\n"))
+        );
+        assert_eq!(
+            ks.command(client, &["info"]),
+            Frame::Bulk(Bytes::from(
+                "# Clients\r\nconnected_clients:1\r\nblocked_clients:0\r\npubsub_clients:0\r\n\r\n# Stats\r\nrejected_connections:2\r\n\r\n# Keyspace\r\n\r\n# Expiry\r\n"
+            ))
+        );
+        assert_eq!(
+            ks.command(client, &["info", "nosuch"]),
+            Frame::Bulk(Bytes::new())
+        );
+        assert_eq!(
+            ks.command(client, &["info", "json"]),
+            Frame::Bulk(Bytes::from(
+                r#"{"clients":{"connected_clients":1,"blocked_clients":0,"pubsub_clients":0},"stats":{"rejected_connections":2},"keyspace":{},"expiry":{}}"#
+            ))
+        );
+        assert_eq!(
+            ks.command(client, &["info", "JSON", "stats"]),
+            Frame::Bulk(Bytes::from(r#"{"stats":{"rejected_connections":2}}"#))
+        );
+    }
+
+    #[test]
+    fn info_reports_when_keys_expire() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        ks.command(client, &["set", "forever", "v"]);
+        ks.command(client, &["set", "soon", "v", "px", "500"]);
+        ks.command(client, &["set", "later", "v", "ex", "7200"]);
+        ks.command(client, &["select", "3"]);
+        ks.command(client, &["set", "k", "v"]);
+
+        let text = match ks.command(client, &["info", "keyspace", "expiry"]) {
+            Frame::Bulk(text) => lossy(&text),
+            other => panic!("not a bulk string: {:?}", other),
+        };
+        let lines: Vec<&str> = text.split("\r\n").collect();
+        assert_eq!(lines[0], "# Keyspace");
+        assert!(lines[1].starts_with("db0:keys=3,expires=2,avg_ttl="));
+        assert_eq!(lines[2], "db3:keys=1,expires=0,avg_ttl=0");
+        assert_eq!(lines[4], "# Expiry");
+        assert_eq!(
+            lines[5],
+            "db0:under_1s=1,under_10s=0,under_1m=0,under_10m=0,under_1h=0,under_1d=1,over_1d=0"
+        );
+        assert_eq!(lines[6], "");
+
+        assert_eq!(
+            ks.command(client, &["info", "expiry", "json"]),
+            Frame::Bulk(Bytes::from(
+                r#"{"expiry":{"db0":{"under_1s":1,"under_10s":0,"under_1m":0,"under_10m":0,"under_1h":0,"under_1d":1,"over_1d":0}}}"#
+            ))
+        );
+    }
+
+    #[test]
+    fn ttl_histogram_is_estimated_for_many_keys() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        for i in 0..4000 {
+            let ttl = if i % 4 == 0 { "30" } else { "3000" };
+            ks.command(client, &["set", &format!("k{}", i), "v", "ex", ttl]);
+        }
+        let stats = ks.dbs[0].ttl_stats();
+        assert_eq!(stats.expires, 4000);
+        // A quarter under a minute, the rest under an hour, give or take
+        // the sample.
+        assert!(
+            (700..1300).contains(&stats.histogram[2]),
+            "{:?}",
+            stats.histogram
+        );
+        assert!(
+            (2700..3300).contains(&stats.histogram[4]),
+            "{:?}",
+            stats.histogram
+        );
+    }
+
+    #[test]
+    fn bigkeys_finds_the_biggest_of_each_type() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        let long = "x".repeat(100);
+        ks.command(client, &["set", "short", "abc"]);
+        ks.command(client, &["set", "long", &long]);
+        ks.command(client, &["rpush", "few", "a", "b", "c"]);
+        ks.command(client, &["rpush", "many", "a", "b", "c", "d", "e"]);
+        // Fewer elements, but they take up more room.
+        ks.command(client, &["rpush", "wide", &long, &long]);
+        for i in 0..100 {
+            ks.command(client, &["set", &format!("filler{}", i), "1"]);
+        }
+        let usage =
+            |ks: &mut Keyspace, key: &str| match ks.command(client, &["memory", "usage", key]) {
+                Frame::Integer(n) => n,
+                other => panic!("not an integer: {:?}", other),
+            };
+        assert!(usage(&mut ks, "wide") > usage(&mut ks, "many"));
+        assert_eq!(
+            ks.command(client, &["memory", "usage", "nosuch"]),
+            Frame::Null
+        );
+
+        let expected = Frame::Array(vec![
+            Frame::Array(vec![
+                Frame::Bulk("list".into()),
+                Frame::Bulk("many".into()),
+                Frame::Integer(5),
+                Frame::Bulk("wide".into()),
+                Frame::Integer(usage(&mut ks, "wide")),
+            ]),
+            Frame::Array(vec![
+                Frame::Bulk("string".into()),
+                Frame::Bulk("long".into()),
+                Frame::Integer(100),
+                Frame::Bulk("long".into()),
+                Frame::Integer(usage(&mut ks, "long")),
+            ]),
+        ]);
+
+        // A step at a time, keeping the biggest seen so far, as a client
+        // would.
+        let mut biggest: Vec<Vec<Frame>> = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let (next, found) =
+                match ks.command(client, &["memory", "bigkeys", &cursor, "count", "5"]) {
+                    Frame::Array(mut reply) => match (reply.remove(0), reply.remove(0)) {
+                        (Frame::Bulk(next), Frame::Array(found)) => (lossy(&next), found),
+                        other => panic!("not a cursor and results: {:?}", other),
+                    },
+                    other => panic!("not an array: {:?}", other),
+                };
+            for found in found {
+                let found = match found {
+                    Frame::Array(found) => found,
+                    other => panic!("not an array: {:?}", other),
+                };
+                let int = |frame: &Frame| match *frame {
+                    Frame::Integer(n) => n,
+                    _ => panic!("not an integer"),
+                };
+                match biggest.iter_mut().find(|seen| seen[0] == found[0]) {
+                    Some(seen) => {
+                        if int(&found[2]) > int(&seen[2]) {
+                            seen[1] = found[1].clone();
+                            seen[2] = found[2].clone();
+                        }
+                        if int(&found[4]) > int(&seen[4]) {
+                            seen[3] = found[3].clone();
+                            seen[4] = found[4].clone();
+                        }
+                    }
+                    None => biggest.push(found),
+                }
+            }
+            if next == "0" {
+                break;
+            }
+            cursor = next;
+        }
+        biggest.sort_by_key(|found| format!("{:?}", found[0]));
+        assert_eq!(
+            Frame::Array(biggest.into_iter().map(Frame::Array).collect()),
+            expected
+        );
+
+        // All at once, filtered by MATCH.
+        assert_eq!(
+            ks.command(
+                client,
+                &["memory", "bigkeys", "0", "match", "s*", "count", "1000"]
+            ),
+            Frame::Array(vec![
+                Frame::Bulk("0".into()),
+                Frame::Array(vec![Frame::Array(vec![
+                    Frame::Bulk("string".into()),
+                    Frame::Bulk("short".into()),
+                    Frame::Integer(3),
+                    Frame::Bulk("short".into()),
+                    Frame::Integer(usage(&mut ks, "short")),
+                ])]),
+            ])
+        );
+    }
+}
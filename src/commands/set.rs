@@ -0,0 +1,447 @@
+//! Commands on set values.
+//!
+//! A set is a `Dict` with no values, so SSCAN walks it with the same cursors
+//! SCAN uses on the keyspace. A set whose last member is removed is
+//! deleted.
+
+use bytes::Bytes;
+use rand::seq::index;
+
+use std::collections::HashSet;
+
+use super::{error, parse_int, syntax_error, wrong_arity, wrong_type, Command, Scan};
+use crate::client::ClientId;
+use crate::db::Value;
+use crate::dict::Dict;
+use crate::keyspace::Keyspace;
+use crate::notify::Events;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "sadd",
+        arity: -3,
+        subcommands: false,
+        handler: sadd,
+    },
+    Command {
+        name: "srem",
+        arity: -3,
+        subcommands: false,
+        handler: srem,
+    },
+    Command {
+        name: "sismember",
+        arity: 3,
+        subcommands: false,
+        handler: sismember,
+    },
+    Command {
+        name: "smismember",
+        arity: -3,
+        subcommands: false,
+        handler: smismember,
+    },
+    Command {
+        name: "smembers",
+        arity: 2,
+        subcommands: false,
+        handler: smembers,
+    },
+    Command {
+        name: "scard",
+        arity: 2,
+        subcommands: false,
+        handler: scard,
+    },
+    Command {
+        name: "spop",
+        arity: -2,
+        subcommands: false,
+        handler: spop,
+    },
+    Command {
+        name: "srandmember",
+        arity: -2,
+        subcommands: false,
+        handler: srandmember,
+    },
+    Command {
+        name: "sscan",
+        arity: -3,
+        subcommands: false,
+        handler: sscan,
+    },
+    Command {
+        name: "sinter",
+        arity: -2,
+        subcommands: false,
+        handler: algebra,
+    },
+    Command {
+        name: "sintercard",
+        arity: -3,
+        subcommands: false,
+        handler: sintercard,
+    },
+    Command {
+        name: "sunion",
+        arity: -2,
+        subcommands: false,
+        handler: algebra,
+    },
+    Command {
+        name: "sdiff",
+        arity: -2,
+        subcommands: false,
+        handler: algebra,
+    },
+    Command {
+        name: "sinterstore",
+        arity: -3,
+        subcommands: false,
+        handler: algebra,
+    },
+    Command {
+        name: "sunionstore",
+        arity: -3,
+        subcommands: false,
+        handler: algebra,
+    },
+    Command {
+        name: "sdiffstore",
+        arity: -3,
+        subcommands: false,
+        handler: algebra,
+    },
+];
+
+/// The set at `key`, or `None` if there is no such key. A key holding
+/// another type is an error reply.
+fn set<'a>(ks: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut Dict<()>>, Frame> {
+    match ks.db().get_mut(key) {
+        None => Ok(None),
+        Some(Value::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(wrong_type()),
+    }
+}
+
+/// Like `set`, but a missing key gets a new, empty set.
+fn set_or_new<'a>(ks: &'a mut Keyspace, key: &Bytes) -> Result<&'a mut Dict<()>, Frame> {
+    if !ks.db().contains(key) {
+        ks.db().insert(key.clone(), Value::Set(Dict::default()));
+    }
+    set(ks, key).map(|set| set.unwrap())
+}
+
+/// Deletes `key` if it holds a set that has been emptied.
+fn remove_if_empty(ks: &mut Keyspace, key: &[u8]) {
+    if matches!(ks.db().get(key), Some(Value::Set(set)) if set.is_empty()) {
+        ks.db().remove(key);
+        ks.notify(Events::GENERIC, "del", key);
+    }
+}
+
+fn members(members: Vec<Bytes>) -> Frame {
+    Frame::Array(members.into_iter().map(Frame::Bulk).collect())
+}
+
+/// SADD key member [member ...]
+fn sadd(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let set = match set_or_new(ks, &args[1]) {
+        Ok(set) => set,
+        Err(e) => return e,
+    };
+    let added = args[2..]
+        .iter()
+        .filter(|member| set.insert((*member).clone(), ()).is_none())
+        .count();
+    if added > 0 {
+        ks.notify(Events::SET, "sadd", &args[1]);
+    }
+    Frame::Integer(added as i64)
+}
+
+/// SREM key member [member ...]
+fn srem(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let key = &args[1];
+    let set = match set(ks, key) {
+        Ok(Some(set)) => set,
+        Ok(None) => return Frame::Integer(0),
+        Err(e) => return e,
+    };
+    let removed = args[2..]
+        .iter()
+        .filter(|member| set.remove(member).is_some())
+        .count();
+    if removed > 0 {
+        ks.notify(Events::SET, "srem", key);
+    }
+    remove_if_empty(ks, key);
+    Frame::Integer(removed as i64)
+}
+
+/// SISMEMBER key member
+fn sismember(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match set(ks, &args[1]) {
+        Ok(set) => Frame::Integer(set.is_some_and(|set| set.contains_key(&args[2])) as i64),
+        Err(e) => e,
+    }
+}
+
+/// SMISMEMBER key member [member ...]
+fn smismember(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let set = match set(ks, &args[1]) {
+        Ok(set) => set,
+        Err(e) => return e,
+    };
+    let found = args[2..]
+        .iter()
+        .map(|member| {
+            let found = set.as_ref().is_some_and(|set| set.contains_key(member));
+            Frame::Integer(found as i64)
+        })
+        .collect();
+    Frame::Array(found)
+}
+
+/// SMEMBERS key
+fn smembers(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match set(ks, &args[1]) {
+        Ok(Some(set)) => members(set.keys().cloned().collect()),
+        Ok(None) => Frame::Array(Vec::new()),
+        Err(e) => e,
+    }
+}
+
+/// SCARD key
+fn scard(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match set(ks, &args[1]) {
+        Ok(set) => Frame::Integer(set.map_or(0, |set| set.len()) as i64),
+        Err(e) => e,
+    }
+}
+
+/// Up to `count` distinct members of `set`, picked at random.
+fn sample(set: &Dict<()>, count: usize) -> Vec<Bytes> {
+    if count >= set.len() {
+        return set.keys().cloned().collect();
+    }
+    let all: Vec<&Bytes> = set.keys().collect();
+    index::sample(&mut rand::thread_rng(), all.len(), count)
+        .into_iter()
+        .map(|i| all[i].clone())
+        .collect()
+}
+
+/// SPOP key [count]
+///
+/// Removes and replies with a random member, or nil if there is no set.
+/// With a count, removes up to that many distinct members and replies with
+/// them as an array.
+fn spop(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let count = match args.len() {
+        2 => None,
+        3 => match parse_int(&args[2]) {
+            Ok(n) if n >= 0 => Some(n as usize),
+            Ok(_) => return error("ERR value is out of range, must be positive"),
+            Err(e) => return e,
+        },
+        _ => return wrong_arity("spop"),
+    };
+    let key = &args[1];
+    let set = match set(ks, key) {
+        Ok(Some(set)) => set,
+        Ok(None) if count.is_some() => return Frame::Array(Vec::new()),
+        Ok(None) => return Frame::Null,
+        Err(e) => return e,
+    };
+
+    let popped = match count {
+        Some(count) => sample(set, count),
+        None => vec![set.random().unwrap().0.clone()],
+    };
+    for member in &popped {
+        set.remove(member);
+    }
+    if !popped.is_empty() {
+        ks.notify(Events::SET, "spop", key);
+    }
+    remove_if_empty(ks, key);
+
+    match count {
+        Some(_) => members(popped),
+        None => Frame::Bulk(popped.into_iter().next().unwrap()),
+    }
+}
+
+/// SRANDMEMBER key [count]
+///
+/// Like SPOP without removing anything. A negative count returns exactly
+/// that many members, possibly repeating some.
+fn srandmember(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let count = match args.len() {
+        2 => None,
+        3 => match parse_int(&args[2]) {
+            Ok(n) => Some(n),
+            Err(e) => return e,
+        },
+        _ => return wrong_arity("srandmember"),
+    };
+    let set = match set(ks, &args[1]) {
+        Ok(Some(set)) => set,
+        Ok(None) if count.is_some() => return Frame::Array(Vec::new()),
+        Ok(None) => return Frame::Null,
+        Err(e) => return e,
+    };
+    match count {
+        None => Frame::Bulk(set.random().unwrap().0.clone()),
+        Some(count) if count >= 0 => members(sample(set, count as usize)),
+        Some(count) => members(
+            (0..count.unsigned_abs())
+                .map(|_| set.random().unwrap().0.clone())
+                .collect(),
+        ),
+    }
+}
+
+/// SSCAN key cursor [MATCH pattern] [COUNT count]
+fn sscan(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let scan = match Scan::parse("sscan", &args[2..]) {
+        Ok(scan) => scan,
+        Err(e) => return e,
+    };
+    let set = match set(ks, &args[1]) {
+        Ok(Some(set)) => set,
+        Ok(None) => return Scan::reply(0, Vec::new()),
+        Err(e) => return e,
+    };
+    let mut found = Vec::new();
+    let cursor = scan.run(|cursor| {
+        let mut visited = 0;
+        let next = set.scan(cursor, |member, _| {
+            visited += 1;
+            if scan.matches(member) {
+                found.push(Frame::Bulk(member.clone()));
+            }
+        });
+        (next, visited)
+    });
+    Scan::reply(cursor, found)
+}
+
+/// SINTER, SUNION and SDIFF key [key ...], and the *STORE forms taking a
+/// destination first.
+///
+/// Missing keys count as empty sets. The STORE forms replace whatever the
+/// destination held, delete it if the result is empty, and reply with the
+/// result's size.
+fn algebra(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let command = args[0].to_ascii_lowercase();
+    let store = command.ends_with(b"store");
+    let keys = if store { &args[2..] } else { &args[1..] };
+
+    let mut result: Option<HashSet<Bytes>> = None;
+    for key in keys {
+        let set = match set(ks, key) {
+            Ok(set) => set,
+            Err(e) => return e,
+        };
+        let has = |member: &Bytes| set.as_ref().is_some_and(|set| set.contains_key(member));
+        result = Some(match result {
+            None => set.iter().flat_map(|set| set.keys().cloned()).collect(),
+            Some(mut acc) => {
+                if command.starts_with(b"sunion") {
+                    acc.extend(set.iter().flat_map(|set| set.keys().cloned()));
+                } else if command.starts_with(b"sinter") {
+                    acc.retain(has);
+                } else {
+                    acc.retain(|member| !has(member));
+                }
+                acc
+            }
+        });
+    }
+    let result = result.unwrap_or_default();
+
+    if !store {
+        return members(result.into_iter().collect());
+    }
+    let len = result.len();
+    if len == 0 {
+        if ks.db().remove(&args[1]).is_some() {
+            ks.notify(Events::GENERIC, "del", &args[1]);
+        }
+    } else {
+        let mut set = Dict::default();
+        for member in result {
+            set.insert(member, ());
+        }
+        ks.db().insert(args[1].clone(), Value::Set(set));
+        ks.notify(Events::SET, &String::from_utf8_lossy(&command), &args[1]);
+    }
+    Frame::Integer(len as i64)
+}
+
+/// SINTERCARD numkeys key [key ...] [LIMIT limit]
+///
+/// Replies with the size of the intersection of the sets, without building
+/// it. With a LIMIT other than 0, stops counting once it gets there.
+fn sintercard(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let numkeys = match parse_int(&args[1]) {
+        Ok(n) if n > 0 => n as usize,
+        _ => return error("ERR numkeys should be greater than 0"),
+    };
+    if numkeys > args.len() - 2 {
+        return error("ERR Number of keys can't be greater than number of args");
+    }
+    let (keys, options) = args[2..].split_at(numkeys);
+    let limit = match options {
+        [] => usize::MAX,
+        [option, n] if option.eq_ignore_ascii_case(b"LIMIT") => match parse_int(n) {
+            Ok(n) if n < 0 => return error("ERR LIMIT can't be negative"),
+            Ok(0) => usize::MAX,
+            Ok(n) => n as usize,
+            Err(e) => return e,
+        },
+        _ => return syntax_error(),
+    };
+
+    // Every key is checked for its type, even once one is missing and the
+    // answer is sure to be 0.
+    let mut sizes = Vec::with_capacity(keys.len());
+    let mut missing = false;
+    for key in keys {
+        match set(ks, key) {
+            Ok(Some(set)) => sizes.push((set.len(), key)),
+            Ok(None) => missing = true,
+            Err(e) => return e,
+        }
+    }
+    if missing {
+        return Frame::Integer(0);
+    }
+
+    // Go through the smallest set, looking each member up in the others,
+    // smallest first as they're likeliest to rule it out.
+    sizes.sort_by_key(|&(len, _)| len);
+    let candidates: Vec<Bytes> = set(ks, sizes[0].1)
+        .unwrap()
+        .unwrap()
+        .keys()
+        .cloned()
+        .collect();
+    let mut count = 0;
+    'members: for member in candidates {
+        if count == limit {
+            break;
+        }
+        for &(_, key) in &sizes[1..] {
+            if !matches!(set(ks, key), Ok(Some(set)) if set.contains_key(&member)) {
+                continue 'members;
+            }
+        }
+        count += 1;
+    }
+    Frame::Integer(count as i64)
+}
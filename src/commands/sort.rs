@@ -0,0 +1,240 @@
+//! SORT and SORT_RO.
+//!
+//! BY and GET patterns name other keys to look at for each element: the
+//! first '*' in the pattern is replaced with the element, and a "->field"
+//! after it names a field of the hash at the key that makes. "#" in a GET
+//! is the element itself.
+
+use bytes::Bytes;
+
+use std::cmp::Ordering;
+
+use super::{error, parse_int, syntax_error, wrong_type, Command};
+use crate::client::ClientId;
+use crate::db::Value;
+use crate::keyspace::Keyspace;
+use crate::notify::Events;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "sort",
+        arity: -2,
+        subcommands: false,
+        handler: sort,
+    },
+    Command {
+        name: "sort_ro",
+        arity: -2,
+        subcommands: false,
+        handler: sort,
+    },
+];
+
+/// The value a BY or GET pattern names for `element`, if there is one.
+fn lookup(ks: &mut Keyspace, pattern: &[u8], element: &Bytes) -> Option<Bytes> {
+    if pattern == b"#" {
+        return Some(element.clone());
+    }
+    let star = pattern.iter().position(|&b| b == b'*')?;
+    let arrow = pattern[star + 1..]
+        .windows(2)
+        .position(|w| w == b"->")
+        .map(|i| star + 1 + i)
+        .filter(|&i| i + 2 < pattern.len());
+    let (key_pattern, field) = match arrow {
+        Some(i) => (&pattern[..i], Some(&pattern[i + 2..])),
+        None => (pattern, None),
+    };
+    let mut key = Vec::with_capacity(key_pattern.len() + element.len());
+    key.extend_from_slice(&key_pattern[..star]);
+    key.extend_from_slice(element);
+    key.extend_from_slice(&key_pattern[star + 1..]);
+
+    match (ks.db().get(&key)?, field) {
+        (Value::Hash(hash), Some(field)) => hash.get(field).cloned(),
+        (value, None) => value.as_string(),
+        _ => None,
+    }
+}
+
+/// Parses a weight, or an element sorted by itself, as a number.
+fn parse_weight(bytes: &[u8]) -> Option<f64> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|n| !n.is_nan())
+}
+
+/// What an element is sorted by.
+enum Weight {
+    Number(f64),
+    /// With ALPHA; a missing weight sorts first.
+    Text(Option<Bytes>),
+}
+
+impl Weight {
+    fn cmp(&self, other: &Weight) -> Ordering {
+        match (self, other) {
+            (Weight::Number(a), Weight::Number(b)) => a.partial_cmp(b).unwrap(),
+            (Weight::Text(a), Weight::Text(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+/// SORT key [BY pattern] [LIMIT offset count] [GET pattern [GET pattern
+///   ...]] [ASC | DESC] [ALPHA] [STORE destination], and SORT_RO, which
+///   takes no STORE
+///
+/// Sorts a list, set or sorted set's elements as numbers, or with ALPHA as
+/// strings, and replies with them, or with GET, the values the patterns
+/// name for each, nil where there is none. BY sorts by the values a
+/// pattern names instead; one without a '*' leaves the elements in the
+/// order they're stored. STORE saves the result as a list and replies
+/// with its length.
+fn sort(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let read_only = args[0].eq_ignore_ascii_case(b"sort_ro");
+    let mut by = None;
+    let mut limit = None;
+    let mut get = Vec::new();
+    let mut desc = false;
+    let mut alpha = false;
+    let mut store = None;
+    let mut i = 2;
+    while i < args.len() {
+        let option = args[i].to_ascii_lowercase();
+        let left = args.len() - i - 1;
+        match &option[..] {
+            b"asc" => desc = false,
+            b"desc" => desc = true,
+            b"alpha" => alpha = true,
+            b"limit" if left >= 2 => {
+                limit = match (parse_int(&args[i + 1]), parse_int(&args[i + 2])) {
+                    (Ok(offset), Ok(count)) => Some((offset, count)),
+                    (Err(e), _) | (_, Err(e)) => return e,
+                };
+                i += 2;
+            }
+            b"store" if left >= 1 && !read_only => {
+                store = Some(args[i + 1].clone());
+                i += 1;
+            }
+            b"by" if left >= 1 => {
+                by = Some(args[i + 1].clone());
+                i += 1;
+            }
+            b"get" if left >= 1 => {
+                get.push(args[i + 1].clone());
+                i += 1;
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+
+    // A BY pattern without a '*' can't name a different key per element,
+    // so means not to sort at all.
+    let mut dont_sort = by.as_ref().is_some_and(|by| !by.contains(&b'*'));
+    let mut elements: Vec<Bytes> = match ks.db().get(&args[1]) {
+        None => Vec::new(),
+        Some(Value::List(list)) => list.iter().cloned().collect(),
+        Some(Value::Set(set)) => {
+            // A set's order is arbitrary, so one stored has to be sorted
+            // to come out the same every time.
+            if dont_sort && store.is_some() {
+                dont_sort = false;
+                alpha = true;
+                by = None;
+            }
+            set.keys().cloned().collect()
+        }
+        Some(Value::ZSet(zset)) => zset.iter(0, false).map(|(m, _)| m.clone()).collect(),
+        Some(_) => return wrong_type(),
+    };
+
+    if dont_sort {
+        // Only a sorted set has an order to go backwards through.
+        if desc && matches!(ks.db().get(&args[1]), Some(Value::ZSet(_))) {
+            elements.reverse();
+        }
+    } else {
+        let mut weighted = Vec::with_capacity(elements.len());
+        for element in elements {
+            let weight = match &by {
+                Some(by) => lookup(ks, by, &element),
+                None => Some(element.clone()),
+            };
+            let weight = if alpha {
+                Weight::Text(weight)
+            } else {
+                match weight.as_ref().map(|w| parse_weight(w)) {
+                    None => Weight::Number(0.0),
+                    Some(Some(n)) => Weight::Number(n),
+                    Some(None) => {
+                        return error("ERR One or more scores can't be converted into double")
+                    }
+                }
+            };
+            weighted.push((weight, element));
+        }
+        // Ties go by the elements themselves, so the order is always the
+        // same.
+        weighted.sort_by(|(a, x), (b, y)| {
+            let order = a.cmp(b).then_with(|| x.cmp(y));
+            if desc {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        elements = weighted.into_iter().map(|(_, element)| element).collect();
+    }
+
+    if let Some((offset, count)) = limit {
+        let offset = offset.max(0) as usize;
+        let count = if count < 0 {
+            usize::MAX
+        } else {
+            count as usize
+        };
+        elements = elements.into_iter().skip(offset).take(count).collect();
+    }
+
+    let mut results = Vec::new();
+    for element in &elements {
+        if get.is_empty() {
+            results.push(Some(element.clone()));
+        }
+        for pattern in &get {
+            results.push(lookup(ks, pattern, element));
+        }
+    }
+
+    let destination = match store {
+        Some(destination) => destination,
+        None => {
+            return Frame::Array(
+                results
+                    .into_iter()
+                    .map(|result| result.map_or(Frame::Null, Frame::Bulk))
+                    .collect(),
+            )
+        }
+    };
+    let len = results.len();
+    if len == 0 {
+        if ks.db().remove(&destination).is_some() {
+            ks.notify(Events::GENERIC, "del", &destination);
+        }
+    } else {
+        let list = results
+            .into_iter()
+            .map(|result| result.unwrap_or_default())
+            .collect();
+        ks.db().insert(destination.clone(), Value::List(list));
+        ks.signal_ready(&destination);
+        ks.notify(Events::LIST, "sortstore", &destination);
+    }
+    Frame::Integer(len as i64)
+}
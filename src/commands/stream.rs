@@ -0,0 +1,1874 @@
+//! Commands on stream values.
+//!
+//! See `crate::stream` for how they are stored. Unlike the other
+//! collections, a stream is not deleted when its last entry goes: its last
+//! ID still matters to the entries added after.
+
+use bytes::Bytes;
+
+use super::{error, lossy, ok, parse_int, syntax_error, wrong_arity, wrong_type, Command};
+use crate::client::ClientId;
+use crate::db::{now_ms, Value};
+use crate::keyspace::Keyspace;
+use crate::notify::Events;
+use crate::resp::Frame;
+use crate::stream::{Consumer, Fields, Group, Stream, StreamId, Trim, NODE_ENTRIES};
+
+use std::ops::Bound;
+use std::time::Duration;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "xadd",
+        arity: -5,
+        subcommands: false,
+        handler: xadd,
+    },
+    Command {
+        name: "xtrim",
+        arity: -4,
+        subcommands: false,
+        handler: xtrim,
+    },
+    Command {
+        name: "xlen",
+        arity: 2,
+        subcommands: false,
+        handler: xlen,
+    },
+    Command {
+        name: "xrange",
+        arity: -4,
+        subcommands: false,
+        handler: xrange,
+    },
+    Command {
+        name: "xrevrange",
+        arity: -4,
+        subcommands: false,
+        handler: xrange,
+    },
+    Command {
+        name: "xread",
+        arity: -4,
+        subcommands: false,
+        handler: xread,
+    },
+    Command {
+        name: "xgroup",
+        arity: -2,
+        subcommands: true,
+        handler: xgroup,
+    },
+    Command {
+        name: "xreadgroup",
+        arity: -7,
+        subcommands: false,
+        handler: xreadgroup,
+    },
+    Command {
+        name: "xack",
+        arity: -4,
+        subcommands: false,
+        handler: xack,
+    },
+    Command {
+        name: "xpending",
+        arity: -3,
+        subcommands: false,
+        handler: xpending,
+    },
+    Command {
+        name: "xclaim",
+        arity: -6,
+        subcommands: false,
+        handler: xclaim,
+    },
+    Command {
+        name: "xautoclaim",
+        arity: -6,
+        subcommands: false,
+        handler: xautoclaim,
+    },
+    Command {
+        name: "xinfo",
+        arity: -2,
+        subcommands: true,
+        handler: xinfo,
+    },
+];
+
+/// The stream at `key`, or `None` if there is no such key. A key holding
+/// another type is an error reply.
+fn stream<'a>(ks: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut Stream>, Frame> {
+    match ks.db().get_mut(key) {
+        None => Ok(None),
+        Some(Value::Stream(stream)) => Ok(Some(stream)),
+        Some(_) => Err(wrong_type()),
+    }
+}
+
+fn invalid_id() -> Frame {
+    error("ERR Invalid stream ID specified as stream command argument")
+}
+
+/// Parses an ID: "ms-seq", or "ms" alone with `missing_seq` for the
+/// sequence number. "-" and "+" are the smallest and greatest IDs.
+fn parse_id(arg: &[u8], missing_seq: u64) -> Result<StreamId, Frame> {
+    match arg {
+        b"-" => return Ok(StreamId::MIN),
+        b"+" => return Ok(StreamId::MAX),
+        _ => {}
+    }
+    let text = std::str::from_utf8(arg).map_err(|_| invalid_id())?;
+    let number = |s: &str| {
+        if s.bytes().all(|b| b.is_ascii_digit()) {
+            s.parse::<u64>().map_err(|_| invalid_id())
+        } else {
+            Err(invalid_id())
+        }
+    };
+    match text.split_once('-') {
+        Some((ms, seq)) => Ok(StreamId {
+            ms: number(ms)?,
+            seq: number(seq)?,
+        }),
+        None => Ok(StreamId {
+            ms: number(text)?,
+            seq: missing_seq,
+        }),
+    }
+}
+
+/// Parses the start or end of an XRANGE: an ID, possibly incomplete, or
+/// with a '(' prefix, one the range stops short of.
+fn parse_bound(arg: &[u8], start: bool) -> Result<StreamId, Frame> {
+    let missing_seq = if start { 0 } else { u64::MAX };
+    if arg.first() != Some(&b'(') {
+        return parse_id(arg, missing_seq);
+    }
+    let id = match &arg[1..] {
+        b"-" | b"+" => return Err(invalid_id()),
+        id => parse_id(id, missing_seq)?,
+    };
+    if start {
+        id.next()
+            .ok_or_else(|| error("ERR invalid start ID for the interval"))
+    } else {
+        id.prev()
+            .ok_or_else(|| error("ERR invalid end ID for the interval"))
+    }
+}
+
+fn id_reply(id: StreamId) -> Frame {
+    Frame::Bulk(id.to_string().into())
+}
+
+/// An entry as the read commands reply with one: its ID, then its fields
+/// and values in one flat array.
+fn entry_reply(id: &StreamId, fields: &Fields) -> Frame {
+    let fields = fields
+        .iter()
+        .flat_map(|(field, value)| vec![Frame::Bulk(field.clone()), Frame::Bulk(value.clone())])
+        .collect();
+    Frame::Array(vec![id_reply(*id), Frame::Array(fields)])
+}
+
+/// The ID XADD is asked to give a new entry.
+enum NewId {
+    /// "*": the current time, or if the last entry's is later, that.
+    Auto,
+    /// "ms-*": the next sequence number within a given millisecond.
+    Seq(u64),
+    Exact(StreamId),
+}
+
+/// How XTRIM and XADD are asked to trim a stream:
+/// <MAXLEN | MINID> [= | ~] threshold [LIMIT count]
+struct TrimArgs {
+    to: Trim,
+    /// With "~", trimming may leave some entries it could have removed,
+    /// as Redis does to only remove whole nodes.
+    approx: bool,
+    limit: usize,
+}
+
+impl TrimArgs {
+    /// Parses the arguments starting at `args[*i]`, which is MAXLEN or
+    /// MINID, and moves `i` past them.
+    fn parse(args: &[Bytes], i: &mut usize) -> Result<TrimArgs, Frame> {
+        let maxlen = args[*i].eq_ignore_ascii_case(b"MAXLEN");
+        *i += 1;
+        let approx = args.get(*i).is_some_and(|arg| &arg[..] == b"~");
+        if args
+            .get(*i)
+            .is_some_and(|arg| &arg[..] == b"~" || &arg[..] == b"=")
+        {
+            *i += 1;
+        }
+        let threshold = args.get(*i).ok_or_else(syntax_error)?;
+        *i += 1;
+        let to = if maxlen {
+            match parse_int(threshold)? {
+                n if n < 0 => return Err(error("ERR The MAXLEN argument must be >= 0.")),
+                n => Trim::MaxLen(n as usize),
+            }
+        } else {
+            Trim::MinId(parse_id(threshold, 0)?)
+        };
+
+        // Approximate trimming is bounded by default, to keep commands on
+        // large streams from taking too long.
+        let mut limit = if approx {
+            NODE_ENTRIES * 100
+        } else {
+            usize::MAX
+        };
+        if args
+            .get(*i)
+            .is_some_and(|arg| arg.eq_ignore_ascii_case(b"LIMIT"))
+        {
+            let n = parse_int(args.get(*i + 1).ok_or_else(syntax_error)?)?;
+            if n < 0 {
+                return Err(error("ERR The LIMIT argument must be >= 0."));
+            }
+            if !approx {
+                return Err(error(
+                    "ERR syntax error, LIMIT cannot be used without the special ~ option",
+                ));
+            }
+            limit = if n == 0 { usize::MAX } else { n as usize };
+            *i += 2;
+        }
+        Ok(TrimArgs { to, approx, limit })
+    }
+}
+
+/// XADD key [NOMKSTREAM] [<MAXLEN | MINID> [= | ~] threshold [LIMIT count]]
+///   <* | id> field value [field value ...]
+///
+/// Replies with the new entry's ID, or with NOMKSTREAM, nil if there is no
+/// stream to add it to. Trims the stream after adding to it, like XTRIM.
+fn xadd(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let mut no_create = false;
+    let mut trim = None;
+    let mut i = 2;
+    while i < args.len() {
+        if args[i].eq_ignore_ascii_case(b"NOMKSTREAM") {
+            no_create = true;
+            i += 1;
+        } else if args[i].eq_ignore_ascii_case(b"MAXLEN") || args[i].eq_ignore_ascii_case(b"MINID")
+        {
+            if trim.is_some() {
+                return error(
+                    "ERR syntax error, MAXLEN and MINID options at the same time are not compatible",
+                );
+            }
+            trim = match TrimArgs::parse(args, &mut i) {
+                Ok(trim) => Some(trim),
+                Err(e) => return e,
+            };
+        } else {
+            break;
+        }
+    }
+    let fields = match args.get(i + 1..) {
+        Some(fields) if !fields.is_empty() && fields.len().is_multiple_of(2) => fields,
+        _ => return wrong_arity("xadd"),
+    };
+    let new_id = match &args[i][..] {
+        b"*" => NewId::Auto,
+        id => match id.strip_suffix(b"-*") {
+            Some(ms) => match parse_id(ms, 0) {
+                Ok(id) if !ms.contains(&b'-') => NewId::Seq(id.ms),
+                _ => return invalid_id(),
+            },
+            None => match parse_id(id, 0) {
+                Ok(StreamId::MIN) => {
+                    return error("ERR The ID specified in XADD must be greater than 0-0")
+                }
+                Ok(id) => NewId::Exact(id),
+                Err(e) => return e,
+            },
+        },
+    };
+
+    let key = &args[1];
+    match stream(ks, key) {
+        Ok(None) if no_create => return Frame::Null,
+        Ok(None) => {
+            ks.db()
+                .insert(key.clone(), Value::Stream(Stream::default()));
+        }
+        Ok(Some(_)) => {}
+        Err(e) => return e,
+    }
+    let stream = stream(ks, key).unwrap().unwrap();
+
+    let last = stream.last_id;
+    if last == StreamId::MAX {
+        return error(
+            "ERR The stream has exhausted the last possible ID, unable to add more items",
+        );
+    }
+    let now = now_ms();
+    let id =
+        match new_id {
+            NewId::Auto if now > last.ms => StreamId { ms: now, seq: 0 },
+            NewId::Auto => last.next().unwrap(),
+            NewId::Seq(ms) if ms == last.ms && last.seq < u64::MAX => last.next().unwrap(),
+            NewId::Seq(ms) if ms > last.ms => StreamId { ms, seq: 0 },
+            NewId::Exact(id) if id > last => id,
+            _ => return error(
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item",
+            ),
+        };
+    let fields = fields
+        .chunks(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect();
+    stream.add(id, fields);
+    let trimmed = match trim {
+        Some(trim) => stream.trim(trim.to, trim.approx, trim.limit),
+        None => 0,
+    };
+    ks.signal_ready(key);
+    ks.notify(Events::STREAM, "xadd", key);
+    if trimmed > 0 {
+        ks.notify(Events::STREAM, "xtrim", key);
+    }
+    id_reply(id)
+}
+
+/// XTRIM key <MAXLEN | MINID> [= | ~] threshold [LIMIT count]
+///
+/// Removes the oldest entries until there are at most MAXLEN, or none
+/// older than MINID, and replies with how many it removed. With "~", only
+/// removes entries in whole nodes' worth, so may leave some, but never
+/// more than LIMIT of them.
+fn xtrim(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let mut i = 2;
+    if !args[i].eq_ignore_ascii_case(b"MAXLEN") && !args[i].eq_ignore_ascii_case(b"MINID") {
+        return syntax_error();
+    }
+    let trim = match TrimArgs::parse(args, &mut i) {
+        Ok(trim) => trim,
+        Err(e) => return e,
+    };
+    if i < args.len() {
+        return syntax_error();
+    }
+    let removed = match stream(ks, &args[1]) {
+        Ok(Some(stream)) => stream.trim(trim.to, trim.approx, trim.limit),
+        Ok(None) => 0,
+        Err(e) => return e,
+    };
+    if removed > 0 {
+        ks.notify(Events::STREAM, "xtrim", &args[1]);
+    }
+    Frame::Integer(removed as i64)
+}
+
+/// XLEN key
+fn xlen(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match stream(ks, &args[1]) {
+        Ok(stream) => Frame::Integer(stream.map_or(0, |stream| stream.len()) as i64),
+        Err(e) => e,
+    }
+}
+
+/// XRANGE key start end [COUNT count] and XREVRANGE key end start
+/// [COUNT count]
+///
+/// Replies with the entries between two IDs inclusive, in order or with
+/// XREVRANGE, newest first. An incomplete start ID is the first of its
+/// millisecond and an incomplete end ID the last.
+fn xrange(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let rev = args[0].eq_ignore_ascii_case(b"xrevrange");
+    let (start, end) = if rev {
+        (&args[3], &args[2])
+    } else {
+        (&args[2], &args[3])
+    };
+    let (start, end) = match (parse_bound(start, true), parse_bound(end, false)) {
+        (Ok(start), Ok(end)) => (start, end),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    let count = match args.len() {
+        4 => usize::MAX,
+        6 if args[4].eq_ignore_ascii_case(b"COUNT") => match parse_int(&args[5]) {
+            Ok(n) => n.max(0) as usize,
+            Err(e) => return e,
+        },
+        _ => return syntax_error(),
+    };
+
+    let stream = match stream(ks, &args[1]) {
+        Ok(Some(stream)) => stream,
+        Ok(None) => return Frame::Array(Vec::new()),
+        Err(e) => return e,
+    };
+    let entries = stream.range(start, end);
+    let entries: Vec<Frame> = if rev {
+        entries
+            .rev()
+            .take(count)
+            .map(|(id, fields)| entry_reply(id, fields))
+            .collect()
+    } else {
+        entries
+            .take(count)
+            .map(|(id, fields)| entry_reply(id, fields))
+            .collect()
+    };
+    Frame::Array(entries)
+}
+
+/// Where XREAD and XREADGROUP read a stream from.
+#[derive(Clone, Copy)]
+enum ReadFrom {
+    /// After an ID; for XREADGROUP, in the consumer's pending entries.
+    After(StreamId),
+    /// "$": after the last ID in the stream.
+    Last,
+    /// ">": for XREADGROUP, the entries no consumer in the group has been
+    /// given yet.
+    New,
+}
+
+/// The arguments XREAD and XREADGROUP share, from the options on:
+/// [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key [key ...]
+/// id [id ...]
+struct Read<'a> {
+    count: usize,
+    /// Whether to block, and for how long; `None` is for ever.
+    block: Option<Option<Duration>>,
+    noack: bool,
+    keys: &'a [Bytes],
+    from: Vec<ReadFrom>,
+}
+
+impl<'a> Read<'a> {
+    fn parse(command: &str, args: &'a [Bytes]) -> Result<Read<'a>, Frame> {
+        let group = command == "xreadgroup";
+        let mut count = usize::MAX;
+        let mut block = None;
+        let mut noack = false;
+        let mut i = 0;
+        loop {
+            match args
+                .get(i)
+                .map(|arg| lossy(arg).to_ascii_lowercase())
+                .as_deref()
+            {
+                Some("count") if i + 1 < args.len() => {
+                    // Redis takes 0 to mean no limit, like leaving it out.
+                    count = match parse_int(&args[i + 1])? {
+                        n if n > 0 => n as usize,
+                        _ => usize::MAX,
+                    };
+                    i += 2;
+                }
+                Some("block") if i + 1 < args.len() => {
+                    block = match parse_int(&args[i + 1]) {
+                        Ok(ms) if ms < 0 => return Err(error("ERR timeout is negative")),
+                        Ok(0) => Some(None),
+                        Ok(ms) => Some(Some(Duration::from_millis(ms as u64))),
+                        Err(_) => {
+                            return Err(error("ERR timeout is not an integer or out of range"))
+                        }
+                    };
+                    i += 2;
+                }
+                Some("noack") if group => {
+                    noack = true;
+                    i += 1;
+                }
+                Some("streams") => break,
+                _ => return Err(syntax_error()),
+            }
+        }
+
+        let streams = &args[i + 1..];
+        if streams.is_empty() || !streams.len().is_multiple_of(2) {
+            return Err(error(format!(
+                "ERR Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.",
+                command,
+                if group { '>' } else { '$' }
+            )));
+        }
+        let (keys, ids) = streams.split_at(streams.len() / 2);
+        let mut from = Vec::with_capacity(ids.len());
+        for id in ids {
+            from.push(match &id[..] {
+                b"$" if group => return Err(error(
+                    "ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.",
+                )),
+                b"$" => ReadFrom::Last,
+                b">" if group => ReadFrom::New,
+                b">" => return Err(error(
+                    "ERR The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option.",
+                )),
+                id => ReadFrom::After(parse_id(id, 0)?),
+            });
+        }
+        Ok(Read {
+            count,
+            block,
+            noack,
+            keys,
+            from,
+        })
+    }
+}
+
+/// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...]
+///   id [id ...]
+///
+/// Replies with the entries after each ID in its stream, up to `count` from
+/// each, as an array of key and entries pairs for the streams that have
+/// any, or nil if none do. "$" stands for the last ID in the stream. With
+/// BLOCK, waits up to `milliseconds`, or with 0 for ever, for entries to
+/// be added if there are none yet.
+fn xread(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let read = match Read::parse("xread", &args[1..]) {
+        Ok(read) => read,
+        Err(e) => return e,
+    };
+    let mut results = Vec::new();
+    let mut last_ids = Vec::with_capacity(read.keys.len());
+    for (key, &from) in read.keys.iter().zip(&read.from) {
+        let stream = match stream(ks, key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) => {
+                last_ids.push(StreamId::MIN);
+                continue;
+            }
+            Err(e) => return e,
+        };
+        last_ids.push(stream.last_id);
+        let after = match from {
+            ReadFrom::After(id) => id,
+            _ => stream.last_id,
+        };
+        let entries: Vec<Frame> = stream
+            .after(after)
+            .take(read.count)
+            .map(|(id, fields)| entry_reply(id, fields))
+            .collect();
+        if !entries.is_empty() {
+            results.push(Frame::Array(vec![
+                Frame::Bulk(key.clone()),
+                Frame::Array(entries),
+            ]));
+        }
+    }
+    if !results.is_empty() {
+        return Frame::Array(results);
+    }
+
+    if let Some(timeout) = read.block {
+        // Once entries are added, "$" would mean the last of those, so
+        // wait for ones after what is the last ID now.
+        let mut command = args.to_vec();
+        let ids = args.len() - read.from.len();
+        for (j, from) in read.from.iter().enumerate() {
+            if let ReadFrom::Last = from {
+                command[ids + j] = last_ids[j].to_string().into();
+            }
+        }
+        ks.block_as(read.keys.to_vec(), timeout, command);
+    }
+    Frame::NullArray
+}
+
+fn no_group(key: &[u8], group: &[u8]) -> Frame {
+    error(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        lossy(key),
+        lossy(group)
+    ))
+}
+
+/// The named group of the stream at `key`, or the NOGROUP error if there is
+/// no such stream or group.
+fn group<'a>(ks: &'a mut Keyspace, key: &[u8], name: &[u8]) -> Result<&'a mut Group, Frame> {
+    match stream(ks, key)? {
+        Some(stream) => stream
+            .groups
+            .get_mut(name)
+            .ok_or_else(|| no_group(key, name)),
+        None => Err(no_group(key, name)),
+    }
+}
+
+const XGROUP_HELP: &[&str] = &[
+    "XGROUP <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "CREATE <key> <groupname> <id|$> [option]",
+    "    Create a new consumer group. Options are:",
+    "    * MKSTREAM",
+    "      Create the empty stream if it does not exist.",
+    "CREATECONSUMER <key> <groupname> <consumer>",
+    "    Create a new consumer in the specified group.",
+    "DESTROY <key> <groupname>",
+    "    Remove the specified group.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// XGROUP CREATE key group <id | $> [MKSTREAM] | DESTROY key group |
+///   CREATECONSUMER key group consumer | HELP
+///
+/// A new group starts reading after the given ID, or with "$", after the
+/// stream's last entry.
+fn xgroup(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let sub = lossy(&args[1]).to_ascii_lowercase();
+    let no_key = || {
+        error("ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.")
+    };
+    match (sub.as_str(), args.len()) {
+        ("create", 5) | ("create", 6) => {
+            let create = match args.get(5) {
+                None => false,
+                Some(arg) if arg.eq_ignore_ascii_case(b"MKSTREAM") => true,
+                Some(_) => return syntax_error(),
+            };
+            let from = match &args[4][..] {
+                b"$" => None,
+                id => match parse_id(id, 0) {
+                    Ok(id) => Some(id),
+                    Err(e) => return e,
+                },
+            };
+            let key = &args[2];
+            match stream(ks, key) {
+                Ok(Some(_)) => {}
+                Ok(None) if create => {
+                    ks.db()
+                        .insert(key.clone(), Value::Stream(Stream::default()));
+                }
+                Ok(None) => return no_key(),
+                Err(e) => return e,
+            }
+            let stream = stream(ks, key).unwrap().unwrap();
+            if stream.groups.contains_key(&args[3]) {
+                return error("BUSYGROUP Consumer Group name already exists");
+            }
+            let last_id = from.unwrap_or(stream.last_id);
+            stream.groups.insert(args[3].clone(), Group::new(last_id));
+            ks.notify(Events::STREAM, "xgroup-create", key);
+            ok()
+        }
+        ("destroy", 4) => {
+            let destroyed = match stream(ks, &args[2]) {
+                Ok(Some(stream)) => stream.groups.remove(&args[3]).is_some(),
+                Ok(None) => return no_key(),
+                Err(e) => return e,
+            };
+            if destroyed {
+                ks.notify(Events::STREAM, "xgroup-destroy", &args[2]);
+            }
+            Frame::Integer(destroyed as i64)
+        }
+        ("createconsumer", 5) => {
+            let created = match stream(ks, &args[2]) {
+                Ok(Some(stream)) => match stream.groups.get_mut(&args[3]) {
+                    Some(group) => {
+                        let created = !group.consumers.contains_key(&args[4]);
+                        group.consumer(&args[4], now_ms());
+                        created
+                    }
+                    None => {
+                        return error(format!(
+                            "NOGROUP No such consumer group '{}' for key name '{}'",
+                            lossy(&args[3]),
+                            lossy(&args[2])
+                        ))
+                    }
+                },
+                Ok(None) => return no_key(),
+                Err(e) => return e,
+            };
+            if created {
+                ks.notify(Events::STREAM, "xgroup-createconsumer", &args[2]);
+            }
+            Frame::Integer(created as i64)
+        }
+        ("help", 2) => Frame::Array(
+            XGROUP_HELP
+                .iter()
+                .map(|line| Frame::Simple(line.to_string()))
+                .collect(),
+        ),
+        ("create", _) | ("destroy", _) | ("createconsumer", _) | ("help", _) => {
+            wrong_arity(&format!("xgroup|{}", sub))
+        }
+        _ => error(format!(
+            "ERR unknown subcommand '{}'. Try XGROUP HELP.",
+            lossy(&args[1])
+        )),
+    }
+}
+
+/// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds]
+///   [NOACK] STREAMS key [key ...] id [id ...]
+///
+/// With ">", reads entries no consumer in the group has been given yet,
+/// and makes them pending for this consumer until it acknowledges them,
+/// unless NOACK says not to bother. With an ID, rereads the consumer's own
+/// pending entries after that ID; ones since deleted from the stream come
+/// back with nil for their fields. Replies like XREAD, and blocks like it
+/// too when there are no new entries for ">".
+fn xreadgroup(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    if !args[1].eq_ignore_ascii_case(b"GROUP") {
+        return syntax_error();
+    }
+    let (name, consumer) = (&args[2], &args[3]);
+    let read = match Read::parse("xreadgroup", &args[4..]) {
+        Ok(read) => read,
+        Err(e) => return e,
+    };
+
+    let now = now_ms();
+    let mut results = Vec::new();
+    for (key, &from) in read.keys.iter().zip(&read.from) {
+        let stream = match stream(ks, key) {
+            Ok(Some(stream)) if stream.groups.contains_key(name) => stream,
+            Ok(_) => {
+                return error(format!(
+                "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                lossy(key),
+                lossy(name)
+            ))
+            }
+            Err(e) => return e,
+        };
+        let entries = match from {
+            ReadFrom::After(after) => {
+                let pending: Vec<StreamId> = {
+                    let group = stream.groups.get_mut(name).unwrap();
+                    let consumer = group.consumer(consumer, now);
+                    let pending: Vec<StreamId> = consumer
+                        .pending
+                        .range((Bound::Excluded(after), Bound::Unbounded))
+                        .take(read.count)
+                        .cloned()
+                        .collect();
+                    if !pending.is_empty() {
+                        consumer.active_at = Some(now);
+                    }
+                    pending
+                };
+                let entries: Vec<Frame> = pending
+                    .iter()
+                    .map(|id| match stream.get(id) {
+                        Some(fields) => entry_reply(id, fields),
+                        None => Frame::Array(vec![id_reply(*id), Frame::NullArray]),
+                    })
+                    .collect();
+                let group = stream.groups.get_mut(name).unwrap();
+                for id in pending {
+                    let entry = group.pending.get_mut(&id).unwrap();
+                    entry.delivered_at = now;
+                    entry.deliveries += 1;
+                }
+                // The consumer's history is there even when it's empty.
+                results.push(Frame::Array(vec![
+                    Frame::Bulk(key.clone()),
+                    Frame::Array(entries),
+                ]));
+                continue;
+            }
+            _ => {
+                let last_id = stream.groups[name].last_id;
+                let entries: Vec<(StreamId, Frame)> = stream
+                    .after(last_id)
+                    .take(read.count)
+                    .map(|(id, fields)| (*id, entry_reply(id, fields)))
+                    .collect();
+                let group = stream.groups.get_mut(name).unwrap();
+                let seen = group.consumer(consumer, now);
+                if !entries.is_empty() {
+                    seen.active_at = Some(now);
+                }
+                for &(id, _) in &entries {
+                    group.last_id = id;
+                    if !read.noack {
+                        group.assign(id, consumer, now).deliveries = 1;
+                    }
+                }
+                entries
+                    .into_iter()
+                    .map(|(_, entry)| entry)
+                    .collect::<Vec<Frame>>()
+            }
+        };
+        if !entries.is_empty() {
+            results.push(Frame::Array(vec![
+                Frame::Bulk(key.clone()),
+                Frame::Array(entries),
+            ]));
+        }
+    }
+    if results.is_empty() {
+        // Reading history always has a reply, so only ">" gets here.
+        if let Some(timeout) = read.block {
+            ks.block(read.keys.to_vec(), timeout);
+        }
+        return Frame::NullArray;
+    }
+    Frame::Array(results)
+}
+
+/// XACK key group id [id ...]
+///
+/// Replies with how many of the entries were pending and no longer are.
+fn xack(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let mut ids = Vec::with_capacity(args.len() - 3);
+    for id in &args[3..] {
+        match parse_id(id, 0) {
+            Ok(id) => ids.push(id),
+            Err(e) => return e,
+        }
+    }
+    match group(ks, &args[1], &args[2]) {
+        Ok(group) => Frame::Integer(ids.into_iter().filter(|&id| group.ack(id)).count() as i64),
+        Err(Frame::Error(ref e)) if e.starts_with("NOGROUP") => Frame::Integer(0),
+        Err(e) => e,
+    }
+}
+
+/// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+///
+/// Without a range, replies with a summary of the group's pending entries:
+/// how many there are, the lowest and highest IDs, and how many each
+/// consumer has. With one, replies with up to `count` of them in the
+/// range: the ID, consumer, milliseconds since it was delivered, and how
+/// many times it has been.
+fn xpending(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let mut min_idle = 0;
+    let mut i = 3;
+    if args.len() > 3 && args[3].eq_ignore_ascii_case(b"IDLE") {
+        min_idle = match args.get(4).map(|arg| parse_int(arg)) {
+            Some(Ok(n)) => n.max(0) as u64,
+            Some(Err(e)) => return e,
+            None => return syntax_error(),
+        };
+        i = 5;
+    }
+    let range = match args.len() - i {
+        0 if i == 3 => None,
+        3 | 4 => {
+            let (start, end) = match (
+                parse_bound(&args[i], true),
+                parse_bound(&args[i + 1], false),
+            ) {
+                (Ok(start), Ok(end)) => (start, end),
+                (Err(e), _) | (_, Err(e)) => return e,
+            };
+            let count = match parse_int(&args[i + 2]) {
+                Ok(n) => n.max(0) as usize,
+                Err(e) => return e,
+            };
+            Some((start, end, count, args.get(i + 3)))
+        }
+        _ => return syntax_error(),
+    };
+    let group = match group(ks, &args[1], &args[2]) {
+        Ok(group) => group,
+        Err(e) => return e,
+    };
+
+    let (start, end, count, consumer) = match range {
+        Some(range) => range,
+        None => {
+            let (first, last) = match (
+                group.pending.keys().next(),
+                group.pending.keys().next_back(),
+            ) {
+                (Some(&first), Some(&last)) => (first, last),
+                _ => {
+                    return Frame::Array(vec![
+                        Frame::Integer(0),
+                        Frame::Null,
+                        Frame::Null,
+                        Frame::NullArray,
+                    ])
+                }
+            };
+            let consumers = group
+                .consumers
+                .iter()
+                .filter(|(_, consumer)| !consumer.pending.is_empty())
+                .map(|(name, consumer)| {
+                    Frame::Array(vec![
+                        Frame::Bulk(name.clone()),
+                        Frame::Bulk(consumer.pending.len().to_string().into()),
+                    ])
+                })
+                .collect();
+            return Frame::Array(vec![
+                Frame::Integer(group.pending.len() as i64),
+                id_reply(first),
+                id_reply(last),
+                Frame::Array(consumers),
+            ]);
+        }
+    };
+    if start > end {
+        return Frame::Array(Vec::new());
+    }
+    let now = now_ms();
+    let entries = group
+        .pending
+        .range(start..=end)
+        .filter(|(_, entry)| consumer.is_none_or(|consumer| entry.consumer == *consumer))
+        .filter(|(_, entry)| now.saturating_sub(entry.delivered_at) >= min_idle)
+        .take(count)
+        .map(|(id, entry)| {
+            Frame::Array(vec![
+                id_reply(*id),
+                Frame::Bulk(entry.consumer.clone()),
+                Frame::Integer(now.saturating_sub(entry.delivered_at) as i64),
+                Frame::Integer(entry.deliveries as i64),
+            ])
+        })
+        .collect();
+    Frame::Array(entries)
+}
+
+/// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms]
+///   [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID]
+///   [LASTID lastid]
+///
+/// Takes over pending entries that have gone unacknowledged for at least
+/// `min-idle-time` milliseconds, and replies with them, or with JUSTID,
+/// their IDs. Claiming counts as a delivery unless JUSTID is given. FORCE
+/// claims entries that aren't pending too, as long as they exist. Entries
+/// since deleted from the stream are dropped from the PEL instead.
+fn xclaim(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let (key, name, consumer) = (&args[1], &args[2], &args[3]);
+    let min_idle = match parse_int(&args[4]) {
+        Ok(n) => n.max(0) as u64,
+        Err(_) => return error("ERR Invalid min-idle-time argument for XCLAIM"),
+    };
+    let mut ids = Vec::new();
+    let mut i = 5;
+    while let Some(Ok(id)) = args.get(i).map(|arg| parse_id(arg, 0)) {
+        ids.push(id);
+        i += 1;
+    }
+
+    let now = now_ms();
+    let mut delivered_at = now;
+    let mut retry_count = None;
+    let (mut force, mut just_id) = (false, false);
+    let mut last_id = None;
+    while i < args.len() {
+        let option = lossy(&args[i]).to_ascii_lowercase();
+        let value = args.get(i + 1);
+        match (option.as_str(), value) {
+            ("force", _) => force = true,
+            ("justid", _) => just_id = true,
+            ("idle", Some(value)) | ("time", Some(value)) | ("retrycount", Some(value)) => {
+                let n = match parse_int(value) {
+                    Ok(n) => n.max(0) as u64,
+                    Err(e) => return e,
+                };
+                match option.as_str() {
+                    "idle" => delivered_at = now.saturating_sub(n),
+                    "time" => delivered_at = n,
+                    _ => retry_count = Some(n),
+                }
+                i += 1;
+            }
+            ("lastid", Some(value)) => {
+                match parse_id(value, 0) {
+                    Ok(id) => last_id = Some(id),
+                    Err(e) => return e,
+                }
+                i += 1;
+            }
+            _ => {
+                return error(format!(
+                    "ERR Unrecognized XCLAIM option '{}'",
+                    lossy(&args[i])
+                ))
+            }
+        }
+        i += 1;
+    }
+
+    let stream = match stream(ks, key) {
+        Ok(Some(stream)) if stream.groups.contains_key(name) => stream,
+        Ok(_) => return no_group(key, name),
+        Err(e) => return e,
+    };
+    let entries: Vec<Option<Fields>> = ids.iter().map(|id| stream.get(id).cloned()).collect();
+    let group = stream.groups.get_mut(name).unwrap();
+    if let Some(last_id) = last_id {
+        group.last_id = group.last_id.max(last_id);
+    }
+    group.consumer(consumer, now);
+
+    let mut claimed = Vec::new();
+    for (id, fields) in ids.into_iter().zip(entries) {
+        let pending = group.pending.get(&id);
+        let fields = match (pending, fields) {
+            (None, Some(fields)) if force => fields,
+            (None, _) => continue,
+            (Some(_), None) => {
+                group.ack(id);
+                continue;
+            }
+            (Some(entry), Some(fields)) => {
+                if now.saturating_sub(entry.delivered_at) < min_idle {
+                    continue;
+                }
+                fields
+            }
+        };
+        let entry = group.assign(id, consumer, delivered_at);
+        match retry_count {
+            Some(n) => entry.deliveries = n,
+            None if !just_id => entry.deliveries += 1,
+            None => {}
+        }
+        claimed.push(if just_id {
+            id_reply(id)
+        } else {
+            entry_reply(&id, &fields)
+        });
+    }
+    if !claimed.is_empty() {
+        group.consumer(consumer, now).active_at = Some(now);
+    }
+    Frame::Array(claimed)
+}
+
+/// XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]
+///
+/// Claims up to `count` (by default 100) pending entries from `start` on
+/// that have gone unacknowledged for at least `min-idle-time`
+/// milliseconds, like XCLAIM, looking at no more than ten times as many.
+/// Replies with the ID to carry on from, or 0-0 once the whole PEL has
+/// been looked at, the claimed entries, and the IDs of entries dropped
+/// from the PEL because they were deleted from the stream.
+fn xautoclaim(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let (key, name, consumer) = (&args[1], &args[2], &args[3]);
+    let min_idle = match parse_int(&args[4]) {
+        Ok(n) => n.max(0) as u64,
+        Err(_) => return error("ERR Invalid min-idle-time argument for XAUTOCLAIM"),
+    };
+    let start = match parse_bound(&args[5], true) {
+        Ok(start) => start,
+        Err(e) => return e,
+    };
+    let mut count = 100;
+    let mut just_id = false;
+    let mut i = 6;
+    while i < args.len() {
+        if args[i].eq_ignore_ascii_case(b"JUSTID") {
+            just_id = true;
+            i += 1;
+        } else if args[i].eq_ignore_ascii_case(b"COUNT") && i + 1 < args.len() {
+            count = match parse_int(&args[i + 1]) {
+                Ok(n) if n > 0 => n as usize,
+                Ok(_) => return error("ERR COUNT must be > 0"),
+                Err(e) => return e,
+            };
+            i += 2;
+        } else {
+            return syntax_error();
+        }
+    }
+
+    let stream = match stream(ks, key) {
+        Ok(Some(stream)) if stream.groups.contains_key(name) => stream,
+        Ok(_) => return no_group(key, name),
+        Err(e) => return e,
+    };
+    // One more than it may look at, to know where to carry on from.
+    let attempts = count.saturating_mul(10);
+    let candidates: Vec<(StreamId, Option<Fields>)> = stream.groups[name]
+        .pending
+        .range(start..)
+        .take(attempts.saturating_add(1))
+        .map(|(id, _)| (*id, stream.get(id).cloned()))
+        .collect();
+    let now = now_ms();
+    let group = stream.groups.get_mut(name).unwrap();
+    group.consumer(consumer, now);
+
+    let mut claimed = Vec::new();
+    let mut deleted = Vec::new();
+    let mut looked_at = 0;
+    for (id, fields) in &candidates {
+        if looked_at == attempts || count == 0 {
+            break;
+        }
+        looked_at += 1;
+        let fields = match fields {
+            Some(fields) => fields,
+            None => {
+                group.ack(*id);
+                deleted.push(id_reply(*id));
+                count -= 1;
+                continue;
+            }
+        };
+        if now.saturating_sub(group.pending[id].delivered_at) < min_idle {
+            continue;
+        }
+        let entry = group.assign(*id, consumer, now);
+        if !just_id {
+            entry.deliveries += 1;
+        }
+        claimed.push(if just_id {
+            id_reply(*id)
+        } else {
+            entry_reply(id, fields)
+        });
+        count -= 1;
+    }
+    if !claimed.is_empty() {
+        group.consumer(consumer, now).active_at = Some(now);
+    }
+    let next = candidates
+        .get(looked_at)
+        .map_or(StreamId::MIN, |&(id, _)| id);
+    Frame::Array(vec![
+        id_reply(next),
+        Frame::Array(claimed),
+        Frame::Array(deleted),
+    ])
+}
+
+const XINFO_HELP: &[&str] = &[
+    "XINFO <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "CONSUMERS <key> <groupname>",
+    "    Show consumers of <groupname>.",
+    "GROUPS <key>",
+    "    Show the stream consumer groups.",
+    "STREAM <key> [FULL [COUNT <count>]",
+    "    Show information about the stream.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// XINFO STREAM key [FULL [COUNT count]] | GROUPS key |
+///   CONSUMERS key group | HELP
+///
+/// Each reply is a flat list of field names and values, as in Redis. A
+/// group's lag is how many entries it has yet to deliver, and its
+/// entries-read how many of all those ever added it got through before
+/// them. There's no radix tree behind a stream here; the radix-tree
+/// fields count the nodes Redis would pack the entries into. FULL lists
+/// the entries and every group's PEL and consumers, COUNT of each at most,
+/// 10 by default or all of them with 0.
+fn xinfo(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let sub = lossy(&args[1]).to_ascii_lowercase();
+    let now = now_ms();
+    match (sub.as_str(), args.len()) {
+        ("stream", 3..) => {
+            let full = match &args[3..] {
+                [] => None,
+                [full] if full.eq_ignore_ascii_case(b"FULL") => Some(10),
+                [full, count, n]
+                    if full.eq_ignore_ascii_case(b"FULL")
+                        && count.eq_ignore_ascii_case(b"COUNT") =>
+                {
+                    match parse_int(n) {
+                        Ok(0) => Some(usize::MAX),
+                        Ok(n) if n > 0 => Some(n as usize),
+                        Ok(_) => return syntax_error(),
+                        Err(e) => return e,
+                    }
+                }
+                _ => return syntax_error(),
+            };
+            match existing_stream(ks, &args[2]) {
+                Ok(stream) => stream_info(stream, full),
+                Err(e) => e,
+            }
+        }
+        ("groups", 3) => match existing_stream(ks, &args[2]) {
+            Ok(stream) => Frame::Array(
+                stream
+                    .groups
+                    .iter()
+                    .map(|(name, group)| group_info(stream, name, group))
+                    .collect(),
+            ),
+            Err(e) => e,
+        },
+        ("consumers", 4) => {
+            let stream = match existing_stream(ks, &args[2]) {
+                Ok(stream) => stream,
+                Err(e) => return e,
+            };
+            match stream.groups.get(&args[3]) {
+                Some(group) => Frame::Array(
+                    group
+                        .consumers
+                        .iter()
+                        .map(|(name, consumer)| consumer_info(name, consumer, now))
+                        .collect(),
+                ),
+                None => error(format!(
+                    "NOGROUP No such consumer group '{}' for key name '{}'",
+                    lossy(&args[3]),
+                    lossy(&args[2])
+                )),
+            }
+        }
+        ("help", 2) => Frame::Array(
+            XINFO_HELP
+                .iter()
+                .map(|line| Frame::Simple(line.to_string()))
+                .collect(),
+        ),
+        ("stream", _) | ("groups", _) | ("consumers", _) | ("help", _) => {
+            wrong_arity(&format!("xinfo|{}", sub))
+        }
+        _ => error(format!(
+            "ERR unknown subcommand '{}'. Try XINFO HELP.",
+            lossy(&args[1])
+        )),
+    }
+}
+
+/// The stream at `key`, or the error reply XINFO gives if there isn't one.
+fn existing_stream<'a>(ks: &'a mut Keyspace, key: &[u8]) -> Result<&'a Stream, Frame> {
+    match stream(ks, key)? {
+        Some(stream) => Ok(stream),
+        None => Err(error("ERR no such key")),
+    }
+}
+
+/// A flat array of field names and values.
+fn info_reply(fields: Vec<(&str, Frame)>) -> Frame {
+    Frame::Array(
+        fields
+            .into_iter()
+            .flat_map(|(name, value)| [Frame::Bulk(Bytes::from(name)), value])
+            .collect(),
+    )
+}
+
+/// The reply to XINFO STREAM, with FULL's `count` if it was given.
+fn stream_info(stream: &Stream, full: Option<usize>) -> Frame {
+    let nodes = stream.len().div_ceil(NODE_ENTRIES) as i64;
+    let first_id = stream.first().map_or(StreamId::MIN, |(id, _)| *id);
+    let mut info = vec![
+        ("length", Frame::Integer(stream.len() as i64)),
+        ("radix-tree-keys", Frame::Integer(nodes)),
+        ("radix-tree-nodes", Frame::Integer(nodes)),
+        ("last-generated-id", id_reply(stream.last_id)),
+        ("max-deleted-entry-id", id_reply(stream.max_deleted_id)),
+        ("entries-added", Frame::Integer(stream.entries_added as i64)),
+        ("recorded-first-entry-id", id_reply(first_id)),
+    ];
+    let count = match full {
+        Some(count) => count,
+        None => {
+            let entry = |entry: Option<(&StreamId, &Fields)>| {
+                entry.map_or(Frame::Null, |(id, fields)| entry_reply(id, fields))
+            };
+            info.push(("groups", Frame::Integer(stream.groups.len() as i64)));
+            info.push(("first-entry", entry(stream.first())));
+            info.push(("last-entry", entry(stream.last())));
+            return info_reply(info);
+        }
+    };
+    let entries = stream
+        .range(StreamId::MIN, StreamId::MAX)
+        .take(count)
+        .map(|(id, fields)| entry_reply(id, fields))
+        .collect();
+    let groups = stream
+        .groups
+        .iter()
+        .map(|(name, group)| {
+            let pending = group
+                .pending
+                .iter()
+                .take(count)
+                .map(|(id, entry)| {
+                    Frame::Array(vec![
+                        id_reply(*id),
+                        Frame::Bulk(entry.consumer.clone()),
+                        Frame::Integer(entry.delivered_at as i64),
+                        Frame::Integer(entry.deliveries as i64),
+                    ])
+                })
+                .collect();
+            let consumers = group
+                .consumers
+                .iter()
+                .map(|(name, consumer)| {
+                    let pending = consumer
+                        .pending
+                        .iter()
+                        .take(count)
+                        .map(|id| {
+                            let entry = &group.pending[id];
+                            Frame::Array(vec![
+                                id_reply(*id),
+                                Frame::Integer(entry.delivered_at as i64),
+                                Frame::Integer(entry.deliveries as i64),
+                            ])
+                        })
+                        .collect();
+                    info_reply(vec![
+                        ("name", Frame::Bulk(name.clone())),
+                        ("seen-time", Frame::Integer(consumer.seen_at as i64)),
+                        (
+                            "active-time",
+                            Frame::Integer(consumer.active_at.map_or(-1, |at| at as i64)),
+                        ),
+                        ("pel-count", Frame::Integer(consumer.pending.len() as i64)),
+                        ("pending", Frame::Array(pending)),
+                    ])
+                })
+                .collect();
+            let lag = group.lag(stream);
+            info_reply(vec![
+                ("name", Frame::Bulk(name.clone())),
+                ("last-delivered-id", id_reply(group.last_id)),
+                ("entries-read", entries_read(stream, lag)),
+                ("lag", Frame::Integer(lag as i64)),
+                ("pel-count", Frame::Integer(group.pending.len() as i64)),
+                ("pending", Frame::Array(pending)),
+                ("consumers", Frame::Array(consumers)),
+            ])
+        })
+        .collect();
+    info.push(("entries", Frame::Array(entries)));
+    info.push(("groups", Frame::Array(groups)));
+    info_reply(info)
+}
+
+/// How many of the entries ever added to `stream` a group with `lag`
+/// entries still to deliver has got through.
+fn entries_read(stream: &Stream, lag: usize) -> Frame {
+    Frame::Integer(stream.entries_added.saturating_sub(lag as u64) as i64)
+}
+
+/// One group in the reply to XINFO GROUPS.
+fn group_info(stream: &Stream, name: &Bytes, group: &Group) -> Frame {
+    let lag = group.lag(stream);
+    info_reply(vec![
+        ("name", Frame::Bulk(name.clone())),
+        ("consumers", Frame::Integer(group.consumers.len() as i64)),
+        ("pending", Frame::Integer(group.pending.len() as i64)),
+        ("last-delivered-id", id_reply(group.last_id)),
+        ("entries-read", entries_read(stream, lag)),
+        ("lag", Frame::Integer(lag as i64)),
+    ])
+}
+
+/// One consumer in the reply to XINFO CONSUMERS: idle is how long since
+/// it was last seen, and inactive how long since it last got any entries,
+/// or -1 if it never has.
+fn consumer_info(name: &Bytes, consumer: &Consumer, now: u64) -> Frame {
+    let since = |at: u64| Frame::Integer(now.saturating_sub(at) as i64);
+    info_reply(vec![
+        ("name", Frame::Bulk(name.clone())),
+        ("pending", Frame::Integer(consumer.pending.len() as i64)),
+        ("idle", since(consumer.seen_at)),
+        (
+            "inactive",
+            consumer.active_at.map_or(Frame::Integer(-1), since),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(s: &str) -> Frame {
+        Frame::Bulk(Bytes::from(s.to_string()))
+    }
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    /// The IDs of the entries in an XRANGE-style reply.
+    fn ids(reply: Frame) -> Vec<String> {
+        match reply {
+            Frame::Array(entries) => entries
+                .into_iter()
+                .map(|entry| match entry {
+                    Frame::Array(mut entry) => match entry.remove(0) {
+                        Frame::Bulk(id) => lossy(&id),
+                        other => panic!("not an ID: {:?}", other),
+                    },
+                    other => panic!("not an entry: {:?}", other),
+                })
+                .collect(),
+            other => panic!("not an array: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_ids() {
+        assert_eq!(parse_id(b"5-3", 0), Ok(id(5, 3)));
+        assert_eq!(parse_id(b"5", 0), Ok(id(5, 0)));
+        assert_eq!(parse_id(b"5", u64::MAX), Ok(id(5, u64::MAX)));
+        assert_eq!(parse_id(b"-", u64::MAX), Ok(StreamId::MIN));
+        assert_eq!(parse_id(b"+", 0), Ok(StreamId::MAX));
+        assert_eq!(
+            parse_id(b"18446744073709551615-18446744073709551615", 0),
+            Ok(StreamId::MAX)
+        );
+        for bad in &["", "5-", "-5", "5-x", "+5", "5-3-1", "18446744073709551616"] {
+            assert_eq!(parse_id(bad.as_bytes(), 0), Err(invalid_id()), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn parses_exclusive_bounds() {
+        assert_eq!(parse_bound(b"5", true), Ok(id(5, 0)));
+        assert_eq!(parse_bound(b"5", false), Ok(id(5, u64::MAX)));
+        assert_eq!(parse_bound(b"(5-3", true), Ok(id(5, 4)));
+        assert_eq!(parse_bound(b"(5-3", false), Ok(id(5, 2)));
+        assert_eq!(parse_bound(b"(5", true), Ok(id(5, 1)));
+        assert_eq!(parse_bound(b"(5", false), Ok(id(5, u64::MAX - 1)));
+        assert_eq!(parse_bound(b"(5-0", false), Ok(id(4, u64::MAX)));
+        assert_eq!(parse_bound(b"(-", true), Err(invalid_id()));
+        assert_eq!(parse_bound(b"(+", false), Err(invalid_id()));
+        assert_eq!(
+            parse_bound(b"(0-0", false),
+            Err(error("ERR invalid end ID for the interval"))
+        );
+        assert_eq!(
+            parse_bound(b"(18446744073709551615-18446744073709551615", true),
+            Err(error("ERR invalid start ID for the interval"))
+        );
+    }
+
+    #[test]
+    fn xadd_generates_sequence_numbers() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        assert_eq!(
+            ks.command(client, &["xadd", "s", "5-*", "f", "v"]),
+            bulk("5-0")
+        );
+        assert_eq!(
+            ks.command(client, &["xadd", "s", "5-*", "f", "v"]),
+            bulk("5-1")
+        );
+        assert_eq!(
+            ks.command(client, &["xadd", "s", "7-*", "f", "v"]),
+            bulk("7-0")
+        );
+        assert!(matches!(
+            ks.command(client, &["xadd", "s", "6-*", "f", "v"]),
+            Frame::Error(_)
+        ));
+        assert_eq!(
+            ks.command(client, &["xadd", "s", "7-1-*", "f", "v"]),
+            invalid_id()
+        );
+        assert_eq!(
+            ks.command(client, &["xadd", "s", "0-0", "f", "v"]),
+            error("ERR The ID specified in XADD must be greater than 0-0")
+        );
+    }
+
+    #[test]
+    fn xadd_refuses_once_ids_are_exhausted() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        let max = "18446744073709551615-18446744073709551615";
+        assert_eq!(ks.command(client, &["xadd", "s", max, "f", "v"]), bulk(max));
+        let exhausted =
+            error("ERR The stream has exhausted the last possible ID, unable to add more items");
+        for new in &["*", "18446744073709551615-*", max] {
+            assert_eq!(ks.command(client, &["xadd", "s", new, "f", "v"]), exhausted);
+        }
+        assert_eq!(ks.command(client, &["xlen", "s"]), Frame::Integer(1));
+    }
+
+    #[test]
+    fn xrange_bounds() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        for new in &["1-0", "1-1", "2-0", "3-0"] {
+            ks.command(client, &["xadd", "s", new, "f", "v"]);
+        }
+
+        assert_eq!(
+            ids(ks.command(client, &["xrange", "s", "1", "2"])),
+            vec!["1-0", "1-1", "2-0"]
+        );
+        assert_eq!(
+            ids(ks.command(client, &["xrange", "s", "(1-0", "(3-0"])),
+            vec!["1-1", "2-0"]
+        );
+        // An incomplete end is the last ID of its millisecond, so
+        // excluding it still leaves the others.
+        assert_eq!(
+            ids(ks.command(client, &["xrange", "s", "(1-0", "(3"])),
+            vec!["1-1", "2-0", "3-0"]
+        );
+        assert_eq!(
+            ids(ks.command(client, &["xrevrange", "s", "+", "(1-1", "COUNT", "1"])),
+            vec!["3-0"]
+        );
+        // A range that ends before it starts is empty, either way round.
+        assert_eq!(
+            ids(ks.command(client, &["xrange", "s", "3", "1"])),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            ids(ks.command(client, &["xrevrange", "s", "1", "3"])),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            ids(ks.command(client, &["xrange", "s", "(2-0", "(2-1"])),
+            Vec::<String>::new()
+        );
+    }
+
+    /// A copy of a group of the stream at `key`.
+    fn group_state(ks: &mut Keyspace, key: &str, name: &str) -> Group {
+        match ks.db().get(key.as_bytes()) {
+            Some(Value::Stream(stream)) => stream.groups[name.as_bytes()].clone(),
+            other => panic!("not a stream: {:?}", other),
+        }
+    }
+
+    /// Who an entry is pending for and how many times it was delivered.
+    fn pending(group: &Group, ms: u64) -> Option<(String, u64)> {
+        group
+            .pending
+            .get(&id(ms, 0))
+            .map(|entry| (lossy(&entry.consumer), entry.deliveries))
+    }
+
+    /// The IDs pending for a consumer, by their milliseconds.
+    fn consumer_pending(group: &Group, name: &str) -> Vec<u64> {
+        group.consumers[name.as_bytes()]
+            .pending
+            .iter()
+            .map(|id| id.ms)
+            .collect()
+    }
+
+    /// A stream "s" with entries 1-0 to `n`-0 and a group "g" reading from
+    /// the start.
+    fn with_group(n: u64) -> (Keyspace, ClientId) {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        for ms in 1..=n {
+            ks.command(client, &["xadd", "s", &format!("{}-0", ms), "f", "v"]);
+        }
+        ks.command(client, &["xgroup", "create", "s", "g", "0"]);
+        (ks, client)
+    }
+
+    #[test]
+    fn xreadgroup_counts_deliveries() {
+        let (mut ks, client) = with_group(3);
+        let read = |ks: &mut Keyspace, consumer: &str, from: &str| {
+            ks.command(
+                client,
+                &["xreadgroup", "group", "g", consumer, "streams", "s", from],
+            )
+        };
+
+        read(&mut ks, "alice", ">");
+        let group = group_state(&mut ks, "s", "g");
+        assert_eq!(group.last_id, id(3, 0));
+        assert_eq!(pending(&group, 1), Some(("alice".into(), 1)));
+        assert_eq!(consumer_pending(&group, "alice"), vec![1, 2, 3]);
+
+        // Rereading history is another delivery, of only what's after the
+        // ID given.
+        read(&mut ks, "alice", "1");
+        let group = group_state(&mut ks, "s", "g");
+        assert_eq!(pending(&group, 1), Some(("alice".into(), 1)));
+        assert_eq!(pending(&group, 2), Some(("alice".into(), 2)));
+
+        // Another consumer's history is its own.
+        assert_eq!(
+            read(&mut ks, "bob", "0"),
+            Frame::Array(vec![Frame::Array(vec![bulk("s"), Frame::Array(vec![])])])
+        );
+        assert_eq!(read(&mut ks, "bob", ">"), Frame::NullArray);
+
+        // NOACK entries are delivered without becoming pending.
+        ks.command(client, &["xadd", "s", "4-0", "f", "v"]);
+        ks.command(
+            client,
+            &[
+                "xreadgroup",
+                "group",
+                "g",
+                "bob",
+                "noack",
+                "streams",
+                "s",
+                ">",
+            ],
+        );
+        let group = group_state(&mut ks, "s", "g");
+        assert_eq!(group.last_id, id(4, 0));
+        assert_eq!(pending(&group, 4), None);
+    }
+
+    #[test]
+    fn xclaim_transfers_ownership() {
+        let (mut ks, client) = with_group(3);
+        ks.command(
+            client,
+            &["xreadgroup", "group", "g", "alice", "streams", "s", ">"],
+        );
+
+        assert_eq!(
+            ids(ks.command(client, &["xclaim", "s", "g", "bob", "0", "1-0", "2-0"])),
+            vec!["1-0", "2-0"]
+        );
+        let group = group_state(&mut ks, "s", "g");
+        assert_eq!(pending(&group, 1), Some(("bob".into(), 2)));
+        assert_eq!(consumer_pending(&group, "alice"), vec![3]);
+        assert_eq!(consumer_pending(&group, "bob"), vec![1, 2]);
+
+        // Too recently delivered to be claimed.
+        assert_eq!(
+            ks.command(client, &["xclaim", "s", "g", "carol", "60000", "1-0"]),
+            Frame::Array(vec![])
+        );
+
+        // JUSTID moves the entry without counting a delivery; RETRYCOUNT
+        // sets the count outright.
+        assert_eq!(
+            ks.command(client, &["xclaim", "s", "g", "alice", "0", "1-0", "JUSTID"]),
+            Frame::Array(vec![bulk("1-0")])
+        );
+        ks.command(
+            client,
+            &["xclaim", "s", "g", "carol", "0", "2-0", "RETRYCOUNT", "7"],
+        );
+        let group = group_state(&mut ks, "s", "g");
+        assert_eq!(pending(&group, 1), Some(("alice".into(), 2)));
+        assert_eq!(pending(&group, 2), Some(("carol".into(), 7)));
+        assert_eq!(consumer_pending(&group, "bob"), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn xclaim_force_needs_the_entry_to_exist() {
+        let (mut ks, client) = with_group(2);
+        ks.command(
+            client,
+            &[
+                "xreadgroup",
+                "group",
+                "g",
+                "alice",
+                "count",
+                "1",
+                "streams",
+                "s",
+                ">",
+            ],
+        );
+
+        // 2-0 was never delivered, so isn't pending until FORCE makes it.
+        assert_eq!(
+            ks.command(client, &["xclaim", "s", "g", "bob", "0", "2-0"]),
+            Frame::Array(vec![])
+        );
+        assert_eq!(
+            ids(ks.command(
+                client,
+                &["xclaim", "s", "g", "bob", "0", "2-0", "9-0", "FORCE"]
+            )),
+            vec!["2-0"]
+        );
+        let group = group_state(&mut ks, "s", "g");
+        assert_eq!(pending(&group, 2), Some(("bob".into(), 1)));
+        assert_eq!(pending(&group, 9), None);
+        assert_eq!(group.last_id, id(1, 0));
+    }
+
+    #[test]
+    fn deleted_entries_leave_the_pel_when_claimed() {
+        let (mut ks, client) = with_group(3);
+        ks.command(
+            client,
+            &["xreadgroup", "group", "g", "alice", "streams", "s", ">"],
+        );
+        ks.command(client, &["xtrim", "s", "maxlen", "1"]);
+
+        // Still pending, so still in alice's history, without fields.
+        assert_eq!(
+            ks.command(
+                client,
+                &[
+                    "xreadgroup",
+                    "group",
+                    "g",
+                    "alice",
+                    "count",
+                    "1",
+                    "streams",
+                    "s",
+                    "0"
+                ],
+            ),
+            Frame::Array(vec![Frame::Array(vec![
+                bulk("s"),
+                Frame::Array(vec![Frame::Array(vec![bulk("1-0"), Frame::NullArray])]),
+            ])])
+        );
+
+        assert_eq!(
+            ids(ks.command(client, &["xclaim", "s", "g", "bob", "0", "1-0", "3-0"])),
+            vec!["3-0"]
+        );
+        let group = group_state(&mut ks, "s", "g");
+        assert_eq!(pending(&group, 1), None);
+        assert_eq!(consumer_pending(&group, "alice"), vec![2]);
+        assert_eq!(consumer_pending(&group, "bob"), vec![3]);
+    }
+
+    fn args(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::from(*arg)).collect()
+    }
+
+    #[test]
+    fn trim_limit_defaults_and_needs_approx() {
+        let parse = |line: &[&str]| {
+            let mut i = 2;
+            TrimArgs::parse(&args(line), &mut i).map(|trim| (trim.approx, trim.limit, i))
+        };
+        assert_eq!(
+            parse(&["xtrim", "s", "MAXLEN", "~", "5"]),
+            Ok((true, NODE_ENTRIES * 100, 5))
+        );
+        assert_eq!(
+            parse(&["xtrim", "s", "MAXLEN", "5"]),
+            Ok((false, usize::MAX, 4))
+        );
+        assert_eq!(
+            parse(&["xtrim", "s", "MINID", "=", "5"]),
+            Ok((false, usize::MAX, 5))
+        );
+        assert_eq!(
+            parse(&["xtrim", "s", "MAXLEN", "~", "5", "LIMIT", "30"]),
+            Ok((true, 30, 7))
+        );
+        assert_eq!(
+            parse(&["xtrim", "s", "MAXLEN", "~", "5", "LIMIT", "0"]),
+            Ok((true, usize::MAX, 7))
+        );
+        assert_eq!(
+            parse(&["xtrim", "s", "MAXLEN", "5", "LIMIT", "30"]),
+            Err(error(
+                "ERR syntax error, LIMIT cannot be used without the special ~ option"
+            ))
+        );
+        assert_eq!(
+            parse(&["xtrim", "s", "MAXLEN", "=", "5", "LIMIT", "30"]).map(|_| ()),
+            Err(error(
+                "ERR syntax error, LIMIT cannot be used without the special ~ option"
+            ))
+        );
+        assert_eq!(
+            parse(&["xtrim", "s", "MAXLEN", "~", "5", "LIMIT", "-1"]),
+            Err(error("ERR The LIMIT argument must be >= 0."))
+        );
+    }
+
+    #[test]
+    fn approximate_trimming_removes_whole_nodes() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        for _ in 0..NODE_ENTRIES * 3 + 50 {
+            ks.command(client, &["xadd", "s", "*", "f", "v"]);
+        }
+        let trim = |ks: &mut Keyspace, line: &[&str]| {
+            let mut command = vec!["xtrim", "s"];
+            command.extend_from_slice(line);
+            ks.command(client, &command)
+        };
+
+        // At most LIMIT, rounded down to whole nodes.
+        assert_eq!(
+            trim(&mut ks, &["maxlen", "~", "0", "limit", "150"]),
+            Frame::Integer(NODE_ENTRIES as i64)
+        );
+        assert_eq!(
+            trim(&mut ks, &["maxlen", "~", "0"]),
+            Frame::Integer(NODE_ENTRIES as i64 * 2)
+        );
+        // Less than a node over, so nothing goes until trimming exactly.
+        assert_eq!(trim(&mut ks, &["maxlen", "~", "10"]), Frame::Integer(0));
+        assert_eq!(trim(&mut ks, &["maxlen", "10"]), Frame::Integer(40));
+        assert_eq!(ks.command(client, &["xlen", "s"]), Frame::Integer(10));
+    }
+
+    #[test]
+    fn xautoclaim_cursor_and_attempts() {
+        let (mut ks, client) = with_group(25);
+        ks.command(
+            client,
+            &["xreadgroup", "group", "g", "alice", "streams", "s", ">"],
+        );
+        let autoclaim = |ks: &mut Keyspace, min_idle: &str, start: &str| match ks.command(
+            client,
+            &[
+                "xautoclaim",
+                "s",
+                "g",
+                "bob",
+                min_idle,
+                start,
+                "COUNT",
+                "1",
+                "JUSTID",
+            ],
+        ) {
+            Frame::Array(mut reply) => {
+                let deleted = reply.pop().unwrap();
+                let claimed = reply.pop().unwrap();
+                (reply.pop().unwrap(), claimed, deleted)
+            }
+            other => panic!("not an array: {:?}", other),
+        };
+        let empty = Frame::Array(vec![]);
+
+        assert_eq!(
+            autoclaim(&mut ks, "0", "0"),
+            (bulk("2-0"), Frame::Array(vec![bulk("1-0")]), empty.clone())
+        );
+        // Nothing is idle long enough: COUNT 1 looks at ten entries at most.
+        assert_eq!(
+            autoclaim(&mut ks, "60000", "2"),
+            (bulk("12-0"), empty.clone(), empty.clone())
+        );
+        assert_eq!(
+            autoclaim(&mut ks, "60000", "(21-0"),
+            (bulk("0-0"), empty.clone(), empty.clone())
+        );
+
+        // Entries deleted from the stream are dropped, and count towards
+        // COUNT like claimed ones.
+        ks.command(client, &["xtrim", "s", "minid", "4"]);
+        assert_eq!(
+            autoclaim(&mut ks, "0", "0"),
+            (bulk("2-0"), empty.clone(), Frame::Array(vec![bulk("1-0")]))
+        );
+        let group = group_state(&mut ks, "s", "g");
+        assert_eq!(pending(&group, 1), None);
+        assert_eq!(pending(&group, 2), Some(("alice".into(), 1)));
+        assert_eq!(consumer_pending(&group, "bob"), Vec::<u64>::new());
+        assert_eq!(group.pending.len(), 24);
+    }
+}
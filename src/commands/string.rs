@@ -0,0 +1,508 @@
+//! Commands on string values.
+
+use bytes::Bytes;
+
+use super::{
+    error, format_human_float, ok, parse_float, parse_int, syntax_error, wrong_arity, wrong_type,
+    Command,
+};
+use crate::client::ClientId;
+use crate::db::{now_ms, Value};
+use crate::keyspace::Keyspace;
+use crate::notify::Events;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "get",
+        arity: 2,
+        subcommands: false,
+        handler: get,
+    },
+    Command {
+        name: "set",
+        arity: -3,
+        subcommands: false,
+        handler: set,
+    },
+    Command {
+        name: "getex",
+        arity: -2,
+        subcommands: false,
+        handler: getex,
+    },
+    Command {
+        name: "getdel",
+        arity: 2,
+        subcommands: false,
+        handler: getdel,
+    },
+    Command {
+        name: "setnx",
+        arity: 3,
+        subcommands: false,
+        handler: setnx,
+    },
+    Command {
+        name: "setex",
+        arity: 4,
+        subcommands: false,
+        handler: setex,
+    },
+    Command {
+        name: "psetex",
+        arity: 4,
+        subcommands: false,
+        handler: setex,
+    },
+    Command {
+        name: "getset",
+        arity: 3,
+        subcommands: false,
+        handler: getset,
+    },
+    Command {
+        name: "mget",
+        arity: -2,
+        subcommands: false,
+        handler: mget,
+    },
+    Command {
+        name: "mset",
+        arity: -3,
+        subcommands: false,
+        handler: mset,
+    },
+    Command {
+        name: "msetnx",
+        arity: -3,
+        subcommands: false,
+        handler: mset,
+    },
+    Command {
+        name: "incr",
+        arity: 2,
+        subcommands: false,
+        handler: incr,
+    },
+    Command {
+        name: "decr",
+        arity: 2,
+        subcommands: false,
+        handler: incr,
+    },
+    Command {
+        name: "incrby",
+        arity: 3,
+        subcommands: false,
+        handler: incr,
+    },
+    Command {
+        name: "decrby",
+        arity: 3,
+        subcommands: false,
+        handler: incr,
+    },
+    Command {
+        name: "incrbyfloat",
+        arity: 3,
+        subcommands: false,
+        handler: incrbyfloat,
+    },
+];
+
+/// GET key
+fn get(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match ks.db().get(&args[1]).map(Value::as_string) {
+        Some(Some(value)) => Frame::Bulk(value),
+        Some(None) => wrong_type(),
+        None => Frame::Null,
+    }
+}
+
+/// When a SET should happen, depending on whether the key already exists.
+#[derive(Clone, Copy, PartialEq)]
+enum Condition {
+    Always,
+    /// NX: only if it doesn't.
+    Missing,
+    /// XX: only if it does.
+    Present,
+}
+
+/// What a SET does to the key's expiry.
+#[derive(Clone, Copy, PartialEq)]
+enum Expiry {
+    /// Drop any expiry the key had.
+    Clear,
+    /// KEEPTTL: leave it as it is.
+    Keep,
+    /// Expire at this unix time in milliseconds.
+    At(u64),
+}
+
+/// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds |
+///   EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
+fn set(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let mut condition = Condition::Always;
+    let mut expiry = Expiry::Clear;
+    let mut get = false;
+
+    let mut i = 3;
+    while i < args.len() {
+        let opt = args[i].to_ascii_uppercase();
+        let next = args.get(i + 1);
+        match (&opt[..], next) {
+            (b"NX", _) if condition != Condition::Present => condition = Condition::Missing,
+            (b"XX", _) if condition != Condition::Missing => condition = Condition::Present,
+            (b"GET", _) => get = true,
+            (b"KEEPTTL", _) if !matches!(expiry, Expiry::At(_)) => expiry = Expiry::Keep,
+            (b"EX", Some(n)) | (b"PX", Some(n)) | (b"EXAT", Some(n)) | (b"PXAT", Some(n))
+                if expiry == Expiry::Clear =>
+            {
+                expiry = match expire_at("set", &opt, n) {
+                    Ok(at) => Expiry::At(at),
+                    Err(e) => return e,
+                };
+                i += 1;
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+
+    let key = &args[1];
+    let old = match ks.db().get(key).map(Value::as_string) {
+        Some(Some(old)) => Some(old),
+        // SET overwrites any type, but SET ... GET can't return another
+        // type's value.
+        Some(None) if get => return wrong_type(),
+        _ => None,
+    };
+    let reply = |old: Option<Bytes>| match (get, old) {
+        (true, Some(old)) => Frame::Bulk(old),
+        (true, None) => Frame::Null,
+        (false, _) => ok(),
+    };
+
+    // NX and XX go by whether the key exists, whatever its type.
+    let allowed = match condition {
+        Condition::Always => true,
+        Condition::Missing => !ks.db().contains(key),
+        Condition::Present => ks.db().contains(key),
+    };
+    if !allowed {
+        // Without GET a skipped SET replies nil; with it, the old value
+        // comes back either way.
+        return if get { reply(old) } else { Frame::Null };
+    }
+
+    let value = Value::string(args[2].clone());
+    match expiry {
+        Expiry::Clear => {
+            ks.db().insert(key.clone(), value);
+        }
+        Expiry::Keep => {
+            ks.db().insert_keep_ttl(key.clone(), value);
+        }
+        Expiry::At(at) => {
+            ks.db().insert(key.clone(), value);
+            ks.db().set_expiry(key, at);
+        }
+    }
+    ks.notify(Events::STRING, "set", key);
+    if let Expiry::At(_) = expiry {
+        ks.notify(Events::GENERIC, "expire", key);
+    }
+    reply(old)
+}
+
+/// Turns the argument of `command`'s EX, PX, EXAT or PXAT option into a
+/// unix time in milliseconds.
+fn expire_at(command: &str, unit: &[u8], arg: &[u8]) -> Result<u64, Frame> {
+    let invalid = || error(format!("ERR invalid expire time in '{}' command", command));
+    let n = parse_int(arg)?;
+    if n <= 0 {
+        return Err(invalid());
+    }
+    let ms = match unit {
+        b"EX" | b"EXAT" => n.checked_mul(1000).ok_or_else(invalid)?,
+        _ => n,
+    };
+    match unit {
+        b"EX" | b"PX" => ms.checked_add(now_ms() as i64).ok_or_else(invalid),
+        _ => Ok(ms),
+    }
+    .map(|at| at as u64)
+}
+
+/// GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+///   PXAT unix-time-milliseconds | PERSIST]
+///
+/// GET that also sets or clears the key's expiry. A time already in the
+/// past deletes the key once its value has been read.
+fn getex(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let expiry = match args.len() {
+        2 => Expiry::Keep,
+        3 if args[2].eq_ignore_ascii_case(b"PERSIST") => Expiry::Clear,
+        4 => {
+            let unit = args[2].to_ascii_uppercase();
+            match &unit[..] {
+                b"EX" | b"PX" | b"EXAT" | b"PXAT" => match expire_at("getex", &unit, &args[3]) {
+                    Ok(at) => Expiry::At(at),
+                    Err(e) => return e,
+                },
+                _ => return syntax_error(),
+            }
+        }
+        _ => return syntax_error(),
+    };
+
+    let key = &args[1];
+    let value = match ks.db().get(key).map(Value::as_string) {
+        Some(Some(value)) => value,
+        Some(None) => return wrong_type(),
+        None => return Frame::Null,
+    };
+    match expiry {
+        Expiry::Keep => {}
+        Expiry::Clear => {
+            if ks.db().persist(key) {
+                ks.notify(Events::GENERIC, "persist", key);
+            }
+        }
+        Expiry::At(at) if at <= now_ms() => {
+            ks.db().remove(key);
+            ks.notify(Events::GENERIC, "del", key);
+        }
+        Expiry::At(at) => {
+            ks.db().set_expiry(key, at);
+            ks.notify(Events::GENERIC, "expire", key);
+        }
+    }
+    Frame::Bulk(value)
+}
+
+/// GETDEL key
+fn getdel(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let key = &args[1];
+    match ks.db().get(key).map(Value::as_string) {
+        Some(Some(value)) => {
+            ks.db().remove(key);
+            ks.notify(Events::GENERIC, "del", key);
+            Frame::Bulk(value)
+        }
+        Some(None) => wrong_type(),
+        None => Frame::Null,
+    }
+}
+
+/// SETNX key value
+///
+/// SET NX from before SET took options: replies 1 if it set the key, or 0
+/// if the key already existed.
+fn setnx(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    if ks.db().contains(&args[1]) {
+        return Frame::Integer(0);
+    }
+    ks.db()
+        .insert(args[1].clone(), Value::string(args[2].clone()));
+    ks.notify(Events::STRING, "set", &args[1]);
+    Frame::Integer(1)
+}
+
+/// SETEX key seconds value and PSETEX key milliseconds value
+///
+/// SET with EX or PX.
+fn setex(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let command = args[0].to_ascii_lowercase();
+    let unit: &[u8] = if &command[..] == b"psetex" {
+        b"PX"
+    } else {
+        b"EX"
+    };
+    let at = match expire_at(&String::from_utf8_lossy(&command), unit, &args[2]) {
+        Ok(at) => at,
+        Err(e) => return e,
+    };
+    let key = &args[1];
+    ks.db().insert(key.clone(), Value::string(args[3].clone()));
+    ks.db().set_expiry(key, at);
+    ks.notify(Events::STRING, "set", key);
+    ks.notify(Events::GENERIC, "expire", key);
+    ok()
+}
+
+/// GETSET key value
+///
+/// SET with GET: replies with the old value, or nil if there was none.
+fn getset(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let key = &args[1];
+    let old = match ks.db().get(key).map(Value::as_string) {
+        Some(Some(old)) => Frame::Bulk(old),
+        Some(None) => return wrong_type(),
+        None => Frame::Null,
+    };
+    ks.db().insert(key.clone(), Value::string(args[2].clone()));
+    ks.notify(Events::STRING, "set", key);
+    old
+}
+
+/// MGET key [key ...]
+///
+/// Keys that are missing or don't hold a string come back as nil.
+fn mget(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let values = args[1..]
+        .iter()
+        .map(|key| match ks.db().get(key).and_then(Value::as_string) {
+            Some(value) => Frame::Bulk(value),
+            None => Frame::Null,
+        })
+        .collect();
+    Frame::Array(values)
+}
+
+/// MSET key value [key value ...] and MSETNX, which sets nothing at all if
+/// any of the keys already exists.
+///
+/// The keyspace runs one command at a time, so either way no other client
+/// can see some of the keys set and not others.
+fn mset(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    if args.len().is_multiple_of(2) {
+        return wrong_arity(&String::from_utf8_lossy(&args[0]).to_ascii_lowercase());
+    }
+    let nx = args[0].eq_ignore_ascii_case(b"msetnx");
+    let pairs = args[1..].chunks(2);
+
+    if nx && args[1..].iter().step_by(2).any(|key| ks.db().contains(key)) {
+        return Frame::Integer(0);
+    }
+    for pair in pairs {
+        ks.db()
+            .insert(pair[0].clone(), Value::string(pair[1].clone()));
+        ks.notify(Events::STRING, "set", &pair[0]);
+    }
+    if nx {
+        Frame::Integer(1)
+    } else {
+        ok()
+    }
+}
+
+/// INCR key, DECR key, INCRBY key increment and DECRBY key decrement.
+///
+/// A missing key counts as 0. The key keeps its expiry.
+fn incr(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let command = args[0].to_ascii_lowercase();
+    let by = match &command[..] {
+        b"incr" | b"decr" => 1,
+        _ => match parse_int(&args[2]) {
+            Ok(by) => by,
+            Err(e) => return e,
+        },
+    };
+    let by = match &command[..] {
+        b"decr" | b"decrby" => match by.checked_neg() {
+            Some(by) => by,
+            None => return error("ERR decrement would overflow"),
+        },
+        _ => by,
+    };
+
+    let key = &args[1];
+    let current = match ks.db().get(key) {
+        None => 0,
+        Some(&Value::Int(n)) => n,
+        Some(value) => match value.as_string() {
+            Some(s) => match parse_int(&s) {
+                Ok(n) => n,
+                Err(e) => return e,
+            },
+            None => return wrong_type(),
+        },
+    };
+    let n = match current.checked_add(by) {
+        Some(n) => n,
+        None => return error("ERR increment or decrement would overflow"),
+    };
+    ks.db().insert_keep_ttl(key.clone(), Value::Int(n));
+    ks.notify(Events::STRING, "incrby", key);
+    Frame::Integer(n)
+}
+
+/// INCRBYFLOAT key increment
+fn incrbyfloat(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let by = match parse_float(&args[2]) {
+        Ok(by) => by,
+        Err(e) => return e,
+    };
+    let key = &args[1];
+    let current = match ks.db().get(key).map(Value::as_string) {
+        None => 0.0,
+        Some(Some(s)) => match parse_float(&s) {
+            Ok(n) => n,
+            Err(e) => return e,
+        },
+        Some(None) => return wrong_type(),
+    };
+
+    let n = current + by;
+    if !n.is_finite() {
+        return error("ERR increment would produce NaN or Infinity");
+    }
+    let text = Bytes::from(format_human_float(n));
+    ks.db()
+        .insert_keep_ttl(key.clone(), Value::string(text.clone()));
+    ks.notify(Events::STRING, "incrbyfloat", key);
+    Frame::Bulk(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_nx_and_xx_go_by_any_type_of_key() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        ks.command(client, &["rpush", "k", "a"]);
+
+        assert_eq!(ks.command(client, &["set", "k", "v", "nx"]), Frame::Null);
+        assert_eq!(
+            ks.command(client, &["lrange", "k", "0", "-1"]),
+            Frame::Array(vec![Frame::Bulk(Bytes::from("a"))])
+        );
+
+        assert_eq!(ks.command(client, &["set", "k", "v", "xx"]), ok());
+        assert_eq!(
+            ks.command(client, &["get", "k"]),
+            Frame::Bulk(Bytes::from("v"))
+        );
+    }
+
+    #[test]
+    fn incrbyfloat_replies_without_exponent_or_rounding_error() {
+        let mut ks = Keyspace::testing();
+        let (client, _) = ks.test_client();
+        let incr =
+            |ks: &mut Keyspace, key: &str, by: &str| ks.command(client, &["incrbyfloat", key, by]);
+        let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string()));
+
+        ks.command(client, &["set", "k", "0.1"]);
+        assert_eq!(incr(&mut ks, "k", "0.2"), bulk("0.3"));
+        assert_eq!(ks.command(client, &["get", "k"]), bulk("0.3"));
+        assert_eq!(incr(&mut ks, "big", "1e20"), bulk("100000000000000000000"));
+        assert_eq!(incr(&mut ks, "n", "-2.5"), bulk("-2.5"));
+        assert_eq!(incr(&mut ks, "n", "2.5"), bulk("0"));
+        assert_eq!(incr(&mut ks, "small", "1.5e-5"), bulk("0.000015"));
+        assert_eq!(incr(&mut ks, "tiny", "1e-20"), bulk("0"));
+        assert_eq!(
+            ks.command(client, &["hincrbyfloat", "h", "f", "10.5"]),
+            bulk("10.5")
+        );
+        assert_eq!(
+            ks.command(client, &["hincrbyfloat", "h", "f", "0.1"]),
+            bulk("10.6")
+        );
+    }
+}
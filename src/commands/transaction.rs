@@ -0,0 +1,66 @@
+//! Transactions: MULTI, EXEC and DISCARD.
+//!
+//! The queueing itself happens in `Keyspace::execute`, which checks each
+//! command as it arrives and queues it if it may run. Errors the commands
+//! give once they run, such as WRONGTYPE, don't stop the rest; they are
+//! simply among EXEC's replies, as in Redis.
+
+use bytes::Bytes;
+
+use super::{error, ok, Command};
+use crate::client::{ClientId, Transaction};
+use crate::keyspace::Keyspace;
+use crate::resp::Frame;
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "multi",
+        arity: 1,
+        subcommands: false,
+        handler: multi,
+    },
+    Command {
+        name: "exec",
+        arity: 1,
+        subcommands: false,
+        handler: exec,
+    },
+    Command {
+        name: "discard",
+        arity: 1,
+        subcommands: false,
+        handler: discard,
+    },
+];
+
+/// MULTI
+fn multi(ks: &mut Keyspace, client: ClientId, _: &[Bytes]) -> Frame {
+    let info = ks.clients.get_mut(&client).unwrap();
+    if info.transaction.is_some() {
+        return error("ERR MULTI calls can not be nested");
+    }
+    info.transaction = Some(Transaction::default());
+    ok()
+}
+
+/// EXEC
+///
+/// Replies with an array of the queued commands' replies.
+fn exec(ks: &mut Keyspace, client: ClientId, _: &[Bytes]) -> Frame {
+    let transaction = match ks.clients.get_mut(&client).unwrap().transaction.take() {
+        Some(transaction) => transaction,
+        None => return error("ERR EXEC without MULTI"),
+    };
+    if transaction.aborted {
+        return error("EXECABORT Transaction discarded because of previous errors.");
+    }
+    Frame::Array(ks.run_transaction(client, &transaction.commands))
+}
+
+/// DISCARD
+fn discard(ks: &mut Keyspace, client: ClientId, _: &[Bytes]) -> Frame {
+    match ks.clients.get_mut(&client).unwrap().transaction.take() {
+        Some(_) => ok(),
+        None => error("ERR DISCARD without MULTI"),
+    }
+}
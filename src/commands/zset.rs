@@ -0,0 +1,585 @@
+//! Commands on sorted set values.
+//!
+//! See `crate::zset` for how they are stored. As with the other
+//! collections, a sorted set whose last member is removed is deleted.
+
+use bytes::Bytes;
+
+use super::{
+    error, format_float, index_range, lossy, parse_int, parse_timeout, syntax_error, wrong_arity,
+    wrong_type, Command, MultiPop,
+};
+use crate::client::ClientId;
+use crate::db::Value;
+use crate::keyspace::Keyspace;
+use crate::notify::Events;
+use crate::resp::Frame;
+use crate::zset::{LexBound, LexRange, ScoreBound, ScoreRange, ZSet};
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "zadd",
+        arity: -4,
+        subcommands: false,
+        handler: zadd,
+    },
+    Command {
+        name: "zincrby",
+        arity: 4,
+        subcommands: false,
+        handler: zincrby,
+    },
+    Command {
+        name: "zscore",
+        arity: 3,
+        subcommands: false,
+        handler: zscore,
+    },
+    Command {
+        name: "zrank",
+        arity: -3,
+        subcommands: false,
+        handler: zrank,
+    },
+    Command {
+        name: "zrevrank",
+        arity: -3,
+        subcommands: false,
+        handler: zrank,
+    },
+    Command {
+        name: "zrange",
+        arity: -4,
+        subcommands: false,
+        handler: zrange,
+    },
+    Command {
+        name: "zcard",
+        arity: 2,
+        subcommands: false,
+        handler: zcard,
+    },
+    Command {
+        name: "zrem",
+        arity: -3,
+        subcommands: false,
+        handler: zrem,
+    },
+    Command {
+        name: "zpopmin",
+        arity: -2,
+        subcommands: false,
+        handler: zpop,
+    },
+    Command {
+        name: "zpopmax",
+        arity: -2,
+        subcommands: false,
+        handler: zpop,
+    },
+    Command {
+        name: "bzpopmin",
+        arity: -3,
+        subcommands: false,
+        handler: bzpop,
+    },
+    Command {
+        name: "bzpopmax",
+        arity: -3,
+        subcommands: false,
+        handler: bzpop,
+    },
+    Command {
+        name: "zmpop",
+        arity: -4,
+        subcommands: false,
+        handler: zmpop,
+    },
+    Command {
+        name: "bzmpop",
+        arity: -5,
+        subcommands: false,
+        handler: zmpop,
+    },
+];
+
+/// The sorted set at `key`, or `None` if there is no such key. A key
+/// holding another type is an error reply.
+pub(super) fn zset<'a>(ks: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut ZSet>, Frame> {
+    match ks.db().get_mut(key) {
+        None => Ok(None),
+        Some(Value::ZSet(zset)) => Ok(Some(zset)),
+        Some(_) => Err(wrong_type()),
+    }
+}
+
+/// Like `zset`, but a missing key gets a new, empty sorted set.
+pub(super) fn zset_or_new<'a>(ks: &'a mut Keyspace, key: &Bytes) -> Result<&'a mut ZSet, Frame> {
+    if !ks.db().contains(key) {
+        ks.db().insert(key.clone(), Value::ZSet(ZSet::default()));
+    }
+    zset(ks, key).map(|zset| zset.unwrap())
+}
+
+/// Deletes `key` if it holds a sorted set that has been emptied.
+fn remove_if_empty(ks: &mut Keyspace, key: &[u8]) {
+    if matches!(ks.db().get(key), Some(Value::ZSet(zset)) if zset.is_empty()) {
+        ks.db().remove(key);
+        ks.notify(Events::GENERIC, "del", key);
+    }
+}
+
+/// The keyspace event for popping from one end of a sorted set.
+fn pop_event(max: bool) -> &'static str {
+    if max {
+        "zpopmax"
+    } else {
+        "zpopmin"
+    }
+}
+
+/// Parses a score. Unlike other floats, scores may be infinite.
+fn parse_score(arg: &[u8]) -> Result<f64, Frame> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|f| !f.is_nan())
+        .ok_or_else(|| error("ERR value is not a valid float"))
+}
+
+fn score_reply(score: f64) -> Frame {
+    Frame::Bulk(format_float(score).into())
+}
+
+/// Parses one end of a ZRANGE BYSCORE range: a score, excluded from the
+/// range if it starts with '('.
+fn parse_score_bound(arg: &[u8]) -> Result<ScoreBound, Frame> {
+    let (exclusive, score) = match arg.first() {
+        Some(b'(') => (true, &arg[1..]),
+        _ => (false, arg),
+    };
+    match parse_score(score) {
+        Ok(score) => Ok(ScoreBound { score, exclusive }),
+        Err(_) => Err(error("ERR min or max is not a float")),
+    }
+}
+
+/// Parses one end of a ZRANGE BYLEX range: '-', '+', or a member prefixed
+/// with '[' to include it or '(' to exclude it.
+fn parse_lex_bound(arg: &Bytes) -> Result<LexBound, Frame> {
+    match arg.first() {
+        Some(b'-') if arg.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if arg.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(arg.slice_from(1))),
+        Some(b'(') => Ok(LexBound::Exclusive(arg.slice_from(1))),
+        _ => Err(error("ERR min or max not valid string range item")),
+    }
+}
+
+/// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+///
+/// NX only adds new members and XX only updates existing ones. GT and LT
+/// only update a member if its new score is greater or less than its
+/// current one; new members are still added. Replies with how many members
+/// were added, or with CH, added or given a new score. With INCR, which
+/// takes a single pair, the score is added to the member's current one,
+/// and the reply is the new score, or nil if the options prevented it.
+fn zadd(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let (mut nx, mut xx, mut gt, mut lt, mut ch, mut incr) =
+        (false, false, false, false, false, false);
+    let mut i = 2;
+    while i < args.len() {
+        match lossy(&args[i]).to_ascii_lowercase().as_str() {
+            "nx" => nx = true,
+            "xx" => xx = true,
+            "gt" => gt = true,
+            "lt" => lt = true,
+            "ch" => ch = true,
+            "incr" => incr = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    let pairs = &args[i..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return syntax_error();
+    }
+    if nx && xx {
+        return error("ERR XX and NX options at the same time are not compatible");
+    }
+    if (gt && lt) || (nx && (gt || lt)) {
+        return error("ERR GT, LT, and/or NX options at the same time are not compatible");
+    }
+    if incr && pairs.len() > 2 {
+        return error("ERR INCR option supports a single increment-element pair");
+    }
+    let mut scores = Vec::with_capacity(pairs.len() / 2);
+    for pair in pairs.chunks(2) {
+        match parse_score(&pair[0]) {
+            Ok(score) => scores.push(score),
+            Err(e) => return e,
+        }
+    }
+
+    let key = &args[1];
+    let nothing = if incr { Frame::Null } else { Frame::Integer(0) };
+    match zset(ks, key) {
+        Ok(None) if xx => return nothing,
+        Ok(_) => {}
+        Err(e) => return e,
+    }
+    let zset = zset_or_new(ks, key).unwrap();
+
+    let (mut added, mut updated) = (0, 0);
+    let mut result = None;
+    for (pair, &score) in pairs.chunks(2).zip(&scores) {
+        let member = &pair[1];
+        match zset.score(member) {
+            Some(current) => {
+                if nx {
+                    continue;
+                }
+                let score = if incr { current + score } else { score };
+                if score.is_nan() {
+                    return error("ERR resulting score is not a number (NaN)");
+                }
+                if (gt && score <= current) || (lt && score >= current) {
+                    continue;
+                }
+                if score != current {
+                    zset.insert(member.clone(), score);
+                    updated += 1;
+                }
+                result = Some(score);
+            }
+            None => {
+                if xx {
+                    continue;
+                }
+                zset.insert(member.clone(), score);
+                added += 1;
+                result = Some(score);
+            }
+        }
+    }
+    ks.signal_ready(key);
+    if added + updated > 0 {
+        ks.notify(Events::ZSET, if incr { "zincr" } else { "zadd" }, key);
+    }
+
+    if incr {
+        return result.map_or(Frame::Null, score_reply);
+    }
+    Frame::Integer(if ch { added + updated } else { added })
+}
+
+/// ZINCRBY key increment member
+///
+/// A missing member counts as having a score of 0.
+fn zincrby(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let by = match parse_score(&args[2]) {
+        Ok(by) => by,
+        Err(e) => return e,
+    };
+    let zset = match zset_or_new(ks, &args[1]) {
+        Ok(zset) => zset,
+        Err(e) => return e,
+    };
+    let score = zset.score(&args[3]).unwrap_or(0.0) + by;
+    if score.is_nan() {
+        return error("ERR resulting score is not a number (NaN)");
+    }
+    zset.insert(args[3].clone(), score);
+    ks.signal_ready(&args[1]);
+    ks.notify(Events::ZSET, "zincr", &args[1]);
+    score_reply(score)
+}
+
+/// ZSCORE key member
+fn zscore(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match zset(ks, &args[1]) {
+        Ok(zset) => match zset.and_then(|zset| zset.score(&args[2])) {
+            Some(score) => score_reply(score),
+            None => Frame::Null,
+        },
+        Err(e) => e,
+    }
+}
+
+/// ZRANK key member [WITHSCORE] and ZREVRANK, which counts from the highest
+/// score.
+fn zrank(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let with_score = match args.len() {
+        3 => false,
+        4 if args[3].eq_ignore_ascii_case(b"WITHSCORE") => true,
+        _ => return syntax_error(),
+    };
+    let rev = args[0].eq_ignore_ascii_case(b"zrevrank");
+    let zset = match zset(ks, &args[1]) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return Frame::Null,
+        Err(e) => return e,
+    };
+    match zset.rank(&args[2], rev) {
+        Some(rank) if with_score => Frame::Array(vec![
+            Frame::Integer(rank as i64),
+            score_reply(zset.score(&args[2]).unwrap()),
+        ]),
+        Some(rank) => Frame::Integer(rank as i64),
+        None => Frame::Null,
+    }
+}
+
+/// What ZRANGE's start and stop are.
+enum Range {
+    Index(i64, i64),
+    Score(ScoreRange),
+    Lex(LexRange),
+}
+
+/// ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count]
+/// [WITHSCORES]
+///
+/// By default start and stop are ranks, which may count back from the end
+/// like LRANGE's indexes. BYSCORE and BYLEX take them as score and member
+/// bounds instead; with REV, the higher bound comes first. LIMIT, which
+/// only goes with those two, skips `offset` elements and returns at most
+/// `count`, or all of them if it's negative.
+fn zrange(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let (mut by_score, mut by_lex, mut rev, mut with_scores) = (false, false, false, false);
+    let mut limit = None;
+    let mut i = 4;
+    while i < args.len() {
+        match lossy(&args[i]).to_ascii_lowercase().as_str() {
+            "byscore" => by_score = true,
+            "bylex" => by_lex = true,
+            "rev" => rev = true,
+            "withscores" => with_scores = true,
+            "limit" if i + 2 < args.len() => {
+                let offset = match parse_int(&args[i + 1]) {
+                    Ok(offset) => offset,
+                    Err(e) => return e,
+                };
+                let count = match parse_int(&args[i + 2]) {
+                    Ok(count) => count,
+                    Err(e) => return e,
+                };
+                limit = Some((offset, count));
+                i += 2;
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+    if by_score && by_lex {
+        return syntax_error();
+    }
+    if limit.is_some() && !by_score && !by_lex {
+        return error(
+            "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+        );
+    }
+    if with_scores && by_lex {
+        return error("ERR syntax error, WITHSCORES not supported in combination with BYLEX");
+    }
+
+    let (low, high) = if rev && (by_score || by_lex) {
+        (&args[3], &args[2])
+    } else {
+        (&args[2], &args[3])
+    };
+    let range = if by_score {
+        match (parse_score_bound(low), parse_score_bound(high)) {
+            (Ok(min), Ok(max)) => Range::Score(ScoreRange { min, max }),
+            (Err(e), _) | (_, Err(e)) => return e,
+        }
+    } else if by_lex {
+        match (parse_lex_bound(low), parse_lex_bound(high)) {
+            (Ok(min), Ok(max)) => Range::Lex(LexRange { min, max }),
+            (Err(e), _) | (_, Err(e)) => return e,
+        }
+    } else {
+        match (parse_int(low), parse_int(high)) {
+            (Ok(start), Ok(stop)) => Range::Index(start, stop),
+            (Err(e), _) | (_, Err(e)) => return e,
+        }
+    };
+
+    let zset = match zset(ks, &args[1]) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return Frame::Array(Vec::new()),
+        Err(e) => return e,
+    };
+    let (offset, count) = limit.unwrap_or((0, -1));
+    if offset < 0 {
+        return Frame::Array(Vec::new());
+    }
+    let count = if count < 0 {
+        usize::MAX
+    } else {
+        count as usize
+    };
+    let found: Vec<(&Bytes, f64)> = match range {
+        Range::Index(start, stop) => match index_range(start, stop, zset.len()) {
+            Some((start, stop)) => zset.iter(start, rev).take(stop - start + 1).collect(),
+            None => Vec::new(),
+        },
+        Range::Score(ref range) => zset
+            .iter_from_score(range, rev)
+            .take_while(|&(_, score)| range.contains(score))
+            .skip(offset as usize)
+            .take(count)
+            .collect(),
+        Range::Lex(ref range) => zset
+            .iter_from_lex(range, rev)
+            .take_while(|&(member, _)| range.contains(member))
+            .skip(offset as usize)
+            .take(count)
+            .collect(),
+    };
+
+    let mut elements = Vec::new();
+    for (member, score) in found {
+        elements.push(Frame::Bulk(member.clone()));
+        if with_scores {
+            elements.push(score_reply(score));
+        }
+    }
+    Frame::Array(elements)
+}
+
+/// ZCARD key
+fn zcard(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    match zset(ks, &args[1]) {
+        Ok(zset) => Frame::Integer(zset.map_or(0, |zset| zset.len()) as i64),
+        Err(e) => e,
+    }
+}
+
+/// ZREM key member [member ...]
+fn zrem(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let key = &args[1];
+    let zset = match zset(ks, key) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return Frame::Integer(0),
+        Err(e) => return e,
+    };
+    let removed = args[2..]
+        .iter()
+        .filter(|member| zset.remove(member).is_some())
+        .count();
+    if removed > 0 {
+        ks.notify(Events::ZSET, "zrem", key);
+    }
+    remove_if_empty(ks, key);
+    Frame::Integer(removed as i64)
+}
+
+/// ZPOPMIN key [count] and ZPOPMAX key [count]
+///
+/// Removes up to `count` members, 1 by default, with the lowest or highest
+/// scores, replying with them and their scores in one flat array.
+fn zpop(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let max = args[0].eq_ignore_ascii_case(b"zpopmax");
+    let count = match args.len() {
+        2 => 1,
+        3 => match parse_int(&args[2]) {
+            Ok(n) if n >= 0 => n as usize,
+            Ok(_) => return error("ERR value is out of range, must be positive"),
+            Err(e) => return e,
+        },
+        _ => return wrong_arity(if max { "zpopmax" } else { "zpopmin" }),
+    };
+    let key = &args[1];
+    let zset = match zset(ks, key) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return Frame::Array(Vec::new()),
+        Err(e) => return e,
+    };
+    let mut elements = Vec::new();
+    for _ in 0..count {
+        match zset.pop(max) {
+            Some((member, score)) => {
+                elements.push(Frame::Bulk(member));
+                elements.push(score_reply(score));
+            }
+            None => break,
+        }
+    }
+    if !elements.is_empty() {
+        ks.notify(Events::ZSET, pop_event(max), key);
+    }
+    remove_if_empty(ks, key);
+    Frame::Array(elements)
+}
+
+/// BZPOPMIN key [key ...] timeout and BZPOPMAX key [key ...] timeout
+///
+/// Pops from the first of the sorted sets that exists, replying with its
+/// key, the member and its score. If none of them do, blocks until one is
+/// added, or replies with a nil array once the timeout passes.
+fn bzpop(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let max = args[0].eq_ignore_ascii_case(b"bzpopmax");
+    let (keys, timeout) = args[1..].split_at(args.len() - 2);
+    let timeout = match parse_timeout(&timeout[0]) {
+        Ok(timeout) => timeout,
+        Err(e) => return e,
+    };
+
+    for key in keys {
+        let zset = match zset(ks, key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => continue,
+            Err(e) => return e,
+        };
+        let (member, score) = zset.pop(max).unwrap();
+        ks.notify(Events::ZSET, pop_event(max), key);
+        remove_if_empty(ks, key);
+        return Frame::Array(vec![
+            Frame::Bulk(key.clone()),
+            Frame::Bulk(member),
+            score_reply(score),
+        ]);
+    }
+    ks.block(keys.to_vec(), timeout);
+    Frame::NullArray
+}
+
+/// ZMPOP numkeys key [key ...] <MIN | MAX> [COUNT count] and BZMPOP
+/// timeout numkeys key [key ...] <MIN | MAX> [COUNT count]
+///
+/// Pops up to `count` members, by default one, from the first of the
+/// sorted sets that exists, replying with its key and an array of member
+/// and score pairs, or with a nil array if none do. BZMPOP blocks until
+/// one does, as BZPOPMIN does.
+fn zmpop(ks: &mut Keyspace, _: ClientId, args: &[Bytes]) -> Frame {
+    let blocking = args[0].eq_ignore_ascii_case(b"bzmpop");
+    let timeout = match blocking.then(|| parse_timeout(&args[1])) {
+        None => None,
+        Some(Ok(timeout)) => Some(timeout),
+        Some(Err(e)) => return e,
+    };
+    let pop = match MultiPop::parse(&args[1 + blocking as usize..], ["min", "max"]) {
+        Ok(pop) => pop,
+        Err(e) => return e,
+    };
+
+    for key in pop.keys {
+        let zset = match zset(ks, key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => continue,
+            Err(e) => return e,
+        };
+        let elements = (0..pop.count)
+            .map_while(|_| zset.pop(!pop.first))
+            .map(|(member, score)| Frame::Array(vec![Frame::Bulk(member), score_reply(score)]))
+            .collect();
+        ks.notify(Events::ZSET, pop_event(!pop.first), key);
+        remove_if_empty(ks, key);
+        return Frame::Array(vec![Frame::Bulk(key.clone()), Frame::Array(elements)]);
+    }
+    if let Some(timeout) = timeout {
+        ks.block(pop.keys.to_vec(), timeout);
+    }
+    Frame::NullArray
+}
@@ -0,0 +1,402 @@
+//! Server configuration.
+//!
+//! Settings come from the command line in the same shape `redis-server`
+//! accepts them: an optional listen address followed by `--name value`
+//! pairs, e.g.
+//!
+//!     rust-rettuce 127.0.0.1:6379 --rate-limit-cmds 1000 --rate-limit-scope ip
+//!
+//! `--config <file>` reads the same settings from a file, one per line, and
+//! `--check-config` validates everything and exits without starting.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::client::ClientClass;
+use crate::ipfilter::{self, IpFilter};
+use crate::notify::Events;
+
+/// Whether a rate limit applies to each connection on its own or is shared
+/// by every connection from the same source IP.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitScope {
+    Client,
+    Ip,
+}
+
+impl FromStr for RateLimitScope {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "client" => Ok(RateLimitScope::Client),
+            "ip" => Ok(RateLimitScope::Ip),
+            _ => Err(ConfigError(format!(
+                "rate-limit-scope must be 'client' or 'ip', got '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// How much unsent reply data a client may accumulate. Going over `hard`
+/// disconnects it at once; staying over `soft` for `soft_seconds` does too.
+/// A zero limit is not enforced.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutputBufferLimit {
+    pub hard: usize,
+    pub soft: usize,
+    pub soft_seconds: u64,
+}
+
+impl FromStr for OutputBufferLimit {
+    type Err = ConfigError;
+
+    /// Parses the `<hard> <soft> <soft-seconds>` part of a
+    /// `client-output-buffer-limit` setting.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 3 {
+            return Err(ConfigError::new(
+                "expected <hard limit> <soft limit> <soft seconds>",
+            ));
+        }
+        Ok(OutputBufferLimit {
+            hard: parse_memory("hard limit", fields[0])?,
+            soft: parse_memory("soft limit", fields[1])?,
+            soft_seconds: parse("soft seconds", fields[2])?,
+        })
+    }
+}
+
+/// Output buffer limits for each client class, with Redis' defaults.
+#[derive(Clone, Copy, Debug)]
+pub struct OutputBufferLimits {
+    pub normal: OutputBufferLimit,
+    pub replica: OutputBufferLimit,
+    pub pubsub: OutputBufferLimit,
+}
+
+impl OutputBufferLimits {
+    pub fn get(&self, class: ClientClass) -> OutputBufferLimit {
+        match class {
+            ClientClass::Normal => self.normal,
+            ClientClass::Replica => self.replica,
+            ClientClass::Pubsub => self.pubsub,
+        }
+    }
+
+    fn get_mut(&mut self, class: ClientClass) -> &mut OutputBufferLimit {
+        match class {
+            ClientClass::Normal => &mut self.normal,
+            ClientClass::Replica => &mut self.replica,
+            ClientClass::Pubsub => &mut self.pubsub,
+        }
+    }
+}
+
+impl Default for OutputBufferLimits {
+    fn default() -> OutputBufferLimits {
+        const MB: usize = 1024 * 1024;
+        OutputBufferLimits {
+            normal: OutputBufferLimit {
+                hard: 0,
+                soft: 0,
+                soft_seconds: 0,
+            },
+            replica: OutputBufferLimit {
+                hard: 256 * MB,
+                soft: 64 * MB,
+                soft_seconds: 60,
+            },
+            pubsub: OutputBufferLimit {
+                hard: 32 * MB,
+                soft: 8 * MB,
+                soft_seconds: 60,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub addr: String,
+
+    /// Commands per second a client (or IP) may issue; 0 disables the limit.
+    pub rate_limit_cmds: u64,
+    /// Bytes per second a client (or IP) may send; 0 disables the limit.
+    pub rate_limit_bytes: u64,
+    /// How many seconds worth of traffic may be spent in a single burst.
+    pub rate_limit_burst: f64,
+    pub rate_limit_scope: RateLimitScope,
+
+    /// Most clients connected at once; connections beyond it are refused.
+    pub maxclients: usize,
+
+    /// Seconds a client may sit idle before it is disconnected; 0 disables
+    /// the timeout.
+    pub timeout: u64,
+
+    /// Interval in seconds for TCP keepalive probes on client sockets; 0
+    /// leaves keepalive off.
+    pub tcp_keepalive: u64,
+    /// Whether to disable Nagle's algorithm on client sockets, trading a
+    /// little bandwidth for lower latency.
+    pub tcp_nodelay: bool,
+
+    /// Limits on reply data queued for slow readers, per client class.
+    pub output_buffer_limits: OutputBufferLimits,
+
+    /// How many numbered databases there are for SELECT to choose from.
+    pub databases: usize,
+
+    /// Which changes to keys are published as keyspace notifications; see
+    /// the `notify` module. None by default.
+    pub notify_keyspace_events: Events,
+
+    /// Seconds to let sessions drain on SIGINT/SIGTERM before exiting anyway.
+    pub shutdown_timeout: u64,
+
+    /// Source addresses allowed to connect, and those turned away.
+    pub ip_filter: IpFilter,
+
+    /// Set by `--check-config`: validate everything, then exit without
+    /// binding any port.
+    pub check_only: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            addr: "127.0.0.1:8080".to_string(),
+            maxclients: 10000,
+            timeout: 0,
+            tcp_keepalive: 300,
+            tcp_nodelay: true,
+            output_buffer_limits: OutputBufferLimits::default(),
+            databases: 16,
+            notify_keyspace_events: Events::default(),
+            shutdown_timeout: 10,
+            rate_limit_cmds: 0,
+            rate_limit_bytes: 0,
+            rate_limit_burst: 1.0,
+            rate_limit_scope: RateLimitScope::Client,
+            ip_filter: IpFilter::default(),
+            check_only: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError(String);
+
+impl ConfigError {
+    pub fn new<S: Into<String>>(msg: S) -> ConfigError {
+        ConfigError(msg.into())
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ConfigError {}
+
+impl Config {
+    /// Builds a config from process arguments, skipping the program name.
+    ///
+    /// Rather than stopping at the first problem, every bad argument and
+    /// config file line is reported, so a whole file can be fixed in one go.
+    pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<Config, Vec<ConfigError>> {
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        let mut args = args.skip(1).peekable();
+
+        if let Some(first) = args.peek() {
+            if !first.starts_with("--") {
+                config.addr = args.next().unwrap();
+            }
+        }
+
+        while let Some(arg) = args.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name.to_string(),
+                None => {
+                    errors.push(ConfigError(format!("unexpected argument '{}'", arg)));
+                    continue;
+                }
+            };
+            if name == "check-config" {
+                config.check_only = true;
+                continue;
+            }
+            let value = match args.next() {
+                Some(value) => value,
+                None => {
+                    errors.push(ConfigError(format!("missing value for '--{}'", name)));
+                    break;
+                }
+            };
+            if name == "config" {
+                config.load_file(&value, &mut errors);
+            } else if let Err(e) = config.set(&name, &value) {
+                errors.push(ConfigError(format!("--{}: {}", name, e)));
+            }
+        }
+
+        errors.extend(config.validate());
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Applies a `redis.conf`-style file: one `name value` setting per line,
+    /// with blank lines and `#` comments ignored.
+    fn load_file(&mut self, path: &str, errors: &mut Vec<ConfigError>) {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                errors.push(ConfigError(format!("{}: {}", path, e)));
+                return;
+            }
+        };
+
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = match line.find(char::is_whitespace) {
+                Some(i) => (&line[..i], line[i..].trim()),
+                None => (line, ""),
+            };
+            let value = value.trim_matches('"');
+            if let Err(e) = self.set(name, value) {
+                errors.push(ConfigError(format!("{}:{}: {}", path, n + 1, e)));
+            }
+        }
+    }
+
+    /// Checks that need the whole config rather than one setting at a time.
+    fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        if self.addr.parse::<SocketAddr>().is_err() {
+            errors.push(ConfigError(format!(
+                "bind address '{}' is not a valid ip:port",
+                self.addr
+            )));
+        }
+        // A bucket that can't hold a whole command would refuse every one.
+        if self.rate_limit_cmds > 0 && self.rate_limit_cmds as f64 * self.rate_limit_burst < 1.0 {
+            errors.push(ConfigError(format!(
+                "rate-limit-cmds {} with rate-limit-burst {} allows less than one command at a time",
+                self.rate_limit_cmds, self.rate_limit_burst
+            )));
+        }
+        errors
+    }
+
+    /// Applies a single `name value` setting.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+        match name.to_ascii_lowercase().as_str() {
+            "bind" => self.addr = value.to_string(),
+            "maxclients" => {
+                self.maxclients = parse(name, value)?;
+                if self.maxclients == 0 {
+                    return Err(ConfigError::new("maxclients must be at least 1"));
+                }
+            }
+            "timeout" => self.timeout = parse(name, value)?,
+            "tcp-keepalive" => self.tcp_keepalive = parse(name, value)?,
+            "tcp-nodelay" => self.tcp_nodelay = parse_bool(name, value)?,
+            // One class per setting, e.g. "pubsub 32mb 8mb 60"; give it
+            // several times to configure several classes.
+            "client-output-buffer-limit" => {
+                let (class, limit) = match value.find(char::is_whitespace) {
+                    Some(i) => (&value[..i], &value[i..]),
+                    None => (value, ""),
+                };
+                let class: ClientClass = class.parse().map_err(ConfigError)?;
+                *self.output_buffer_limits.get_mut(class) = limit.parse()?;
+            }
+            "databases" => {
+                self.databases = parse(name, value)?;
+                if self.databases == 0 {
+                    return Err(ConfigError::new("databases must be at least 1"));
+                }
+            }
+            "notify-keyspace-events" => self.notify_keyspace_events = value.parse()?,
+            "shutdown-timeout" => self.shutdown_timeout = parse(name, value)?,
+            "rate-limit-cmds" => self.rate_limit_cmds = parse(name, value)?,
+            "rate-limit-bytes" => self.rate_limit_bytes = parse(name, value)?,
+            "rate-limit-burst" => {
+                let burst: f64 = parse(name, value)?;
+                if burst <= 0.0 {
+                    return Err(ConfigError("rate-limit-burst must be positive".to_string()));
+                }
+                self.rate_limit_burst = burst;
+            }
+            "rate-limit-scope" => self.rate_limit_scope = value.parse()?,
+            // Both lists accumulate, so they can be given several times.
+            "allow" => self.ip_filter.allow.extend(ipfilter::parse_list(value)?),
+            "deny" => self.ip_filter.deny.extend(ipfilter::parse_list(value)?),
+            _ => return Err(ConfigError(format!("unknown setting '{}'", name))),
+        }
+        Ok(())
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError(format!("invalid value '{}' for '{}'", value, name)))
+}
+
+/// Parses a memory size the way redis.conf writes them: a plain number of
+/// bytes, or one suffixed with k, kb, m, mb, g or gb. The bare letters are
+/// powers of 1000 and the `b` forms powers of 1024.
+fn parse_memory(name: &str, value: &str) -> Result<usize, ConfigError> {
+    let lower = value.to_ascii_lowercase();
+    let split = lower
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(lower.len());
+    let (digits, unit) = lower.split_at(split);
+    let unit = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => {
+            return Err(ConfigError(format!(
+                "invalid value '{}' for '{}'",
+                value, name
+            )))
+        }
+    };
+    let n: usize = parse(name, digits)?;
+    n.checked_mul(unit)
+        .ok_or_else(|| ConfigError(format!("value '{}' for '{}' is too large", value, name)))
+}
+
+/// Parses a `yes`/`no` flag, as used throughout redis.conf.
+fn parse_bool(name: &str, value: &str) -> Result<bool, ConfigError> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(ConfigError(format!(
+            "'{}' must be 'yes' or 'no', got '{}'",
+            name, value
+        ))),
+    }
+}
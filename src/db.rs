@@ -0,0 +1,611 @@
+//! The data itself: a map from keys to typed values.
+//!
+//! Keys may carry an expiry time, kept as a unix timestamp in milliseconds
+//! so EXPIREAT-style commands can use it directly. An expired key is
+//! removed the first time anything looks at it, and `active_expire` sweeps
+//! up the ones nobody looks at, the same way Redis does: by sampling keys
+//! that have an expiry for ones whose time has passed.
+//!
+//! Hash fields with their own expiry are handled alike: looking a hash up
+//! removes its expired fields first, and `active_expire` samples the
+//! hashes that have such fields too.
+//!
+//! What the database does on its own, expiring keys and fields and
+//! creating keys, is noted as it happens, for the keyspace to publish as
+//! notifications once the command that caused it has run.
+
+use bytes::Bytes;
+use rand::Rng;
+
+use std::collections::{HashMap, VecDeque};
+use std::str;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::dict::Dict;
+use crate::hash::Hash;
+use crate::notify::Events;
+use crate::stream::{Stream, StreamId};
+use crate::zset::ZSet;
+
+/// A stored value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(Bytes),
+    /// A string that holds a canonical 64-bit integer, stored as the number
+    /// so counters don't have to reparse their digits.
+    Int(i64),
+    List(VecDeque<Bytes>),
+    Hash(Hash),
+    Set(Dict<()>),
+    ZSet(ZSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// Stores a string, as an integer if it is one exactly as Redis would
+    /// print it back: no sign other than '-', no leading zeros, no spaces.
+    pub fn string(bytes: Bytes) -> Value {
+        if bytes.len() <= 20 {
+            if let Some(n) = str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.parse::<i64>().ok())
+            {
+                if n.to_string().as_bytes() == &bytes[..] {
+                    return Value::Int(n);
+                }
+            }
+        }
+        Value::String(bytes)
+    }
+
+    /// The type's name, as TYPE and SCAN's TYPE option spell it.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::String(_) | Value::Int(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// How the value is stored, as OBJECT ENCODING reports it. Short strings
+    /// are "embstr", as Redis keeps those in the same allocation as their
+    /// header; here it only tells clients which strings Redis would treat
+    /// that way.
+    pub fn encoding(&self) -> &'static str {
+        match *self {
+            Value::String(ref bytes) if bytes.len() <= EMBSTR_MAX_LEN => "embstr",
+            Value::String(_) => "raw",
+            Value::Int(_) => "int",
+            Value::List(ref list) if is_small_list(list) => "listpack",
+            Value::List(_) => "quicklist",
+            Value::Hash(ref hash) if is_small_hash(hash) && hash.has_expiries() => "listpackex",
+            Value::Hash(ref hash) if is_small_hash(hash) => "listpack",
+            Value::Hash(_) => "hashtable",
+            Value::Set(ref set) if is_intset(set) => "intset",
+            Value::Set(ref set) if is_small_set(set) => "listpack",
+            Value::Set(_) => "hashtable",
+            Value::ZSet(ref zset) if is_small_zset(zset) => "listpack",
+            Value::ZSet(_) => "skiplist",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Whether dropping the value is slow enough to be worth doing on the
+    /// lazyfree thread.
+    pub fn is_large(&self) -> bool {
+        match *self {
+            Value::String(ref bytes) => bytes.len() >= LAZYFREE_MIN_LEN,
+            Value::Int(_) => false,
+            Value::List(ref list) => list.len() > LAZYFREE_MIN_ELEMENTS,
+            Value::Hash(ref hash) => hash.len() > LAZYFREE_MIN_ELEMENTS,
+            Value::Set(ref set) => set.len() > LAZYFREE_MIN_ELEMENTS,
+            Value::ZSet(ref zset) => zset.len() > LAZYFREE_MIN_ELEMENTS,
+            Value::Stream(ref stream) => stream.len() > LAZYFREE_MIN_ELEMENTS,
+        }
+    }
+
+    /// How big the value is, as redis-cli --bigkeys measures it: a
+    /// string's length, or how many elements anything else has.
+    pub fn size(&self) -> usize {
+        match *self {
+            Value::String(ref bytes) => bytes.len(),
+            Value::Int(n) => n.to_string().len(),
+            Value::List(ref list) => list.len(),
+            Value::Hash(ref hash) => hash.len(),
+            Value::Set(ref set) => set.len(),
+            Value::ZSet(ref zset) => zset.len(),
+            Value::Stream(ref stream) => stream.len(),
+        }
+    }
+
+    /// Roughly how many bytes the value takes up: its contents, plus
+    /// `ELEMENT_OVERHEAD` for each element. For a collection, only the first
+    /// `samples` elements are measured, or all of them with 0, and the
+    /// rest assumed to be like them, as MEMORY USAGE does.
+    pub fn memory_usage(&self, samples: usize) -> usize {
+        let sampled = |sizes: &mut dyn Iterator<Item = usize>, len: usize| {
+            let samples = if samples == 0 { len } else { samples };
+            let (mut total, mut seen) = (0, 0);
+            for size in sizes.take(samples) {
+                total += size + ELEMENT_OVERHEAD;
+                seen += 1;
+            }
+            (total * len).checked_div(seen).unwrap_or(0)
+        };
+        let contents = match *self {
+            Value::String(ref bytes) => bytes.len(),
+            Value::Int(_) => 0,
+            Value::List(ref list) => sampled(&mut list.iter().map(Bytes::len), list.len()),
+            Value::Hash(ref hash) => {
+                sampled(&mut hash.iter().map(|(k, v)| k.len() + v.len()), hash.len())
+            }
+            Value::Set(ref set) => sampled(&mut set.keys().map(Bytes::len), set.len()),
+            Value::ZSet(ref zset) => sampled(
+                // Each member is in the skiplist and the map of scores.
+                &mut zset.iter(0, false).map(|(m, _)| 2 * m.len() + 8),
+                zset.len(),
+            ),
+            Value::Stream(ref stream) => sampled(
+                &mut stream
+                    .range(StreamId::MIN, StreamId::MAX)
+                    .map(|(_, fields)| {
+                        16 + fields.iter().map(|(f, v)| f.len() + v.len()).sum::<usize>()
+                    }),
+                stream.len(),
+            ),
+        };
+        VALUE_OVERHEAD + contents
+    }
+
+    /// The value's contents if it is a string, however it is stored.
+    pub fn as_string(&self) -> Option<Bytes> {
+        match *self {
+            Value::String(ref bytes) => Some(bytes.clone()),
+            Value::Int(n) => Some(n.to_string().into()),
+            _ => None,
+        }
+    }
+}
+
+/// What `memory_usage` counts for the value itself, before its contents.
+const VALUE_OVERHEAD: usize = 16;
+
+/// What `memory_usage` counts for each element of a collection besides its
+/// contents: the allocation and links around it.
+const ELEMENT_OVERHEAD: usize = 16;
+
+/// Longest string Redis stores with the "embstr" encoding.
+const EMBSTR_MAX_LEN: usize = 44;
+
+/// Strings at least this long are freed on the lazyfree thread by UNLINK.
+/// Allocations this big are usually mapped separately, so freeing them
+/// means a call into the kernel to unmap them.
+const LAZYFREE_MIN_LEN: usize = 128 * 1024;
+
+/// Collections with more elements than this are freed on the lazyfree
+/// thread by UNLINK: Redis' LAZYFREE_THRESHOLD.
+const LAZYFREE_MIN_ELEMENTS: usize = 64;
+
+/// Lists up to this many elements, none longer than `LISTPACK_MAX_VALUE`,
+/// are ones Redis would pack into a single listpack. The defaults of
+/// `list-max-listpack-size` and `list-max-listpack-value`.
+const LISTPACK_MAX_ENTRIES: usize = 128;
+const LISTPACK_MAX_VALUE: usize = 64;
+
+fn is_small_list(list: &VecDeque<Bytes>) -> bool {
+    list.len() <= LISTPACK_MAX_ENTRIES && list.iter().all(|v| v.len() <= LISTPACK_MAX_VALUE)
+}
+
+/// Likewise for hashes, with `hash-max-listpack-entries` and
+/// `hash-max-listpack-value`. Field names count against the value limit
+/// too.
+const HASH_LISTPACK_MAX_ENTRIES: usize = 128;
+const HASH_LISTPACK_MAX_VALUE: usize = 64;
+
+fn is_small_hash(hash: &Hash) -> bool {
+    hash.len() <= HASH_LISTPACK_MAX_ENTRIES
+        && hash
+            .iter()
+            .all(|(k, v)| k.len() <= HASH_LISTPACK_MAX_VALUE && v.len() <= HASH_LISTPACK_MAX_VALUE)
+}
+
+/// Sets of integers up to `set-max-intset-entries` are ones Redis keeps as
+/// an intset; others within `set-max-listpack-entries` and
+/// `set-max-listpack-value` it keeps as a listpack.
+const INTSET_MAX_ENTRIES: usize = 512;
+const SET_LISTPACK_MAX_ENTRIES: usize = 128;
+const SET_LISTPACK_MAX_VALUE: usize = 64;
+
+fn is_intset(set: &Dict<()>) -> bool {
+    set.len() <= INTSET_MAX_ENTRIES && set.keys().all(|m| parse_int(m).is_some())
+}
+
+fn is_small_set(set: &Dict<()>) -> bool {
+    set.len() <= SET_LISTPACK_MAX_ENTRIES && set.keys().all(|m| m.len() <= SET_LISTPACK_MAX_VALUE)
+}
+
+/// Sorted sets within `zset-max-listpack-entries` and
+/// `zset-max-listpack-value` are ones Redis keeps as a listpack.
+const ZSET_LISTPACK_MAX_ENTRIES: usize = 128;
+const ZSET_LISTPACK_MAX_VALUE: usize = 64;
+
+fn is_small_zset(zset: &ZSet) -> bool {
+    zset.len() <= ZSET_LISTPACK_MAX_ENTRIES
+        && zset
+            .iter(0, false)
+            .all(|(m, _)| m.len() <= ZSET_LISTPACK_MAX_VALUE)
+}
+
+/// How many keys with an expiry each round of `active_expire` samples.
+const EXPIRE_SAMPLE: usize = 20;
+
+/// `active_expire` keeps sampling while more than this share of a sample
+/// had expired, since there are probably many more.
+const EXPIRE_REPEAT_RATIO: f64 = 0.25;
+
+/// The buckets `ttl_stats` sorts keys with an expiry into: each its name,
+/// and how many milliseconds away expiring keys in it are under.
+pub const TTL_BUCKETS: &[(&str, u64)] = &[
+    ("1s", 1_000),
+    ("10s", 10_000),
+    ("1m", 60_000),
+    ("10m", 600_000),
+    ("1h", 3_600_000),
+    ("1d", 86_400_000),
+];
+
+/// How many keys with an expiry `ttl_stats` looks at. With more than that,
+/// it estimates from a random sample of this many.
+const TTL_SAMPLE: usize = 1000;
+
+/// When a database's keys expire, for INFO.
+pub struct TtlStats {
+    /// How many keys have an expiry.
+    pub expires: usize,
+    /// Their average time to live, in milliseconds.
+    pub avg_ttl: u64,
+    /// How many expire within each of `TTL_BUCKETS` and not the one
+    /// before, then how many expire later than the last.
+    pub histogram: Vec<usize>,
+}
+
+#[derive(Default)]
+pub struct Db {
+    entries: Dict<Value>,
+    expires: Sampled<u64>,
+    /// The keys of hashes with fields that have an expiry.
+    volatile_hashes: Sampled<()>,
+    /// Keyspace notifications not yet published: each event's class and
+    /// name, and the key.
+    events: Vec<(Events, &'static str, Bytes)>,
+}
+
+/// Some of the keys, each with a `T`, such as its expiry time. The keys
+/// are also kept in a vector, so a random one can be picked in constant
+/// time.
+#[derive(Default)]
+struct Sampled<T> {
+    /// Each key's `T` and its index in `keys`.
+    at: HashMap<Bytes, (T, usize)>,
+    keys: Vec<Bytes>,
+}
+
+impl<T: Copy> Sampled<T> {
+    fn get(&self, key: &[u8]) -> Option<T> {
+        self.at.get(key).map(|&(at, _)| at)
+    }
+
+    fn insert(&mut self, key: Bytes, at: T) {
+        if let Some(entry) = self.at.get_mut(&key) {
+            entry.0 = at;
+            return;
+        }
+        self.at.insert(key.clone(), (at, self.keys.len()));
+        self.keys.push(key);
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<T> {
+        let (at, index) = self.at.remove(key)?;
+        self.keys.swap_remove(index);
+        if let Some(moved) = self.keys.get(index) {
+            self.at.get_mut(moved).unwrap().1 = index;
+        }
+        Some(at)
+    }
+
+    fn random(&self) -> Option<(&Bytes, T)> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let key = &self.keys[rand::thread_rng().gen_range(0, self.keys.len())];
+        Some((key, self.at[key].0))
+    }
+}
+
+/// Reads a 64-bit integer written exactly as the number would print: no
+/// sign other than '-', no leading zeros, no spaces. This is what Redis
+/// accepts as an integer, both in arguments and in stored strings.
+pub fn parse_int(bytes: &[u8]) -> Option<i64> {
+    if bytes.len() > 20 {
+        return None;
+    }
+    let n: i64 = str::from_utf8(bytes).ok()?.parse().ok()?;
+    if n.to_string().as_bytes() == bytes {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+/// The current time as a unix timestamp in milliseconds.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl Db {
+    pub fn get(&mut self, key: &[u8]) -> Option<&Value> {
+        self.expire_if_due(key);
+        self.entries.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Value> {
+        self.expire_if_due(key);
+        self.entries.get_mut(key)
+    }
+
+    pub fn contains(&mut self, key: &[u8]) -> bool {
+        self.expire_if_due(key);
+        self.entries.contains_key(key)
+    }
+
+    /// Stores `value` under `key`, returning whatever was there before. Any
+    /// expiry the old value had is dropped.
+    pub fn insert(&mut self, key: Bytes, value: Value) -> Option<Value> {
+        self.expires.remove(&key);
+        self.insert_keep_ttl(key, value)
+    }
+
+    /// Like `insert`, but a live key keeps its expiry.
+    pub fn insert_keep_ttl(&mut self, key: Bytes, value: Value) -> Option<Value> {
+        self.expire_if_due(&key);
+        match value {
+            Value::Hash(ref hash) if hash.has_expiries() => {
+                self.volatile_hashes.insert(key.clone(), ())
+            }
+            _ => {
+                self.volatile_hashes.remove(&key);
+            }
+        }
+        let old = self.entries.insert(key.clone(), value);
+        if old.is_none() {
+            self.events.push((Events::NEW, "new", key));
+        }
+        old
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Value> {
+        self.expire_if_due(key);
+        self.expires.remove(key);
+        self.volatile_hashes.remove(key);
+        self.entries.remove(key)
+    }
+
+    /// Removes a key along with its expiry, for moving it elsewhere.
+    pub fn take(&mut self, key: &[u8]) -> Option<(Value, Option<u64>)> {
+        self.expire_if_due(key);
+        let at = self.expires.remove(key);
+        self.volatile_hashes.remove(key);
+        self.entries.remove(key).map(|value| (value, at))
+    }
+
+    /// Notes that the hash at `key` may now have fields with an expiry,
+    /// for the active expiry cycle to look at.
+    pub fn watch_fields(&mut self, key: &Bytes) {
+        if let Some(Value::Hash(hash)) = self.entries.get(key) {
+            if hash.has_expiries() {
+                self.volatile_hashes.insert(key.clone(), ());
+            }
+        }
+    }
+
+    /// Stores `value` under `key` with the given expiry, replacing whatever
+    /// was there.
+    pub fn insert_with_expiry(&mut self, key: Bytes, value: Value, at: Option<u64>) {
+        self.insert(key.clone(), value);
+        if let Some(at) = at {
+            self.expires.insert(key, at);
+        }
+    }
+
+    /// Makes an existing key expire at `at`, in unix milliseconds. Returns
+    /// false if there is no such key.
+    pub fn set_expiry(&mut self, key: &[u8], at: u64) -> bool {
+        self.expire_if_due(key);
+        match self.entries.get_key_value(key) {
+            Some((key, _)) => {
+                self.expires.insert(key.clone(), at);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// When a key expires: `None` if it doesn't exist, `Some(None)` if it
+    /// never does.
+    pub fn expiry(&mut self, key: &[u8]) -> Option<Option<u64>> {
+        if !self.contains(key) {
+            return None;
+        }
+        Some(self.expires.get(key))
+    }
+
+    /// Removes a key's expiry. Returns false if it had none.
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        self.expire_if_due(key);
+        self.expires.remove(key).is_some()
+    }
+
+    /// Visits one step's worth of keys for SCAN, skipping expired ones, and
+    /// returns the cursor to carry on from. See `Dict::scan`.
+    pub fn scan<F: FnMut(&Bytes, &Value)>(&self, cursor: u64, mut visit: F) -> u64 {
+        let now = now_ms();
+        self.entries.scan(cursor, |key, value| {
+            if !matches!(self.expires.get(key), Some(at) if at <= now) {
+                visit(key, value);
+            }
+        })
+    }
+
+    /// How many keys there are, counting expired ones not yet removed.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// When the keys with an expiry expire. Keys past their time but not
+    /// yet removed count as expiring within the first bucket.
+    pub fn ttl_stats(&self) -> TtlStats {
+        let now = now_ms();
+        let expires = self.expires.keys.len();
+        let ttls: Vec<u64> = if expires <= TTL_SAMPLE {
+            self.expires
+                .at
+                .values()
+                .map(|&(at, _)| at.saturating_sub(now))
+                .collect()
+        } else {
+            (0..TTL_SAMPLE)
+                .filter_map(|_| self.expires.random())
+                .map(|(_, at)| at.saturating_sub(now))
+                .collect()
+        };
+
+        let mut histogram = vec![0; TTL_BUCKETS.len() + 1];
+        for &ttl in &ttls {
+            let bucket = TTL_BUCKETS
+                .iter()
+                .position(|&(_, under)| ttl < under)
+                .unwrap_or(TTL_BUCKETS.len());
+            histogram[bucket] += 1;
+        }
+        if ttls.len() < expires {
+            for n in &mut histogram {
+                *n = *n * expires / ttls.len();
+            }
+        }
+        TtlStats {
+            expires,
+            avg_ttl: ttls
+                .iter()
+                .sum::<u64>()
+                .checked_div(ttls.len() as u64)
+                .unwrap_or(0),
+            histogram,
+        }
+    }
+
+    /// Every key that hasn't expired, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &Bytes> {
+        let now = now_ms();
+        self.entries
+            .keys()
+            .filter(move |key| !matches!(self.expires.get(key), Some(at) if at <= now))
+    }
+
+    /// Removes expired keys and hash fields that haven't been touched,
+    /// spending at most `budget` on it. Returns how many keys were removed
+    /// or had fields removed.
+    pub fn active_expire(&mut self, budget: Duration) -> usize {
+        let start = Instant::now();
+        let mut removed = 0;
+        loop {
+            let now = now_ms();
+            let sample = EXPIRE_SAMPLE.min(self.expires.keys.len());
+            let mut expired = 0;
+            for _ in 0..sample {
+                let key = match self.expires.random() {
+                    Some((key, at)) if at <= now => key.clone(),
+                    _ => continue,
+                };
+                self.expires.remove(&key);
+                self.volatile_hashes.remove(&key);
+                self.entries.remove(&key);
+                self.events.push((Events::EXPIRED, "expired", key));
+                expired += 1;
+            }
+
+            // Hashes count as expired if any of their fields did.
+            let hash_sample = EXPIRE_SAMPLE.min(self.volatile_hashes.keys.len());
+            for _ in 0..hash_sample {
+                let key = match self.volatile_hashes.random() {
+                    Some((key, _)) => key.clone(),
+                    None => break,
+                };
+                if self.expire_fields(&key, now) > 0 {
+                    expired += 1;
+                }
+            }
+            let sample = sample + hash_sample;
+            removed += expired;
+
+            if (expired as f64) <= sample as f64 * EXPIRE_REPEAT_RATIO || start.elapsed() >= budget
+            {
+                return removed;
+            }
+        }
+    }
+
+    /// Removes a key right away if its expiry time has passed, or if it's
+    /// a hash, its fields whose expiry time has.
+    fn expire_if_due(&mut self, key: &[u8]) {
+        if self.volatile_hashes.get(key).is_some() {
+            self.expire_fields(key, now_ms());
+        }
+        match self.expires.get(key) {
+            Some(at) if at <= now_ms() => {}
+            _ => return,
+        }
+        self.expires.remove(key);
+        self.volatile_hashes.remove(key);
+        self.entries.remove(key);
+        self.events
+            .push((Events::EXPIRED, "expired", Bytes::from(key)));
+    }
+
+    /// Takes the notifications noted since the last call.
+    pub fn take_events(&mut self) -> Vec<(Events, &'static str, Bytes)> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Removes the fields of the hash at `key` that expire at or before
+    /// `now`, and the hash itself if that was all of them. Returns how many
+    /// fields were removed.
+    fn expire_fields(&mut self, key: &[u8], now: u64) -> usize {
+        let hash = match self.entries.get_mut(key) {
+            Some(Value::Hash(hash)) => hash,
+            _ => {
+                self.volatile_hashes.remove(key);
+                return 0;
+            }
+        };
+        let removed = hash.expire(now);
+        if removed > 0 {
+            self.events
+                .push((Events::HASH, "hexpired", Bytes::from(key)));
+        }
+        if hash.is_empty() {
+            self.expires.remove(key);
+            self.volatile_hashes.remove(key);
+            self.entries.remove(key);
+            self.events.push((Events::GENERIC, "del", Bytes::from(key)));
+        } else if !hash.has_expiries() {
+            self.volatile_hashes.remove(key);
+        }
+        removed
+    }
+}
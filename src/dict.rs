@@ -0,0 +1,285 @@
+//! A hash table keyed by bytes that supports Redis-style cursor scans.
+//!
+//! `std`'s `HashMap` can't be walked a piece at a time across commands
+//! while other commands change it. `Dict` chains entries in a power-of-two
+//! number of buckets and hands out cursors that count through the buckets
+//! in reverse-binary order, as Redis' `dictScan` does. Doubling or halving
+//! the table splits or merges buckets without moving them around that
+//! order. So a full scan returns every key that was present for all of
+//! it, no matter how the table was resized in between; some keys may come
+//! back twice.
+
+use bytes::Bytes;
+
+use rand::Rng;
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::mem;
+
+/// Smallest table allocated once the dict holds anything.
+const MIN_BUCKETS: usize = 4;
+
+#[derive(Clone)]
+pub struct Dict<V> {
+    buckets: Vec<Vec<(Bytes, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<V: fmt::Debug> fmt::Debug for Dict<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Equal if they hold the same entries, however they are laid out.
+impl<V: PartialEq> PartialEq for Dict<V> {
+    fn eq(&self, other: &Dict<V>) -> bool {
+        self.len == other.len && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<V> Default for Dict<V> {
+    fn default() -> Dict<V> {
+        Dict {
+            buckets: Vec::new(),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<V> Dict<V> {
+    fn bucket(&self, key: &[u8]) -> usize {
+        (self.hasher.hash_one(key) as usize) & (self.buckets.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get_key_value(&self, key: &[u8]) -> Option<(&Bytes, &V)> {
+        if self.len == 0 {
+            return None;
+        }
+        self.buckets[self.bucket(key)]
+            .iter()
+            .find(|(k, _)| &k[..] == key)
+            .map(|(k, v)| (k, v))
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        if self.len == 0 {
+            return None;
+        }
+        let bucket = self.bucket(key);
+        self.buckets[bucket]
+            .iter_mut()
+            .find(|(k, _)| &k[..] == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get_key_value(key).is_some()
+    }
+
+    /// Stores `value` under `key`, returning the value it replaced.
+    pub fn insert(&mut self, key: Bytes, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(mem::replace(old, value));
+        }
+        if self.len >= self.buckets.len() {
+            self.resize((self.buckets.len() * 2).max(MIN_BUCKETS));
+        }
+        let bucket = self.bucket(&key);
+        self.buckets[bucket].push((key, value));
+        self.len += 1;
+        None
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        if self.len == 0 {
+            return None;
+        }
+        let bucket = self.bucket(key);
+        let index = self.buckets[bucket]
+            .iter()
+            .position(|(k, _)| &k[..] == key)?;
+        let (_, value) = self.buckets[bucket].swap_remove(index);
+        self.len -= 1;
+
+        // Give memory back once the table is mostly empty, as Redis does
+        // below 10% use.
+        if self.len == 0 {
+            self.buckets = Vec::new();
+        } else if self.buckets.len() > MIN_BUCKETS && self.len * 10 < self.buckets.len() {
+            self.resize((self.len.next_power_of_two() * 2).max(MIN_BUCKETS));
+        }
+        Some(value)
+    }
+
+    fn resize(&mut self, size: usize) {
+        let old = mem::replace(&mut self.buckets, (0..size).map(|_| Vec::new()).collect());
+        for (key, value) in old.into_iter().flatten() {
+            let bucket = self.bucket(&key);
+            self.buckets[bucket].push((key, value));
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &V)> {
+        self.buckets.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Bytes> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// A random entry, found as Redis finds one: by trying random buckets
+    /// until one isn't empty, then picking from its chain. Entries in
+    /// shorter chains are a little more likely to come up.
+    pub fn random(&self) -> Option<(&Bytes, &V)> {
+        if self.len == 0 {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        loop {
+            let bucket = &self.buckets[rng.gen_range(0, self.buckets.len())];
+            if !bucket.is_empty() {
+                let (k, v) = &bucket[rng.gen_range(0, bucket.len())];
+                return Some((k, v));
+            }
+        }
+    }
+
+    /// Visits the entries of the bucket at `cursor` and returns the cursor
+    /// to carry on from, 0 once the whole table has been covered. Start a
+    /// scan at 0.
+    pub fn scan<F: FnMut(&Bytes, &V)>(&self, cursor: u64, mut visit: F) -> u64 {
+        if self.buckets.is_empty() {
+            return 0;
+        }
+        let mask = (self.buckets.len() - 1) as u64;
+        for (key, value) in &self.buckets[(cursor & mask) as usize] {
+            visit(key, value);
+        }
+
+        // Increment the bits above the mask reversed, so the next bucket
+        // is the one that comes next in reverse-binary order.
+        let mut cursor = cursor | !mask;
+        cursor = cursor.reverse_bits();
+        cursor = cursor.wrapping_add(1);
+        cursor.reverse_bits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn key(prefix: &str, i: usize) -> Bytes {
+        Bytes::from(format!("{}{}", prefix, i))
+    }
+
+    /// Scans `dict` to the end, calling `between` after each step, and
+    /// returns every key seen.
+    fn scan_all<F: FnMut(&mut Dict<()>)>(dict: &mut Dict<()>, mut between: F) -> HashSet<Bytes> {
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        for _ in 0..100_000 {
+            cursor = dict.scan(cursor, |k, _| {
+                seen.insert(k.clone());
+            });
+            if cursor == 0 {
+                return seen;
+            }
+            between(dict);
+        }
+        panic!("scan never finished");
+    }
+
+    #[test]
+    fn scan_survives_growing() {
+        let mut dict = Dict::default();
+        for i in 0..20 {
+            dict.insert(key("stable", i), ());
+        }
+        let mut added = 0;
+        let seen = scan_all(&mut dict, |dict| {
+            // Growing for ever would keep the scan from ever finishing.
+            for _ in 0..50 {
+                if added < 2000 {
+                    dict.insert(key("new", added), ());
+                    added += 1;
+                }
+            }
+        });
+        assert!(dict.buckets.len() >= 512);
+        for i in 0..20 {
+            assert!(seen.contains(&key("stable", i)), "missed stable{}", i);
+        }
+    }
+
+    #[test]
+    fn scan_survives_shrinking() {
+        let mut dict = Dict::default();
+        for i in 0..20 {
+            dict.insert(key("stable", i), ());
+        }
+        for i in 0..2000 {
+            dict.insert(key("old", i), ());
+        }
+        let buckets = dict.buckets.len();
+        let mut removed = 0;
+        let seen = scan_all(&mut dict, |dict| {
+            for _ in 0..200 {
+                dict.remove(&key("old", removed));
+                removed += 1;
+            }
+        });
+        assert!(dict.buckets.len() < buckets);
+        for i in 0..20 {
+            assert!(seen.contains(&key("stable", i)), "missed stable{}", i);
+        }
+    }
+
+    #[test]
+    fn scan_survives_both() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            let mut dict = Dict::default();
+            for i in 0..rng.gen_range(1, 100) {
+                dict.insert(key("stable", i), ());
+            }
+            let stable: Vec<Bytes> = dict.keys().cloned().collect();
+            let mut churn = 0;
+            let seen = scan_all(&mut dict, |dict| {
+                let n = rng.gen_range(0, 100);
+                if churn < 2000 && rng.gen() {
+                    for _ in 0..n {
+                        dict.insert(key("churn", churn), ());
+                        churn += 1;
+                    }
+                } else {
+                    for i in churn.saturating_sub(n)..churn {
+                        dict.remove(&key("churn", i));
+                    }
+                    churn = churn.saturating_sub(n);
+                }
+            });
+            for key in &stable {
+                assert!(seen.contains(key), "missed {:?}", key);
+            }
+        }
+    }
+}
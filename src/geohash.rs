@@ -0,0 +1,384 @@
+//! Geohashes, as Redis stores geo positions in sorted set scores.
+//!
+//! A position is encoded as 26 bits of longitude and 26 of latitude,
+//! interleaved so that nearby positions mostly share a prefix. 52 bits fit
+//! exactly in a score. A prefix of a hash (fewer steps) names a cell of
+//! the map, and the members in a cell are a score range, so a search looks
+//! at the cell around its centre and that cell's neighbours, each a range
+//! query, then checks each member found against the exact shape.
+
+pub const LONG_MIN: f64 = -180.0;
+pub const LONG_MAX: f64 = 180.0;
+/// The latitudes EPSG:3857, the web mercator projection, covers.
+pub const LAT_MIN: f64 = -85.05112878;
+pub const LAT_MAX: f64 = 85.05112878;
+
+/// The most steps, each of which halves a cell in both directions.
+pub const MAX_STEPS: u32 = 26;
+
+/// Redis' earth radius, so distances come out the same as its.
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+
+/// Half the circumference of the earth in the mercator projection.
+const MERCATOR_MAX: f64 = 20037726.37;
+
+/// A hash of `step` steps: the `2 * step` low bits of `bits`.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Hash {
+    pub bits: u64,
+    pub step: u32,
+}
+
+/// The bounds of the cell a hash names.
+pub struct Area {
+    pub long_min: f64,
+    pub long_max: f64,
+    pub lat_min: f64,
+    pub lat_max: f64,
+}
+
+pub fn valid(longitude: f64, latitude: f64) -> bool {
+    (LONG_MIN..=LONG_MAX).contains(&longitude) && (LAT_MIN..=LAT_MAX).contains(&latitude)
+}
+
+/// Spreads the bits of `x` over the even bits of the result.
+fn spread(x: u32) -> u64 {
+    (0..32).fold(0, |acc, i| acc | (((x as u64 >> i) & 1) << (2 * i)))
+}
+
+/// Gathers the even bits of `x`.
+fn squash(x: u64) -> u32 {
+    (0..32).fold(0, |acc, i| acc | (((x >> (2 * i)) & 1) << i) as u32)
+}
+
+/// Hashes a valid position to `step` steps. Latitude takes the even bits,
+/// longitude the odd ones.
+pub fn encode(longitude: f64, latitude: f64, step: u32) -> Hash {
+    let cells = (1u64 << step) as f64;
+    let lat_offset = (latitude - LAT_MIN) / (LAT_MAX - LAT_MIN) * cells;
+    let long_offset = (longitude - LONG_MIN) / (LONG_MAX - LONG_MIN) * cells;
+    Hash {
+        bits: spread(lat_offset as u32) | (spread(long_offset as u32) << 1),
+        step,
+    }
+}
+
+pub fn decode(hash: Hash) -> Area {
+    let cells = (1u64 << hash.step) as f64;
+    let lat = squash(hash.bits) as f64;
+    let long = squash(hash.bits >> 1) as f64;
+    Area {
+        lat_min: LAT_MIN + lat / cells * (LAT_MAX - LAT_MIN),
+        lat_max: LAT_MIN + (lat + 1.0) / cells * (LAT_MAX - LAT_MIN),
+        long_min: LONG_MIN + long / cells * (LONG_MAX - LONG_MIN),
+        long_max: LONG_MIN + (long + 1.0) / cells * (LONG_MAX - LONG_MIN),
+    }
+}
+
+/// The position a full hash stands for: the centre of its cell.
+pub fn position(bits: u64) -> (f64, f64) {
+    let area = decode(Hash {
+        bits,
+        step: MAX_STEPS,
+    });
+    let longitude = (area.long_min + area.long_max) / 2.0;
+    let latitude = (area.lat_min + area.lat_max) / 2.0;
+    (
+        longitude.clamp(LONG_MIN, LONG_MAX),
+        latitude.clamp(LAT_MIN, LAT_MAX),
+    )
+}
+
+/// The cell next to `hash`, `dx` cells east and `dy` cells north, each of
+/// which is -1, 0 or 1. Moving off an edge wraps around.
+fn neighbour(hash: Hash, dx: i8, dy: i8) -> Hash {
+    let used = 64 - hash.step * 2;
+    let shift = |bits: u64, mask: u64, d: i8| {
+        // Adding or subtracting one at a time in the bits of one
+        // coordinate: fill the other coordinate's bits so carries and
+        // borrows pass through them.
+        let (coord, other) = (bits & mask, !mask >> used);
+        let one = other + 1;
+        let moved = match d {
+            0 => return coord,
+            1 => coord.wrapping_add(one),
+            _ => (coord | other).wrapping_sub(one),
+        };
+        moved & (mask >> used)
+    };
+    let bits =
+        shift(hash.bits, 0xaaaa_aaaa_aaaa_aaaa, dx) | shift(hash.bits, 0x5555_5555_5555_5555, dy);
+    Hash {
+        bits,
+        step: hash.step,
+    }
+}
+
+/// The great-circle distance in meters between two positions.
+pub fn distance(long1: f64, lat1: f64, long2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((long2.to_radians() - long1.to_radians()) / 2.0).sin();
+    2.0 * EARTH_RADIUS_IN_METERS * (u * u + lat1.cos() * lat2.cos() * v * v).sqrt().asin()
+}
+
+/// What a search looks for around its centre, in meters.
+#[derive(Clone, Copy)]
+pub enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+impl Shape {
+    /// The distance from the centre to a position, or `None` if the
+    /// position is outside the shape.
+    pub fn distance(&self, centre: (f64, f64), long: f64, lat: f64) -> Option<f64> {
+        match *self {
+            Shape::Radius(radius) => {
+                Some(distance(centre.0, centre.1, long, lat)).filter(|&d| d <= radius)
+            }
+            Shape::Box { width, height } => {
+                // Latitude is cheaper, so rule positions out by that first.
+                let lat_distance =
+                    EARTH_RADIUS_IN_METERS * (lat.to_radians() - centre.1.to_radians()).abs();
+                if lat_distance > height / 2.0 {
+                    return None;
+                }
+                if distance(long, lat, centre.0, lat) > width / 2.0 {
+                    return None;
+                }
+                Some(distance(centre.0, centre.1, long, lat))
+            }
+        }
+    }
+
+    /// How far the shape reaches from its centre.
+    fn radius(&self) -> f64 {
+        match *self {
+            Shape::Radius(radius) => radius,
+            Shape::Box { width, height } => (width / 2.0).hypot(height / 2.0),
+        }
+    }
+
+    /// The latitudes and longitudes the shape spans, as (min long, min lat,
+    /// max long, max lat).
+    fn bounds(&self, centre: (f64, f64)) -> (f64, f64, f64, f64) {
+        let (long, lat) = centre;
+        let (half_width, half_height) = match *self {
+            Shape::Radius(radius) => (radius, radius),
+            Shape::Box { width, height } => (width / 2.0, height / 2.0),
+        };
+        let lat_delta = (half_height / EARTH_RADIUS_IN_METERS).to_degrees();
+        let long_delta =
+            |lat: f64| (half_width / EARTH_RADIUS_IN_METERS / lat.to_radians().cos()).to_degrees();
+        // The longitudes the shape spans are widest at the edge nearer a
+        // pole.
+        let long_delta = if lat < 0.0 {
+            long_delta(lat - lat_delta)
+        } else {
+            long_delta(lat + lat_delta)
+        };
+        (
+            long - long_delta,
+            lat - lat_delta,
+            long + long_delta,
+            lat + lat_delta,
+        )
+    }
+}
+
+/// How many steps give cells about big enough that the shape fits in one
+/// cell and its neighbours.
+fn estimate_steps(radius: f64, lat: f64) -> u32 {
+    if radius == 0.0 {
+        return MAX_STEPS;
+    }
+    let mut radius = radius;
+    let mut step: i32 = 1;
+    while radius < MERCATOR_MAX {
+        radius *= 2.0;
+        step += 1;
+    }
+    // Make sure the range is covered in most cases.
+    step -= 2;
+    // Cells get narrower towards the poles.
+    if !(-66.0..=66.0).contains(&lat) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&lat) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, MAX_STEPS as i32) as u32
+}
+
+/// The cells to look in for members within `shape` around `centre`: the
+/// centre's cell and those of its neighbours the shape may reach into.
+pub fn search_cells(centre: (f64, f64), shape: &Shape) -> Vec<Hash> {
+    let (long, lat) = centre;
+    let (min_long, min_lat, max_long, max_lat) = shape.bounds(centre);
+    let mut step = estimate_steps(shape.radius(), lat);
+    let mut hash = encode(long, lat, step);
+
+    // Near the edge of a cell, the estimate may leave a neighbour too
+    // close to the centre to cover the whole shape.
+    let too_big = {
+        let north = decode(neighbour(hash, 0, 1));
+        let south = decode(neighbour(hash, 0, -1));
+        let east = decode(neighbour(hash, 1, 0));
+        let west = decode(neighbour(hash, -1, 0));
+        north.lat_max < max_lat
+            || south.lat_min > min_lat
+            || east.long_max < max_long
+            || west.long_min > min_long
+    };
+    if step > 1 && too_big {
+        step -= 1;
+        hash = encode(long, lat, step);
+    }
+
+    // Leave out neighbours on sides the shape doesn't reach. The order is
+    // Redis', so unsorted results come back in the same order.
+    let area = decode(hash);
+    let mut cells = Vec::with_capacity(9);
+    for &(dx, dy) in &[
+        (0, 0),
+        (0, 1),
+        (0, -1),
+        (1, 0),
+        (-1, 0),
+        (1, 1),
+        (-1, 1),
+        (1, -1),
+        (-1, -1),
+    ] {
+        if step >= 2
+            && ((dy < 0 && area.lat_min < min_lat)
+                || (dy > 0 && area.lat_max > max_lat)
+                || (dx < 0 && area.long_min < min_long)
+                || (dx > 0 && area.long_max > max_long))
+        {
+            continue;
+        }
+        let cell = neighbour(hash, dx, dy);
+        // At low steps, neighbours may wrap around to the same cell.
+        if !cells.contains(&cell) {
+            cells.push(cell);
+        }
+    }
+    cells
+}
+
+/// The range of full hashes within a cell, as `[min, max)`.
+pub fn score_range(cell: Hash) -> (u64, u64) {
+    let shift = 2 * (MAX_STEPS - cell.step);
+    (cell.bits << shift, (cell.bits + 1) << shift)
+}
+
+/// Distance units, in meters.
+pub fn unit(arg: &[u8]) -> Option<f64> {
+    match &arg.to_ascii_lowercase()[..] {
+        b"m" => Some(1.0),
+        b"km" => Some(1000.0),
+        b"ft" => Some(0.3048),
+        b"mi" => Some(1609.34),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn hashes_match_redis() {
+        // Redis' scores for its GEOADD example, and the positions GEOPOS
+        // gives back for them.
+        for &(long, lat, bits, centre) in &[
+            (
+                13.361389,
+                38.115556,
+                3479099956230698,
+                (13.361_389_338_970_184, 38.115_556_395_496_3),
+            ),
+            (
+                15.087269,
+                37.502669,
+                3479447370796909,
+                (15.087_267_458_438_873, 37.502_668_423_331_62),
+            ),
+        ] {
+            let hash = encode(long, lat, MAX_STEPS);
+            assert_eq!(hash.bits, bits);
+            assert_eq!(position(hash.bits), centre);
+            assert_eq!(encode(centre.0, centre.1, MAX_STEPS).bits, bits);
+        }
+    }
+
+    #[test]
+    fn estimates_steps_like_redis() {
+        assert_eq!(estimate_steps(0.0, 0.0), MAX_STEPS);
+        assert_eq!(estimate_steps(5000.0, 0.0), 11);
+        assert_eq!(estimate_steps(5000.0, 70.0), 10);
+        assert_eq!(estimate_steps(5000.0, -85.0), 9);
+        assert_eq!(estimate_steps(1.0, 0.0), 24);
+        assert_eq!(estimate_steps(MERCATOR_MAX * 4.0, 0.0), 1);
+    }
+
+    /// Whether the score ranges of `cells` take in `position`.
+    fn covered(cells: &[Hash], position: (f64, f64)) -> bool {
+        let bits = encode(position.0, position.1, MAX_STEPS).bits;
+        cells.iter().any(|&cell| {
+            let (min, max) = score_range(cell);
+            (min..max).contains(&bits)
+        })
+    }
+
+    #[test]
+    fn search_reaches_across_cell_edges() {
+        // Centres just inside the east edge of their cell, and places just
+        // over the edge, so only the neighbouring cell holds them.
+        let radius = 5000.0;
+        let shape = Shape::Radius(radius);
+        for &lat in &[0.0, 45.0, -60.0, 75.0] {
+            let area = decode(encode(13.0, lat, estimate_steps(radius, lat)));
+            let edge = area.long_max;
+            let centre = (edge - 1e-6, lat);
+            let place = (edge + 1e-6, lat);
+            assert!(shape.distance(centre, place.0, place.1).is_some());
+            assert!(covered(&search_cells(centre, &shape), place));
+        }
+    }
+
+    #[test]
+    fn search_cells_cover_the_shape() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..500 {
+            let centre = (rng.gen_range(-179.0, 179.0), rng.gen_range(-80.0, 80.0));
+            let reach: f64 = rng.gen_range(1.0, 200_000.0);
+            let shape = if rng.gen() {
+                Shape::Radius(reach)
+            } else {
+                Shape::Box {
+                    width: reach * 2.0,
+                    height: rng.gen_range(1.0, reach * 2.0),
+                }
+            };
+            let cells = search_cells(centre, &shape);
+            let (min_long, min_lat, max_long, max_lat) = shape.bounds(centre);
+            for _ in 0..50 {
+                let long = rng.gen_range(min_long, max_long);
+                let lat = rng.gen_range(min_lat, max_lat);
+                if valid(long, lat) && shape.distance(centre, long, lat).is_some() {
+                    assert!(
+                        covered(&cells, (long, lat)),
+                        "{:?} not found around {:?} within {}",
+                        (long, lat),
+                        centre,
+                        reach
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Glob-style pattern matching, as used by KEYS, SCAN MATCH and PSUBSCRIBE.
+//!
+//! The syntax is Redis': `*` matches any run of bytes, `?` any single byte,
+//! `[abc]` and `[a-z]` a byte from a set (`[^...]` one not in it), and `\`
+//! makes the next byte literal, inside a set or out.
+
+/// Whether all of `s` matches `pattern`.
+pub fn matches(pattern: &[u8], s: &[u8], nocase: bool) -> bool {
+    let mut p = 0;
+    let mut i = 0;
+    // Where to resume if the pattern stops matching after a `*`: the
+    // pattern position just past it, and the next byte of `s` it could
+    // swallow.
+    let mut backtrack = None;
+
+    while i < s.len() {
+        if p < pattern.len() {
+            if pattern[p] == b'*' {
+                while p < pattern.len() && pattern[p] == b'*' {
+                    p += 1;
+                }
+                backtrack = Some((p, i));
+                continue;
+            }
+            if let Some(len) = match_one(&pattern[p..], s[i], nocase) {
+                p += len;
+                i += 1;
+                continue;
+            }
+        }
+        match backtrack {
+            Some((star_p, star_i)) => {
+                p = star_p;
+                i = star_i + 1;
+                backtrack = Some((star_p, star_i + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Matches the pattern element at the start of `pattern`, which must not be
+/// `*`, against `c`. Returns how many pattern bytes the element took up if
+/// it matched.
+fn match_one(pattern: &[u8], c: u8, nocase: bool) -> Option<usize> {
+    match pattern[0] {
+        b'?' => Some(1),
+        b'\\' if pattern.len() > 1 => eq(pattern[1], c, nocase).then_some(2),
+        b'[' => {
+            let (matched, len) = match_class(&pattern[1..], c, nocase);
+            matched.then_some(len + 1)
+        }
+        literal => eq(literal, c, nocase).then_some(1),
+    }
+}
+
+/// Matches the set that follows a `[`. Returns whether `c` is in it, and
+/// how many bytes the set took up, closing `]` included. A set missing its
+/// `]` runs to the end of the pattern.
+fn match_class(pattern: &[u8], c: u8, nocase: bool) -> (bool, usize) {
+    let mut i = 0;
+    let negate = pattern.first() == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    while i < pattern.len() {
+        match pattern[i] {
+            b']' => {
+                i += 1;
+                break;
+            }
+            b'\\' if i + 1 < pattern.len() => {
+                matched |= eq(pattern[i + 1], c, nocase);
+                i += 2;
+            }
+            start if i + 2 < pattern.len() && pattern[i + 1] == b'-' => {
+                let (mut lo, mut hi, mut c) = (start, pattern[i + 2], c);
+                if nocase {
+                    lo = lo.to_ascii_lowercase();
+                    hi = hi.to_ascii_lowercase();
+                    c = c.to_ascii_lowercase();
+                }
+                if lo > hi {
+                    std::mem::swap(&mut lo, &mut hi);
+                }
+                matched |= (lo..=hi).contains(&c);
+                i += 3;
+            }
+            literal => {
+                matched |= eq(literal, c, nocase);
+                i += 1;
+            }
+        }
+    }
+    (matched != negate, i)
+}
+
+fn eq(a: u8, b: u8, nocase: bool) -> bool {
+    if nocase {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_like_redis() {
+        // (pattern, string, nocase, whether it matches)
+        let table: &[(&str, &str, bool, bool)] = &[
+            ("*", "", false, true),
+            ("h?llo", "hello", false, true),
+            ("h?llo", "hllo", false, false),
+            // A `*` has to give back what it swallowed when what follows
+            // only matches further on.
+            ("a*b*c", "aXbYbZc", false, true),
+            ("a*bc", "abcbcbd", false, false),
+            ("*ab", "aab", false, true),
+            ("a**b", "ab", false, true),
+            ("*a*a*a*b", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", false, false),
+            ("h[ae]llo", "hallo", false, true),
+            ("h[ae]llo", "hillo", false, false),
+            ("h[^e]llo", "hallo", false, true),
+            ("h[^e]llo", "hello", false, false),
+            ("h[a-b]llo", "hbllo", false, true),
+            // A backwards range is the same as a forwards one.
+            ("[z-a]", "m", false, true),
+            ("[^z-a]", "m", false, false),
+            ("\\*", "*", false, true),
+            ("\\*", "x", false, false),
+            ("\\?\\[", "?[", false, true),
+            ("[\\]]", "]", false, true),
+            ("[\\^a]", "^", false, true),
+            // A trailing `\` is just itself.
+            ("a\\", "a\\", false, true),
+            // A set missing its `]` runs to the end of the pattern.
+            ("[abc", "b", false, true),
+            ("[abc", "d", false, false),
+            ("x[a-", "xa", false, true),
+            ("HELLO", "hello", false, false),
+            ("HELLO", "hello", true, true),
+            ("[A-C]x", "bX", true, true),
+            ("[^A-C]", "b", true, false),
+            ("\\H*", "hey", true, true),
+        ];
+        for &(pattern, s, nocase, expected) in table {
+            assert_eq!(
+                matches(pattern.as_bytes(), s.as_bytes(), nocase),
+                expected,
+                "{:?} against {:?}, nocase {}",
+                pattern,
+                s,
+                nocase
+            );
+        }
+    }
+}
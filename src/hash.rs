@@ -0,0 +1,116 @@
+//! Hashes: a `Dict` of fields, any of which may be given its own expiry
+//! time, as HEXPIRE does.
+//!
+//! Expiry times are kept both by field and in time order, so the fields
+//! whose time has come can be found without looking at the rest. Expired
+//! fields are removed when the database looks the hash up, or by its
+//! active expiry cycle; see `Db`.
+
+use bytes::Bytes;
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::dict::Dict;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Hash {
+    fields: Dict<Bytes>,
+    /// Each field with an expiry, and when it expires in unix
+    /// milliseconds.
+    expires: HashMap<Bytes, u64>,
+    /// The same, ordered by time.
+    by_time: BTreeSet<(u64, Bytes)>,
+}
+
+impl Hash {
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        self.fields.get(field)
+    }
+
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.fields.contains_key(field)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &Bytes)> {
+        self.fields.iter()
+    }
+
+    pub fn random(&self) -> Option<(&Bytes, &Bytes)> {
+        self.fields.random()
+    }
+
+    /// See `Dict::scan`.
+    pub fn scan<F: FnMut(&Bytes, &Bytes)>(&self, cursor: u64, visit: F) -> u64 {
+        self.fields.scan(cursor, visit)
+    }
+
+    /// Sets a field, as HSET does: a field that was there before loses
+    /// its expiry. Returns the old value.
+    pub fn insert(&mut self, field: Bytes, value: Bytes) -> Option<Bytes> {
+        self.persist(&field);
+        self.fields.insert(field, value)
+    }
+
+    /// Sets a field, keeping its expiry, as the HINCRBY family do.
+    pub fn update(&mut self, field: Bytes, value: Bytes) -> Option<Bytes> {
+        self.fields.insert(field, value)
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Bytes> {
+        self.persist(field);
+        self.fields.remove(field)
+    }
+
+    /// When a field expires, if it has an expiry.
+    pub fn expiry(&self, field: &[u8]) -> Option<u64> {
+        self.expires.get(field).copied()
+    }
+
+    /// Makes an existing field expire at `at`.
+    pub fn set_expiry(&mut self, field: &Bytes, at: u64) {
+        if let Some(old) = self.expires.insert(field.clone(), at) {
+            self.by_time.remove(&(old, field.clone()));
+        }
+        self.by_time.insert((at, field.clone()));
+    }
+
+    /// Removes a field's expiry. Returns false if it had none.
+    pub fn persist(&mut self, field: &[u8]) -> bool {
+        match self.expires.remove_entry(field) {
+            Some((field, at)) => {
+                self.by_time.remove(&(at, field));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether any field has an expiry.
+    pub fn has_expiries(&self) -> bool {
+        !self.expires.is_empty()
+    }
+
+    /// Removes the fields whose expiry time is at or before `now`. Returns
+    /// how many there were.
+    pub fn expire(&mut self, now: u64) -> usize {
+        let mut removed = 0;
+        while let Some((at, _)) = self.by_time.first() {
+            if *at > now {
+                break;
+            }
+            let (_, field) = self.by_time.pop_first().unwrap();
+            self.expires.remove(&field);
+            self.fields.remove(&field);
+            removed += 1;
+        }
+        removed
+    }
+}
@@ -0,0 +1,708 @@
+//! The keyspace service.
+//!
+//! All data lives in a single task that owns it outright, so commands never
+//! contend on a lock and every command sees the effects of the ones before
+//! it, whichever connection sent them. Sessions hand it batches of decoded
+//! commands over a bounded channel, and the replies for a batch come back
+//! together on a oneshot channel, in order.
+//!
+//! The task also keeps the registry of connected clients behind the CLIENT
+//! commands. Connections are added and removed over a separate, unbounded
+//! channel, which is always drained before the next batch is run, so a
+//! client is registered before its first command is handled.
+//!
+//! Pub/sub messages don't wait on anybody's batch: PUBLISH queues each one
+//! on its subscribers' unbounded push channels, given in when they
+//! connected, and their sessions write them out in their own time. Keyspace
+//! notifications go out the same way; see the `notify` module.
+//!
+//! A batch whose command blocks, as BLPOP can, is set aside until that
+//! command can finish; see the `blocking` module.
+//!
+//! Clients tracking keys with CLIENT TRACKING hear that they changed from
+//! the same place keyspace notifications are published, whether or not
+//! those are turned on.
+//!
+//! Between MULTI and EXEC, a client's commands are checked and queued
+//! rather than run. EXEC then runs them all in one go, which makes the
+//! transaction atomic for free: nothing else runs until it's done.
+
+use bytes::Bytes;
+use futures::sync::{mpsc, oneshot};
+use futures::{task, StartSend};
+use tokio::prelude::*;
+use tokio::timer::{Delay, Interval};
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::vec;
+
+use crate::blocking::{Blocked, BlockedClients};
+use crate::client::{ClientClass, ClientId, ClientInfo, Push, Tracking};
+use crate::commands::{self, Command};
+use crate::db::Db;
+use crate::glob;
+use crate::lazyfree::LazyFree;
+use crate::notify::Events;
+use crate::pubsub::Registry;
+use crate::resp::Frame;
+use crate::stats::Stats;
+
+/// How many batches may queue up for the keyspace before senders have to wait.
+const QUEUE_DEPTH: usize = 1024;
+
+/// How often expired keys nobody has touched are swept up, and how long
+/// each sweep may take at most: Redis' default of ten times a second, using
+/// no more than a quarter of that time.
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+const ACTIVE_EXPIRE_BUDGET: Duration = Duration::from_millis(25);
+
+/// Batches handled per poll before the task yields.
+const REQUESTS_PER_POLL: usize = 256;
+
+/// A batch of commands from one client.
+pub struct Request {
+    pub client: ClientId,
+    /// Each command's arguments, starting with its name.
+    pub commands: Vec<Vec<Bytes>>,
+    pub reply: oneshot::Sender<Vec<Frame>>,
+}
+
+enum Control {
+    Connected {
+        client: ClientId,
+        addr: SocketAddr,
+        local_addr: SocketAddr,
+        push: mpsc::UnboundedSender<Push>,
+    },
+    Disconnected(ClientId),
+    /// The client closed its end while it had a batch with us.
+    HungUp(ClientId),
+}
+
+/// The sending side of the keyspace service, cloned into every session.
+#[derive(Clone)]
+pub struct Handle {
+    requests: mpsc::Sender<Request>,
+    control: mpsc::UnboundedSender<Control>,
+}
+
+impl Handle {
+    /// Registers a newly accepted connection. Messages for it that aren't
+    /// replies go to `push`.
+    pub fn connected(
+        &self,
+        client: ClientId,
+        addr: SocketAddr,
+        local_addr: SocketAddr,
+        push: mpsc::UnboundedSender<Push>,
+    ) {
+        // Only fails once the service has stopped, when nothing is served
+        // anyway.
+        let _ = self.control.unbounded_send(Control::Connected {
+            client,
+            addr,
+            local_addr,
+            push,
+        });
+    }
+
+    /// Tells the keyspace a client hung up while waiting on a batch, so a
+    /// blocked command in it can be given up on.
+    pub fn hung_up(&self, client: ClientId) {
+        let _ = self.control.unbounded_send(Control::HungUp(client));
+    }
+
+    /// Forgets a connection once its session has ended.
+    pub fn disconnected(&self, client: ClientId) {
+        let _ = self.control.unbounded_send(Control::Disconnected(client));
+    }
+}
+
+impl Sink for Handle {
+    type SinkItem = Request;
+    type SinkError = mpsc::SendError<Request>;
+
+    fn start_send(&mut self, request: Request) -> StartSend<Request, Self::SinkError> {
+        self.requests.start_send(request)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.requests.poll_complete()
+    }
+}
+
+/// Creates the service, with `databases` numbered databases, publishing
+/// the keyspace notifications in `notify`. The returned future is the
+/// keyspace task itself; it runs until every `Handle` has been dropped.
+pub fn service(
+    databases: usize,
+    notify: Events,
+    stats: Arc<Stats>,
+) -> (Handle, impl Future<Item = (), Error = ()>) {
+    let (requests, requests_rx) = mpsc::channel(QUEUE_DEPTH);
+    let (control, control_rx) = mpsc::unbounded();
+    let service = Service {
+        requests: requests_rx,
+        control: control_rx,
+        expire_timer: Interval::new_interval(ACTIVE_EXPIRE_INTERVAL),
+        block_timer: None,
+        keyspace: Keyspace::new(databases, notify, stats),
+    };
+    (Handle { requests, control }, service)
+}
+
+struct Service {
+    requests: mpsc::Receiver<Request>,
+    control: mpsc::UnboundedReceiver<Control>,
+    expire_timer: Interval,
+    /// Fires at the earliest deadline of any blocked client.
+    block_timer: Option<Delay>,
+    keyspace: Keyspace,
+}
+
+impl Service {
+    /// Times out blocked clients whose deadline has passed, and arranges to
+    /// be woken for the next one.
+    fn poll_block_timer(&mut self) -> Result<(), ()> {
+        loop {
+            let deadline = match self.keyspace.blocked.next_deadline() {
+                Some(deadline) => deadline,
+                None => {
+                    self.block_timer = None;
+                    return Ok(());
+                }
+            };
+            let timer = self.block_timer.get_or_insert_with(|| Delay::new(deadline));
+            if Delay::deadline(timer) != deadline {
+                timer.reset(deadline);
+            }
+            match timer.poll().map_err(|e| {
+                println!("block timer failed: {}", e);
+            })? {
+                Async::Ready(()) => self.keyspace.time_out_blocked(Instant::now()),
+                Async::NotReady => return Ok(()),
+            }
+        }
+    }
+}
+
+impl Future for Service {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        while let Async::Ready(Some(_)) = self.expire_timer.poll().map_err(|e| {
+            println!("expire timer failed: {}", e);
+        })? {
+            self.keyspace.active_expire();
+        }
+        self.poll_block_timer()?;
+
+        for _ in 0..REQUESTS_PER_POLL {
+            while let Async::Ready(Some(event)) = self.control.poll()? {
+                self.keyspace.apply(event);
+            }
+
+            let request = match self.requests.poll()? {
+                Async::Ready(Some(request)) => request,
+                Async::Ready(None) => return Ok(Async::Ready(())),
+                Async::NotReady => {
+                    // Commands that just blocked may need an earlier wakeup.
+                    self.poll_block_timer()?;
+                    return Ok(Async::NotReady);
+                }
+            };
+            self.keyspace.run(Batch {
+                client: request.client,
+                commands: request.commands.into_iter(),
+                replies: Vec::new(),
+                reply: request.reply,
+            });
+        }
+
+        // Still busy; yield so the timers get a look in, then carry on.
+        task::current().notify();
+        Ok(Async::NotReady)
+    }
+}
+
+/// A batch of commands partway through running.
+pub struct Batch {
+    pub client: ClientId,
+    /// The commands that haven't run yet.
+    pub commands: vec::IntoIter<Vec<Bytes>>,
+    /// The replies of those that have.
+    pub replies: Vec<Frame>,
+    pub reply: oneshot::Sender<Vec<Frame>>,
+}
+
+/// What a command that called `Keyspace::block` is waiting for.
+struct BlockOn {
+    keys: Vec<Bytes>,
+    deadline: Option<Instant>,
+    /// What to run instead of the command when a key is signalled.
+    command: Option<Vec<Bytes>>,
+}
+
+/// Everything commands run against: the databases, the connected and
+/// blocked clients, pub/sub subscriptions, the command table and the
+/// thread big values are freed on.
+pub struct Keyspace {
+    pub dbs: Vec<Db>,
+    /// The database selected by the client whose command is running.
+    pub selected: usize,
+    pub clients: HashMap<ClientId, ClientInfo>,
+    pub blocked: BlockedClients,
+    /// SUBSCRIBE's channels.
+    pub channels: Registry,
+    /// PSUBSCRIBE's patterns.
+    pub patterns: Registry,
+    /// SSUBSCRIBE's shard channels, which PUBLISH doesn't reach.
+    pub shard_channels: Registry,
+    /// The clients that turned on CLIENT TRACKING.
+    pub tracking: HashMap<ClientId, Tracking>,
+    /// Which keyspace notifications to publish.
+    notify: Events,
+    /// The server-wide counters the accept loop keeps, for INFO.
+    pub stats: Arc<Stats>,
+    pub lazyfree: LazyFree,
+    commands: HashMap<&'static [u8], &'static Command>,
+    /// Set by `block` while a command runs.
+    block_on: Option<BlockOn>,
+    /// Whether blocked clients are being served, so serving them doesn't
+    /// start over from within one of their commands.
+    serving_blocked: bool,
+}
+
+impl Keyspace {
+    fn new(databases: usize, notify: Events, stats: Arc<Stats>) -> Keyspace {
+        Keyspace {
+            dbs: (0..databases).map(|_| Db::default()).collect(),
+            selected: 0,
+            clients: HashMap::new(),
+            blocked: BlockedClients::default(),
+            channels: Registry::default(),
+            patterns: Registry::default(),
+            shard_channels: Registry::default(),
+            tracking: HashMap::new(),
+            notify,
+            stats,
+            lazyfree: LazyFree::start(),
+            commands: commands::table(),
+            block_on: None,
+            serving_blocked: false,
+        }
+    }
+
+    /// The database the running command works on.
+    pub fn db(&mut self) -> &mut Db {
+        &mut self.dbs[self.selected]
+    }
+
+    /// Parses a database number argument, or produces the error reply for
+    /// one that isn't a number or names a database that doesn't exist.
+    pub fn db_index(&self, arg: &[u8]) -> Result<usize, Frame> {
+        let n = commands::parse_int(arg)?;
+        if n < 0 || n as usize >= self.dbs.len() {
+            return Err(commands::error("ERR DB index is out of range"));
+        }
+        Ok(n as usize)
+    }
+
+    /// Sweeps expired keys out of every database, sharing one time budget.
+    fn active_expire(&mut self) {
+        let start = Instant::now();
+        for db in &mut self.dbs {
+            let elapsed = start.elapsed();
+            if elapsed >= ACTIVE_EXPIRE_BUDGET {
+                break;
+            }
+            db.active_expire(ACTIVE_EXPIRE_BUDGET - elapsed);
+        }
+        self.publish_db_events();
+    }
+
+    fn apply(&mut self, event: Control) {
+        match event {
+            Control::Connected {
+                client,
+                addr,
+                local_addr,
+                push,
+            } => {
+                let info = ClientInfo::new(client, addr, local_addr, push);
+                self.clients.insert(client, info);
+            }
+            Control::Disconnected(client) => {
+                self.clients.remove(&client);
+                self.tracking.remove(&client);
+                self.blocked.unblock(client);
+                self.unsubscribe_all(client);
+            }
+            Control::HungUp(client) => {
+                // Nobody will read the replies, but sending what there is
+                // lets the session finish.
+                if let Some(blocked) = self.blocked.unblock(client) {
+                    let _ = blocked.batch.reply.send(blocked.batch.replies);
+                }
+            }
+        }
+    }
+
+    /// Blocks the client whose command is running until one of `keys` is
+    /// signalled as ready, or `timeout` passes; `None` waits forever. The
+    /// command's reply is what the client gets if it times out. When a key
+    /// is signalled the command runs again, and may block again.
+    pub fn block(&mut self, keys: Vec<Bytes>, timeout: Option<Duration>) {
+        self.block_on = Some(BlockOn {
+            keys,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            command: None,
+        });
+    }
+
+    /// Like `block`, but runs `command` rather than the command itself when
+    /// a key is signalled, for commands whose arguments mean something
+    /// different by then, as XREAD's "$" does.
+    pub fn block_as(&mut self, keys: Vec<Bytes>, timeout: Option<Duration>, command: Vec<Bytes>) {
+        self.block(keys, timeout);
+        if let Some(block_on) = &mut self.block_on {
+            block_on.command = Some(command);
+        }
+    }
+
+    /// Notes that `key` in the selected database may have something for
+    /// clients blocked on it.
+    pub fn signal_ready(&mut self, key: &[u8]) {
+        self.blocked.signal(self.selected, key);
+    }
+
+    /// Drops all of `client`'s channel, pattern and shard channel
+    /// subscriptions.
+    pub fn unsubscribe_all(&mut self, client: ClientId) {
+        self.channels.remove_client(client);
+        self.patterns.remove_client(client);
+        self.shard_channels.remove_client(client);
+    }
+
+    /// Sends `client` a frame outside of its replies.
+    pub fn push(&self, client: ClientId, frame: Frame) {
+        if let Some(info) = self.clients.get(&client) {
+            // The session may have ended already; then nobody's listening.
+            let _ = info.push.unbounded_send(Push::Frame(frame));
+        }
+    }
+
+    /// Delivers `message` to the clients subscribed to `channel`, and to
+    /// a pattern matching it. Returns how many deliveries there were: a
+    /// client gets the message once for each subscription it has that
+    /// matches.
+    pub fn publish(&self, channel: &Bytes, message: &Bytes) -> usize {
+        let mut receivers = 0;
+        for client in self.channels.subscribers(channel) {
+            let frame = Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"message")),
+                Frame::Bulk(channel.clone()),
+                Frame::Bulk(message.clone()),
+            ]);
+            self.push(client, frame);
+            receivers += 1;
+        }
+        for pattern in self.patterns.channels() {
+            if !glob::matches(pattern, channel, false) {
+                continue;
+            }
+            for client in self.patterns.subscribers(pattern) {
+                let frame = Frame::Array(vec![
+                    Frame::Bulk(Bytes::from_static(b"pmessage")),
+                    Frame::Bulk(pattern.clone()),
+                    Frame::Bulk(channel.clone()),
+                    Frame::Bulk(message.clone()),
+                ]);
+                self.push(client, frame);
+                receivers += 1;
+            }
+        }
+        receivers
+    }
+
+    /// Tells the clients tracking `key` that it changed, or with no key,
+    /// that every key did. The message goes to the client each one
+    /// redirects to, on `__redis__:invalidate`, as RESP2 clients get it
+    /// from Redis; like there, a redirect client not in pub/sub mode
+    /// misses it.
+    pub fn invalidate(&self, key: Option<&[u8]>) {
+        for tracking in self.tracking.values() {
+            if let Some(key) = key {
+                let tracked = tracking.prefixes.is_empty()
+                    || tracking
+                        .prefixes
+                        .iter()
+                        .any(|prefix| key.starts_with(prefix));
+                if !tracked {
+                    continue;
+                }
+            }
+            let subscribed = self
+                .clients
+                .get(&tracking.redirect)
+                .is_some_and(|info| info.class == ClientClass::Pubsub);
+            if !subscribed {
+                continue;
+            }
+            let keys = match key {
+                Some(key) => Frame::Array(vec![Frame::Bulk(Bytes::from(key))]),
+                None => Frame::NullArray,
+            };
+            let frame = Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"message")),
+                Frame::Bulk(Bytes::from_static(b"__redis__:invalidate")),
+                keys,
+            ]);
+            self.push(tracking.redirect, frame);
+        }
+    }
+
+    /// Delivers `message` to the clients subscribed to the shard channel
+    /// `channel`. Returns how many there were.
+    pub fn spublish(&self, channel: &Bytes, message: &Bytes) -> usize {
+        let mut receivers = 0;
+        for client in self.shard_channels.subscribers(channel) {
+            let frame = Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"smessage")),
+                Frame::Bulk(channel.clone()),
+                Frame::Bulk(message.clone()),
+            ]);
+            self.push(client, frame);
+            receivers += 1;
+        }
+        receivers
+    }
+
+    /// Publishes the keyspace notification that `event` happened to `key`
+    /// in the selected database, if events of class `class` are published.
+    pub fn notify(&mut self, class: Events, event: &str, key: &[u8]) {
+        self.notify_db(self.selected, class, event, key);
+    }
+
+    /// Like `notify`, for a key in database `db`.
+    pub fn notify_db(&mut self, db: usize, class: Events, event: &str, key: &[u8]) {
+        // Whatever the databases did on their own, such as expiring the
+        // key, happened first.
+        self.publish_db_events();
+        self.publish_event(db, class, event, key);
+    }
+
+    fn publish_event(&self, db: usize, class: Events, event: &str, key: &[u8]) {
+        // A new key comes with the event for whatever created it.
+        if class != Events::NEW {
+            self.invalidate(Some(key));
+        }
+        if !self.notify.publishes(class) {
+            return;
+        }
+        if self.notify.contains(Events::KEYSPACE) {
+            let mut channel = format!("__keyspace@{}__:", db).into_bytes();
+            channel.extend_from_slice(key);
+            self.publish(&channel.into(), &Bytes::from(event));
+        }
+        if self.notify.contains(Events::KEYEVENT) {
+            let channel = format!("__keyevent@{}__:{}", db, event);
+            self.publish(&channel.into(), &Bytes::from(key));
+        }
+    }
+
+    /// Publishes the notifications the databases have noted.
+    fn publish_db_events(&mut self) {
+        for db in 0..self.dbs.len() {
+            for (class, event, key) in self.dbs[db].take_events() {
+                self.publish_event(db, class, event, &key);
+            }
+        }
+    }
+
+    /// Runs a batch until it's done, then sends its replies, unless one of
+    /// its commands blocks first.
+    fn run(&mut self, mut batch: Batch) {
+        while let Some(args) = batch.commands.next() {
+            let reply = self.execute(batch.client, &args);
+            if let Some(block_on) = self.block_on.take() {
+                let blocked = Blocked {
+                    command: block_on.command.unwrap_or(args),
+                    db: self.selected,
+                    keys: block_on.keys,
+                    deadline: block_on.deadline,
+                    timeout_reply: reply,
+                    batch,
+                };
+                self.blocked.block(blocked.batch.client, blocked);
+                return;
+            }
+            batch.replies.push(reply);
+            self.serve_blocked();
+        }
+        // The client may have disconnected while waiting; that's fine.
+        let _ = batch.reply.send(batch.replies);
+    }
+
+    /// Runs the commands of clients blocked on keys that have been
+    /// signalled, until no more are.
+    fn serve_blocked(&mut self) {
+        if self.serving_blocked {
+            return;
+        }
+        self.serving_blocked = true;
+        while let Some(clients) = self.blocked.next_ready() {
+            for client in clients {
+                // An earlier client's batch may have ended this one's wait.
+                let command = match self.blocked.get(client) {
+                    Some(blocked) => blocked.command.clone(),
+                    None => continue,
+                };
+                let reply = self.execute(client, &command);
+                // Still nothing for it: it keeps its place and deadline.
+                if self.block_on.take().is_some() {
+                    continue;
+                }
+                let mut batch = self.blocked.unblock(client).unwrap().batch;
+                batch.replies.push(reply);
+                self.run(batch);
+            }
+        }
+        self.serving_blocked = false;
+    }
+
+    /// Unblocks clients whose deadline is at or before `now`, with the
+    /// reply their command gave for that.
+    fn time_out_blocked(&mut self, now: Instant) {
+        for client in self.blocked.timed_out(now) {
+            let blocked = self.blocked.unblock(client).unwrap();
+            let mut batch = blocked.batch;
+            batch.replies.push(blocked.timeout_reply);
+            self.run(batch);
+        }
+        self.serve_blocked();
+    }
+
+    /// Runs the commands of a transaction one after another, for EXEC. None
+    /// of them blocks: one that would gets the reply it gives on timing out.
+    pub fn run_transaction(&mut self, client: ClientId, commands: &[Vec<Bytes>]) -> Vec<Frame> {
+        commands
+            .iter()
+            .map(|args| {
+                let reply = self.execute(client, args);
+                self.block_on = None;
+                reply
+            })
+            .collect()
+    }
+
+    /// Looks the command up, checks it may run and runs it, or between
+    /// MULTI and EXEC, queues it. A command refused while queueing makes
+    /// the transaction fail.
+    fn execute(&mut self, client: ClientId, args: &[Bytes]) -> Frame {
+        let command = match self.check(client, args) {
+            Ok(command) => command,
+            Err(e) => {
+                let transaction = self
+                    .clients
+                    .get_mut(&client)
+                    .and_then(|info| info.transaction.as_mut());
+                if let Some(transaction) = transaction {
+                    transaction.aborted = true;
+                }
+                return e;
+            }
+        };
+        let info = self.clients.get_mut(&client).unwrap();
+        if let Some(transaction) = &mut info.transaction {
+            if command.queued_in_transaction() {
+                transaction.commands.push(args.to_vec());
+                return Frame::Simple("QUEUED".to_string());
+            }
+        }
+        let reply = (command.handler)(self, client, args);
+        self.publish_db_events();
+        reply
+    }
+
+    /// Looks the command up and checks that `client` may run it with
+    /// `args`, noting the interaction in the client's info.
+    fn check(&mut self, client: ClientId, args: &[Bytes]) -> Result<&'static Command, Frame> {
+        let name = args[0].to_ascii_lowercase();
+        let command = match self.commands.get(&name[..]) {
+            Some(&command) => command,
+            None => return Err(unknown_command(args)),
+        };
+
+        let info = match self.clients.get_mut(&client) {
+            Some(info) => info,
+            None => return Err(commands::error("ERR unknown client")),
+        };
+        info.last_interaction = Instant::now();
+        self.selected = info.db;
+        info.last_command = match args.get(1) {
+            Some(sub) if command.subcommands => format!(
+                "{}|{}",
+                command.name,
+                String::from_utf8_lossy(sub).to_ascii_lowercase()
+            ),
+            _ => command.name.to_string(),
+        };
+
+        if !command.arity_ok(args.len()) {
+            return Err(commands::wrong_arity(command.name));
+        }
+        if info.class == ClientClass::Pubsub && !command.allowed_when_subscribed() {
+            return Err(commands::error(format!(
+                "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                info.last_command
+            )));
+        }
+        Ok(command)
+    }
+}
+
+fn unknown_command(args: &[Bytes]) -> Frame {
+    let mut msg = format!(
+        "ERR unknown command '{}', with args beginning with: ",
+        String::from_utf8_lossy(&args[0])
+    );
+    for arg in &args[1..] {
+        msg.push_str(&format!("'{}' ", String::from_utf8_lossy(arg)));
+    }
+    commands::error(msg)
+}
+
+#[cfg(test)]
+impl Keyspace {
+    /// An empty keyspace with the default 16 databases, to run commands
+    /// against in tests.
+    pub fn testing() -> Keyspace {
+        Keyspace::new(16, Events::default(), Arc::default())
+    }
+
+    /// Registers a client, as if it had just connected, and returns it
+    /// with what would reach its session besides replies.
+    pub fn test_client(&mut self) -> (ClientId, mpsc::UnboundedReceiver<Push>) {
+        let client = ClientId::next();
+        let addr = "127.0.0.1:6379".parse().unwrap();
+        let (push, pushed) = mpsc::unbounded();
+        self.apply(Control::Connected {
+            client,
+            addr,
+            local_addr: addr,
+            push,
+        });
+        (client, pushed)
+    }
+
+    /// Runs one command as `client`, as if it had come in a batch.
+    pub fn command(&mut self, client: ClientId, args: &[&str]) -> Frame {
+        let args: Vec<Bytes> = args.iter().map(|arg| Bytes::from(*arg)).collect();
+        self.execute(client, &args)
+    }
+}
@@ -0,0 +1,32 @@
+//! Freeing large values off the keyspace task.
+//!
+//! Dropping a whole database, or one enormous value, can take long enough
+//! to hold up every client waiting on the keyspace. Anything handed to
+//! `LazyFree` is instead sent to a dedicated thread that does nothing but
+//! drop what it receives, like Redis' lazyfree background thread.
+
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+pub struct LazyFree {
+    queue: Sender<Box<dyn Send>>,
+}
+
+impl LazyFree {
+    /// Starts the background thread.
+    pub fn start() -> LazyFree {
+        let (queue, garbage) = mpsc::channel::<Box<dyn Send>>();
+        thread::Builder::new()
+            .name("lazyfree".to_string())
+            .spawn(move || garbage.into_iter().for_each(drop))
+            .expect("failed to start the lazyfree thread");
+        LazyFree { queue }
+    }
+
+    /// Drops `value` on the background thread.
+    pub fn free<T: Send + 'static>(&self, value: T) {
+        // The thread only stops once we're gone, but if it has died somehow
+        // the value comes back and is dropped here instead.
+        let _ = self.queue.send(Box::new(value));
+    }
+}
@@ -1,55 +1,119 @@
-//! A chat server that broadcasts a message to all connections.
+//! A Redis-protocol server.
 //!
-//! This is a line-based server which accepts connections, reads lines from
-//! those connections, and broadcasts the lines to all other connected clients.
+//! The server accepts connections, decodes commands from them (see the
+//! `resp` module) and runs them against an in-memory keyspace. Each
+//! connection is driven by its own `CacheSession` task, and all of them
+//! share one keyspace task that owns the data (see the `keyspace` module).
+//! The commands themselves live in `commands`.
 //!
-//! This example is similar to chat.rs, but uses combinators and a much more
-//! functional style.
+//! This started out similar to tokio's chat.rs example, but uses combinators
+//! and a much more functional style.
 //!
 //! You can test this out by running:
 //!
-//!     cargo run --example chat
+//!     cargo run -- 127.0.0.1:8080
 //!
 //! And then in another window run:
 //!
-//!     cargo run --example connect 127.0.0.1:8080
+//!     redis-cli -p 8080 set hello world
 //!
-//! You can run the second command in multiple windows and then chat between the
-//! two, seeing the messages from the other client as they're received. For all
-//! connected clients they'll all join the same room and see everyone else's
-//! messages.
+//! See the `config` module for the other settings.
 
 #![deny(warnings)]
 
+extern crate bytes;
 extern crate futures;
 extern crate tokio;
 
+mod blocking;
+mod cache_session;
+mod client;
+mod commands;
+mod config;
+mod db;
+mod dict;
+mod geohash;
+mod glob;
+mod hash;
+mod ipfilter;
+mod keyspace;
+mod lazyfree;
+mod notify;
+mod pubsub;
+mod ratelimit;
+mod resp;
+mod shutdown;
+mod stats;
+mod stream;
+mod zset;
+
+use bytes::BytesMut;
 use tokio::io;
 use tokio::net::TcpListener;
 use tokio::prelude::*;
+use tokio::reactor::Handle;
+use tokio::sync::watch;
 
-use std::collections::HashMap;
 use std::env;
-use std::io::BufReader;
-use std::iter;
-use std::sync::{Arc, Mutex};
+use std::net::SocketAddr;
+use std::process;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cache_session::CacheSession;
+use client::ClientId;
+use config::Config;
+use ratelimit::Limiters;
+use resp::Frame;
+use stats::Stats;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = match Config::from_args(env::args()) {
+        Ok(config) => config,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            process::exit(1);
+        }
+    };
+    if config.check_only {
+        println!("Configuration OK");
+        return Ok(());
+    }
+    let config = Arc::new(config);
+
     // Create the TCP listener we'll accept connections on.
-    let addr = env::args().nth(1).unwrap_or("127.0.0.1:8080".to_string());
-    let addr = addr.parse()?;
+    let addr: SocketAddr = config.addr.parse()?;
 
-    let socket = TcpListener::bind(&addr)?;
+    // Bind through std and hand the socket to tokio: mio 0.6's own bind
+    // path converts the address assuming an old `SocketAddr` layout and
+    // fails with EAFNOSUPPORT on current Rust releases.
+    let socket = std::net::TcpListener::bind(addr)?;
+    let socket = TcpListener::from_std(socket, &Handle::default())?;
     println!("Listening on: {}", addr);
 
-    // This is running on the Tokio runtime, so it will be multi-threaded. The
-    // `Arc<Mutex<...>>` allows state to be shared across the threads.
-    let connections = Arc::new(Mutex::new(HashMap::new()));
-    let kv_store = Arc::new(Mutex::new(HashMap::new()));
+    // This is running on the Tokio runtime, so it will be multi-threaded.
+    // Sessions reach the data through the keyspace task; the rest of the
+    // shared state sits behind an `Arc`.
+    let stats = Arc::new(Stats::default());
+    let (keyspace, keyspace_service) = keyspace::service(
+        config.databases,
+        config.notify_keyspace_events,
+        stats.clone(),
+    );
+    let limiters = Arc::new(Limiters::new(config.clone()));
+
+    // Sessions watch this to learn that the server is shutting down.
+    let (mut shutdown_tx, shutdown_rx) = watch::channel(false);
+    shutdown::install_handlers();
 
     // The server task asynchronously iterates over and processes each incoming
     // connection.
-    let srv = socket
+    let drain_deadline = Duration::from_secs(config.shutdown_timeout);
+    let accept_stats = stats.clone();
+    let accept = socket
         .incoming()
         .map_err(|e| {
             println!("failed to accept socket; error = {:?}", e);
@@ -59,90 +123,79 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             // The client's socket address
             let addr = stream.peer_addr()?;
 
-            println!("New Connection: {}", addr);
-
-            // Split the TcpStream into two separate handles. One handle for reading
-            // and one handle for writing. This lets us use separate tasks for
-            // reading and writing.
-            let (reader, writer) = stream.split();
-
-            // Create a channel for our stream, which other sockets will use to
-            // send us messages. Then register our address with the stream to send
-            // data to us.
-            let (tx, rx) = futures::sync::mpsc::unbounded();
-            connections.lock().unwrap().insert(addr, tx);
-
-            // Define here what we do for the actual I/O. That is, read a bunch of
-            // lines from the socket and dispatch them while we also write any lines
-            // from other sockets.
-            let connections_inner = connections.clone();
-            let kv_store_inner = kv_store.clone();
-            let reader = BufReader::new(reader);
-
-            // Model the read portion of this socket by mapping an infinite
-            // iterator to each line off the socket. This "loop" is then
-            // terminated with an error once we hit EOF on the socket.
-            let iter = stream::iter_ok::<_, io::Error>(iter::repeat(()));
-
-            let socket_reader = iter.fold(reader, move |reader, _| {
-                // Read a line off the socket, failing if we're at EOF
-                let line = io::read_until(reader, b'\n', Vec::new());
-                let line = line.and_then(|(reader, vec)| {
-                    if vec.len() == 0 {
-                        Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"))
-                    } else {
-                        Ok((reader, vec))
-                    }
-                });
-
-                // Convert the bytes we read into a string, and then send that
-                // string to all other connected clients.
-                let line = line.map(|(reader, vec)| (reader, String::from_utf8(vec)));
-
-                // Move the connection state into the closure below.
-                let connections = connections_inner.clone();
-                let kv_store_handle = kv_store_inner.clone();
-
-                line.map(move |(reader, message)| {
-                    println!("{}: {:?}", addr, message);
-                    let mut conns = connections.lock().unwrap();
-
-                    if let Ok(msg) = message {
-                        let mut kv = kv_store_handle.lock().unwrap();
-                        kv.insert(format!("{}", addr), format!("{}", msg));
-
-                        let tx = conns.get_mut(&addr).unwrap();
-                        tx.unbounded_send(format!("+{}", msg)).unwrap();
-                        println!("thing now {:?}", kv);
-                    } else {
-                        let tx = conns.get_mut(&addr).unwrap();
-                        tx.unbounded_send("You didn't send valid UTF-8.".to_string())
-                            .unwrap();
-                    }
-
-                    reader
-                })
-            });
-
-            // Whenever we receive a string on the Receiver, we write it to
-            // `WriteHalf<TcpStream>`.
-            let socket_writer = rx.fold(writer, |writer, msg| {
-                let amt = io::write_all(writer, msg.into_bytes());
-                let amt = amt.map(|(writer, _)| writer);
-                amt.map_err(|_| ())
-            });
-
-            // Now that we've got futures representing each half of the socket, we
-            // use the `select` combinator to wait for either half to be done to
-            // tear down the other. Then we spawn off the result.
-            let connections = connections.clone();
-            let socket_reader = socket_reader.map_err(|_| ());
-            let connection = socket_reader.map(|_| ()).select(socket_writer.map(|_| ()));
+            // Turn away filtered addresses before reading anything from them;
+            // dropping the stream closes it.
+            if !config.ip_filter.permits(addr.ip()) {
+                let rejected = Stats::incr(&accept_stats.rejected_connections);
+                println!(
+                    "Rejected connection from {} ({} rejected so far)",
+                    addr, rejected
+                );
+                return Ok(());
+            }
+
+            // Past maxclients, tell the client why before hanging up rather
+            // than starting a session for it.
+            let connected = Stats::incr(&accept_stats.connected_clients);
+            if connected > config.maxclients {
+                Stats::decr(&accept_stats.connected_clients);
+                let rejected = Stats::incr(&accept_stats.rejected_connections);
+                let reason = "max number of clients reached";
+                println!(
+                    "Rejected connection from {}: {} ({} rejected so far)",
+                    addr, reason, rejected
+                );
+                let reply = Frame::Error(format!("ERR {}", reason));
+                let mut buf = BytesMut::new();
+                reply.encode(&mut buf);
+                tokio::spawn(io::write_all(stream, buf).then(|_| Ok(())));
+                return Ok(());
+            }
+
+            let id = ClientId::next();
+            println!("{} New Connection: {}", id, addr);
+            let (push, pushed) = futures::sync::mpsc::unbounded();
+            keyspace.connected(id, addr, stream.local_addr()?, push);
+
+            // A socket we can't tune still works, so just note the failure.
+            let tuned =
+                stream
+                    .set_nodelay(config.tcp_nodelay)
+                    .and_then(|_| match config.tcp_keepalive {
+                        0 => Ok(()),
+                        secs => stream.set_keepalive(Some(Duration::from_secs(secs))),
+                    });
+            if let Err(e) = tuned {
+                println!("{} failed to set socket options: {}", id, e);
+            }
+
+            // Rate limiting state for this client, possibly shared with the
+            // other connections from the same IP.
+            let limiter = limiters.acquire(addr.ip());
+
+            let session = CacheSession::new(
+                &config,
+                id,
+                addr,
+                stream,
+                keyspace.clone(),
+                limiter.clone(),
+                pushed,
+                shutdown_rx.clone(),
+            );
 
             // Spawn a task to process the connection
-            tokio::spawn(connection.then(move |_| {
-                connections.lock().unwrap().remove(&addr);
-                println!("Connection {} closed.", addr);
+            let limiters = limiters.clone();
+            let stats = accept_stats.clone();
+            let keyspace = keyspace.clone();
+            tokio::spawn(session.then(move |result| {
+                if let Err(e) = result {
+                    println!("{} error: {}", id, e);
+                }
+                keyspace.disconnected(id);
+                limiters.release(addr.ip(), limiter);
+                Stats::decr(&stats.connected_clients);
+                println!("{} Connection {} closed.", id, addr);
                 Ok(())
             }));
 
@@ -150,7 +203,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .map_err(|err| println!("error occurred: {:?}", err));
 
+    // Accept connections until we're signalled, then drop the listener, tell
+    // every session to drain, and wait for them to finish.
+    let srv = accept
+        .select2(shutdown::signalled())
+        .then(move |_| {
+            let connected = stats.connected_clients.load(Ordering::SeqCst);
+            println!("Shutting down, draining {} connections", connected);
+            let _ = shutdown_tx.broadcast(true);
+            shutdown::drain(stats, drain_deadline)
+        })
+        .map(|_| println!("All connections drained, exiting"));
+
     // execute server
-    tokio::run(srv);
+    tokio::run(future::lazy(move || {
+        tokio::spawn(keyspace_service);
+        srv
+    }));
     Ok(())
 }
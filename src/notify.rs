@@ -0,0 +1,111 @@
+//! Keyspace notifications: pub/sub messages about changes to keys, for
+//! clients that want to react to them.
+//!
+//! Each change is published on up to two channels. `__keyspace@<db>__:<key>`
+//! gets the name of the event, and `__keyevent@<db>__:<event>` the key.
+//! Which changes are published, and on which of the two channels, is set
+//! with `notify-keyspace-events`, using Redis' letters:
+//!
+//!     K  keyspace channel      E  keyevent channel
+//!     g  generic commands      $  strings
+//!     l  lists                 s  sets
+//!     h  hashes                z  sorted sets
+//!     t  streams               x  expired keys
+//!     e  evicted keys          n  new keys
+//!     A  alias for g$lshztxe
+//!
+//! Nothing is published unless at least one of K and E is given too. Keys
+//! are never evicted yet, so `e` has nothing to announce.
+
+use std::ops::BitOr;
+use std::str::FromStr;
+
+use crate::config::ConfigError;
+
+/// A set of the classes of event to publish, and where to.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Events(u16);
+
+impl Events {
+    pub const KEYSPACE: Events = Events(1 << 0);
+    pub const KEYEVENT: Events = Events(1 << 1);
+    /// Commands that work on any type: DEL, EXPIRE, RENAME and the like.
+    pub const GENERIC: Events = Events(1 << 2);
+    pub const STRING: Events = Events(1 << 3);
+    pub const LIST: Events = Events(1 << 4);
+    pub const SET: Events = Events(1 << 5);
+    pub const HASH: Events = Events(1 << 6);
+    pub const ZSET: Events = Events(1 << 7);
+    pub const STREAM: Events = Events(1 << 8);
+    pub const EXPIRED: Events = Events(1 << 9);
+    pub const EVICTED: Events = Events(1 << 10);
+    /// A key being created. Not part of `A`, as it's rarely wanted and
+    /// fires for every write that makes a key.
+    pub const NEW: Events = Events(1 << 11);
+
+    /// Every class `A` stands for.
+    const ALL: Events = Events(
+        Events::GENERIC.0
+            | Events::STRING.0
+            | Events::LIST.0
+            | Events::SET.0
+            | Events::HASH.0
+            | Events::ZSET.0
+            | Events::STREAM.0
+            | Events::EXPIRED.0
+            | Events::EVICTED.0,
+    );
+
+    /// Whether every event in `other` is in the set.
+    pub fn contains(self, other: Events) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether events of class `class` are published anywhere.
+    pub fn publishes(self, class: Events) -> bool {
+        self.contains(class) && (self.contains(Events::KEYSPACE) || self.contains(Events::KEYEVENT))
+    }
+}
+
+impl BitOr for Events {
+    type Output = Events;
+
+    fn bitor(self, other: Events) -> Events {
+        Events(self.0 | other.0)
+    }
+}
+
+impl FromStr for Events {
+    type Err = ConfigError;
+
+    /// Parses a `notify-keyspace-events` value, such as "KEA" or "Elg".
+    /// The empty string turns notifications off.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut events = Events::default();
+        for c in s.chars() {
+            events = events
+                | match c {
+                    'K' => Events::KEYSPACE,
+                    'E' => Events::KEYEVENT,
+                    'g' => Events::GENERIC,
+                    '$' => Events::STRING,
+                    'l' => Events::LIST,
+                    's' => Events::SET,
+                    'h' => Events::HASH,
+                    'z' => Events::ZSET,
+                    't' => Events::STREAM,
+                    'x' => Events::EXPIRED,
+                    'e' => Events::EVICTED,
+                    'n' => Events::NEW,
+                    'A' => Events::ALL,
+                    _ => {
+                        return Err(ConfigError::new(format!(
+                            "unknown keyspace event class '{}'",
+                            c
+                        )))
+                    }
+                };
+        }
+        Ok(events)
+    }
+}
@@ -0,0 +1,87 @@
+//! Who is subscribed to what, for PUBLISH to find the clients a message
+//! goes to. A `Registry` holds one kind of subscription: SUBSCRIBE's
+//! channels, PSUBSCRIBE's patterns or SSUBSCRIBE's shard channels, all
+//! treated just the same.
+//!
+//! The registry is kept both ways round: by channel, to deliver messages,
+//! and by client, to answer how many subscriptions it has and to drop them
+//! all when it disconnects. A channel is only listed while somebody is
+//! subscribed to it.
+
+use bytes::Bytes;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::client::ClientId;
+
+#[derive(Default)]
+pub struct Registry {
+    channels: HashMap<Bytes, HashSet<ClientId>>,
+    clients: HashMap<ClientId, HashSet<Bytes>>,
+}
+
+impl Registry {
+    /// Subscribes `client` to `channel`. Returns false if it already was.
+    pub fn subscribe(&mut self, client: ClientId, channel: Bytes) -> bool {
+        if !self
+            .clients
+            .entry(client)
+            .or_default()
+            .insert(channel.clone())
+        {
+            return false;
+        }
+        self.channels.entry(channel).or_default().insert(client);
+        true
+    }
+
+    /// Unsubscribes `client` from `channel`. Returns false if it wasn't
+    /// subscribed.
+    pub fn unsubscribe(&mut self, client: ClientId, channel: &[u8]) -> bool {
+        let subscribed = match self.clients.get_mut(&client) {
+            Some(channels) => channels.remove(channel),
+            None => false,
+        };
+        if !subscribed {
+            return false;
+        }
+        if self.clients[&client].is_empty() {
+            self.clients.remove(&client);
+        }
+        let subscribers = self.channels.get_mut(channel).unwrap();
+        subscribers.remove(&client);
+        if subscribers.is_empty() {
+            self.channels.remove(channel);
+        }
+        true
+    }
+
+    /// The channels `client` is subscribed to.
+    pub fn subscriptions(&self, client: ClientId) -> Vec<Bytes> {
+        self.clients
+            .get(&client)
+            .map_or_else(Vec::new, |channels| channels.iter().cloned().collect())
+    }
+
+    /// How many channels `client` is subscribed to.
+    pub fn count(&self, client: ClientId) -> usize {
+        self.clients.get(&client).map_or(0, HashSet::len)
+    }
+
+    /// The clients subscribed to `channel`.
+    pub fn subscribers(&self, channel: &[u8]) -> impl Iterator<Item = ClientId> + '_ {
+        self.channels.get(channel).into_iter().flatten().copied()
+    }
+
+    /// Every channel with at least one subscriber.
+    pub fn channels(&self) -> impl Iterator<Item = &Bytes> {
+        self.channels.keys()
+    }
+
+    /// Drops all of `client`'s subscriptions.
+    pub fn remove_client(&mut self, client: ClientId) {
+        for channel in self.subscriptions(client) {
+            self.unsubscribe(client, &channel);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Token-bucket rate limiting for client traffic.
+//!
+//! Each limited connection owns (or, when limiting per IP, shares) a
+//! `Limiter` holding up to two buckets: one counting commands and one
+//! counting bytes read off the socket.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, RateLimitScope};
+
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// A bucket refilling at `rate` tokens per second that can hold `burst`
+    /// seconds worth of tokens. It starts full.
+    pub fn new(rate: u64, burst: f64) -> TokenBucket {
+        let rate = rate as f64;
+        TokenBucket {
+            rate,
+            capacity: rate * burst,
+            tokens: rate * burst,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last);
+        self.last = now;
+
+        let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+        self.tokens = (self.tokens + secs * self.rate).min(self.capacity);
+    }
+
+    /// Takes `n` tokens if they are all available.
+    pub fn try_take(&mut self, n: f64) -> bool {
+        self.refill();
+        if self.tokens >= n {
+            self.tokens -= n;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes `n` tokens unconditionally, letting the bucket go into debt, and
+    /// returns how long the caller should wait for the debt to be repaid.
+    pub fn take(&mut self, n: f64) -> Duration {
+        self.refill();
+        self.tokens -= n;
+        if self.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            let wait = -self.tokens / self.rate;
+            Duration::new(wait.trunc() as u64, (wait.fract() * 1e9) as u32)
+        }
+    }
+}
+
+pub struct Limiter {
+    cmds: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Limiter {
+    pub fn new(config: &Config) -> Limiter {
+        let bucket = |rate| {
+            if rate > 0 {
+                Some(TokenBucket::new(rate, config.rate_limit_burst))
+            } else {
+                None
+            }
+        };
+
+        Limiter {
+            cmds: bucket(config.rate_limit_cmds),
+            bytes: bucket(config.rate_limit_bytes),
+        }
+    }
+
+    /// Returns false when the client has used up its command allowance, in
+    /// which case the command should be refused.
+    pub fn allow_command(&mut self) -> bool {
+        match self.cmds {
+            Some(ref mut bucket) => bucket.try_take(1.0),
+            None => true,
+        }
+    }
+
+    /// Accounts for `n` bytes read and returns how long to hold off reading
+    /// more from the socket.
+    pub fn consume_bytes(&mut self, n: usize) -> Duration {
+        match self.bytes {
+            Some(ref mut bucket) => bucket.take(n as f64),
+            None => Duration::from_secs(0),
+        }
+    }
+}
+
+/// Hands out limiters to new connections according to the configured scope.
+pub struct Limiters {
+    config: Arc<Config>,
+    per_ip: Mutex<HashMap<IpAddr, Shared>>,
+}
+
+/// A per-IP limiter and how many open connections from that IP use it.
+struct Shared {
+    limiter: Arc<Mutex<Limiter>>,
+    connections: usize,
+}
+
+impl Limiters {
+    pub fn new(config: Arc<Config>) -> Limiters {
+        Limiters {
+            config,
+            per_ip: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The limiter a connection from `ip` should use, or `None` when rate
+    /// limiting is switched off.
+    pub fn acquire(&self, ip: IpAddr) -> Option<Arc<Mutex<Limiter>>> {
+        if self.config.rate_limit_cmds == 0 && self.config.rate_limit_bytes == 0 {
+            return None;
+        }
+
+        match self.config.rate_limit_scope {
+            RateLimitScope::Client => Some(Arc::new(Mutex::new(Limiter::new(&self.config)))),
+            RateLimitScope::Ip => {
+                let mut per_ip = self.per_ip.lock().unwrap();
+                let shared = per_ip.entry(ip).or_insert_with(|| Shared {
+                    limiter: Arc::new(Mutex::new(Limiter::new(&self.config))),
+                    connections: 0,
+                });
+                shared.connections += 1;
+                Some(shared.limiter.clone())
+            }
+        }
+    }
+
+    /// Called when a connection closes so per-IP buckets don't outlive the
+    /// last connection from that address.
+    pub fn release(&self, ip: IpAddr, limiter: Option<Arc<Mutex<Limiter>>>) {
+        if limiter.is_none() || self.config.rate_limit_scope != RateLimitScope::Ip {
+            return;
+        }
+        let mut per_ip = self.per_ip.lock().unwrap();
+        if let Some(shared) = per_ip.get_mut(&ip) {
+            shared.connections -= 1;
+            if shared.connections == 0 {
+                per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_ip_limiter_lasts_until_last_connection_closes() {
+        let config = Config {
+            rate_limit_cmds: 10,
+            rate_limit_scope: RateLimitScope::Ip,
+            ..Config::default()
+        };
+        let limiters = Limiters::new(Arc::new(config));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let a = limiters.acquire(ip);
+        let b = limiters.acquire(ip);
+        assert!(Arc::ptr_eq(a.as_ref().unwrap(), b.as_ref().unwrap()));
+
+        // Both still held elsewhere, as a closing session's may be.
+        limiters.release(ip, a.clone());
+        assert!(limiters.per_ip.lock().unwrap().contains_key(&ip));
+        limiters.release(ip, b.clone());
+        assert!(limiters.per_ip.lock().unwrap().is_empty());
+    }
+}
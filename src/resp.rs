@@ -0,0 +1,475 @@
+//! RESP2, the Redis serialization protocol.
+//!
+//! Clients send each command as an array of bulk strings and get back a
+//! single frame of any type. `RespCodec` plugs the parser and encoder into
+//! `tokio::codec`, so a socket can be read as a stream of commands and
+//! written as a sink of `Frame`s.
+//!
+//! Like Redis, the decoder also accepts "inline" commands: a line of plain,
+//! space-separated text such as `SET foo bar`, so the server can be driven
+//! from telnet or netcat. Anything that doesn't start with `*` is read that
+//! way.
+
+use bytes::{Bytes, BytesMut};
+use tokio::codec::{Decoder, Encoder};
+
+use std::fmt;
+use std::io;
+use std::str;
+
+// Not every frame type has a command replying with it yet.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Bytes),
+    /// The null bulk string, `$-1`.
+    Null,
+    Array(Vec<Frame>),
+    /// The null array, `*-1`.
+    NullArray,
+    /// Several frames sent one after another, for commands such as
+    /// SUBSCRIBE that reply once per argument. Not a RESP type itself.
+    Many(Vec<Frame>),
+}
+
+/// Largest bulk string a client may send, matching Redis' default
+/// `proto-max-bulk-len` of 512MB.
+const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+
+/// Largest number of elements a client may declare in an array header.
+const MAX_ARRAY_LEN: i64 = i32::MAX as i64;
+
+/// Longest line we buffer while waiting for its end: an inline command, or
+/// an array or bulk string header, which is only ever a few digits long.
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Cap on how many elements we allocate for up front, so a huge array
+/// header alone can't make us reserve gigabytes.
+const MAX_PREALLOC: usize = 1024;
+
+impl Frame {
+    /// Appends the wire encoding of the frame to `dst`.
+    pub fn encode(&self, dst: &mut BytesMut) {
+        match *self {
+            Frame::Simple(ref s) => put_line(dst, b'+', s.as_bytes()),
+            Frame::Error(ref s) => put_line(dst, b'-', s.as_bytes()),
+            Frame::Integer(n) => put_line(dst, b':', n.to_string().as_bytes()),
+            Frame::Bulk(ref data) => {
+                put_line(dst, b'$', data.len().to_string().as_bytes());
+                dst.extend_from_slice(data);
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Null => dst.extend_from_slice(b"$-1\r\n"),
+            Frame::Array(ref items) => {
+                put_line(dst, b'*', items.len().to_string().as_bytes());
+                for item in items {
+                    item.encode(dst);
+                }
+            }
+            Frame::NullArray => dst.extend_from_slice(b"*-1\r\n"),
+            Frame::Many(ref frames) => {
+                for frame in frames {
+                    frame.encode(dst);
+                }
+            }
+        }
+    }
+}
+
+fn put_line(dst: &mut BytesMut, prefix: u8, body: &[u8]) {
+    dst.extend_from_slice(&[prefix]);
+    dst.extend_from_slice(body);
+    dst.extend_from_slice(b"\r\n");
+}
+
+/// Why a request couldn't be decoded.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The malformed request has been consumed, so decoding can carry on
+    /// with whatever follows it.
+    Skipped(String),
+    /// There's no telling where the malformed request ends; nothing after it
+    /// can be trusted and the connection should be closed.
+    Fatal(String),
+    Io(io::Error),
+}
+
+impl DecodeError {
+    /// The error reply that tells the client what went wrong.
+    pub fn reply(&self) -> Frame {
+        match *self {
+            DecodeError::Skipped(ref msg) | DecodeError::Fatal(ref msg) => {
+                Frame::Error(format!("ERR Protocol error: {}", msg))
+            }
+            DecodeError::Io(ref e) => Frame::Error(format!("ERR {}", e)),
+        }
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(e: io::Error) -> DecodeError {
+        DecodeError::Io(e)
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Skipped(ref msg) | DecodeError::Fatal(ref msg) => {
+                write!(f, "Protocol error: {}", msg)
+            }
+            DecodeError::Io(ref e) => e.fmt(f),
+        }
+    }
+}
+
+fn fatal(msg: &str) -> DecodeError {
+    DecodeError::Fatal(msg.to_string())
+}
+
+/// Incremental parser over whatever has been buffered so far. Every method
+/// returns `Ok(None)` when the input ends before the frame does, in which
+/// case the caller waits for more bytes and starts over.
+struct Parser<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// The next `\r\n`-terminated line, without the terminator.
+    fn line(&mut self) -> Option<&'a [u8]> {
+        let rest = &self.buf[self.pos..];
+        let end = rest.windows(2).position(|w| w == b"\r\n")?;
+        self.pos += end + 2;
+        Some(&rest[..end])
+    }
+
+    fn int(&mut self, what: &str) -> Result<Option<i64>, DecodeError> {
+        match self.line() {
+            Some(line) => str::from_utf8(line)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .map(Some)
+                .ok_or_else(|| fatal(&format!("invalid {}", what))),
+            None if self.buf.len() - self.pos > MAX_LINE_LEN => {
+                Err(fatal(&format!("invalid {}", what)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// A command sent as RESP: an array of bulk strings. Redis accepts
+    /// nothing else from clients, so neither do we. An empty array comes
+    /// back as no arguments at all.
+    fn command(&mut self) -> Result<Option<Vec<Bytes>>, DecodeError> {
+        self.pos += 1;
+        let len = match self.int("multibulk length")? {
+            None => return Ok(None),
+            Some(len) if len <= 0 => return Ok(Some(Vec::new())),
+            Some(len) if len > MAX_ARRAY_LEN => return Err(fatal("invalid multibulk length")),
+            Some(len) => len as usize,
+        };
+
+        let mut args = Vec::with_capacity(len.min(MAX_PREALLOC));
+        for _ in 0..len {
+            match self.bulk()? {
+                Some(arg) => args.push(arg),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(args))
+    }
+
+    fn bulk(&mut self) -> Result<Option<Bytes>, DecodeError> {
+        match self.buf.get(self.pos) {
+            None => return Ok(None),
+            Some(b'$') => self.pos += 1,
+            Some(&other) => {
+                return Err(fatal(&format!("expected '$', got '{}'", other as char)));
+            }
+        }
+
+        let len = match self.int("bulk length")? {
+            None => return Ok(None),
+            Some(len) if !(0..=MAX_BULK_LEN).contains(&len) => {
+                return Err(fatal("invalid bulk length"));
+            }
+            Some(len) => len as usize,
+        };
+        let rest = &self.buf[self.pos..];
+        if rest.len() < len + 2 {
+            return Ok(None);
+        }
+        if &rest[len..len + 2] != b"\r\n" {
+            return Err(fatal("bulk string not terminated by CRLF"));
+        }
+        self.pos += len + 2;
+        Ok(Some(Bytes::from(&rest[..len])))
+    }
+
+    /// An inline command, read up to the next newline and split into the
+    /// same arguments a RESP client would have sent.
+    fn inline(&mut self) -> Result<Option<Vec<Bytes>>, DecodeError> {
+        let rest = &self.buf[self.pos..];
+        let end = match rest.iter().position(|&b| b == b'\n') {
+            Some(end) => end,
+            None if rest.len() > MAX_LINE_LEN => {
+                return Err(fatal("too big inline request"));
+            }
+            None => return Ok(None),
+        };
+        self.pos += end + 1;
+
+        let line = &rest[..end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let args = split_args(line)
+            .ok_or_else(|| DecodeError::Skipped("unbalanced quotes in request".to_string()))?;
+        Ok(Some(args.into_iter().map(Bytes::from).collect()))
+    }
+}
+
+/// Splits an inline command into arguments the way `redis-cli` and Redis'
+/// `sdssplitargs` do: arguments are separated by whitespace and may be
+/// wrapped in double quotes (with C-style and `\xHH` escapes) or single
+/// quotes (where only `\'` is special). Returns `None` on unbalanced quotes.
+fn split_args(line: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Some(args);
+        }
+
+        let mut arg = Vec::new();
+        let mut in_double = false;
+        let mut in_single = false;
+        loop {
+            let c = line.get(i).cloned();
+            if in_double {
+                match c {
+                    None => return None,
+                    Some(b'\\')
+                        if i + 3 < line.len()
+                            && line[i + 1] == b'x'
+                            && is_hex(line[i + 2])
+                            && is_hex(line[i + 3]) =>
+                    {
+                        arg.push(hex(line[i + 2]) * 16 + hex(line[i + 3]));
+                        i += 3;
+                    }
+                    Some(b'\\') if i + 1 < line.len() => {
+                        i += 1;
+                        arg.push(match line[i] {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 8,
+                            b'a' => 7,
+                            other => other,
+                        });
+                    }
+                    Some(b'"') => {
+                        // The closing quote must end the argument.
+                        match line.get(i + 1) {
+                            Some(next) if !next.is_ascii_whitespace() => return None,
+                            _ => {}
+                        }
+                        i += 1;
+                        break;
+                    }
+                    Some(c) => arg.push(c),
+                }
+            } else if in_single {
+                match c {
+                    None => return None,
+                    Some(b'\\') if line.get(i + 1) == Some(&b'\'') => {
+                        i += 1;
+                        arg.push(b'\'');
+                    }
+                    Some(b'\'') => {
+                        match line.get(i + 1) {
+                            Some(next) if !next.is_ascii_whitespace() => return None,
+                            _ => {}
+                        }
+                        i += 1;
+                        break;
+                    }
+                    Some(c) => arg.push(c),
+                }
+            } else {
+                match c {
+                    None => break,
+                    Some(c) if c.is_ascii_whitespace() || c == 0 => break,
+                    Some(b'"') => in_double = true,
+                    Some(b'\'') => in_single = true,
+                    Some(c) => arg.push(c),
+                }
+            }
+            i += 1;
+        }
+        args.push(arg);
+    }
+}
+
+fn is_hex(c: u8) -> bool {
+    c.is_ascii_hexdigit()
+}
+
+fn hex(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        _ => c - b'A' + 10,
+    }
+}
+
+/// Reads commands and writes RESP2 frames.
+///
+/// A command is decoded as its raw arguments, the command name first. They
+/// are kept as bytes all the way through, so keys and values can hold
+/// anything: newlines, NULs, invalid UTF-8.
+#[derive(Debug, Default)]
+pub struct RespCodec;
+
+impl Decoder for RespCodec {
+    type Item = Vec<Bytes>;
+    type Error = DecodeError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Vec<Bytes>>, DecodeError> {
+        loop {
+            let mut parser = Parser {
+                buf: &src[..],
+                pos: 0,
+            };
+            let inline = match src.first() {
+                None => return Ok(None),
+                Some(b'*') => false,
+                Some(_) => true,
+            };
+            let args = if inline {
+                parser.inline()
+            } else {
+                parser.command()
+            };
+            let consumed = parser.pos;
+
+            match args {
+                // Empty commands are skipped, as Redis does, so a stray
+                // newline from telnet is harmless.
+                Ok(Some(ref args)) if args.is_empty() => {
+                    src.advance(consumed);
+                }
+                Ok(Some(args)) => {
+                    src.advance(consumed);
+                    return Ok(Some(args));
+                }
+                Ok(None) => return Ok(None),
+                Err(DecodeError::Skipped(msg)) => {
+                    src.advance(consumed);
+                    return Err(DecodeError::Skipped(msg));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Encoder for RespCodec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> io::Result<()> {
+        frame.encode(dst);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(input: &[u8]) -> (Result<Option<Vec<Bytes>>, DecodeError>, BytesMut) {
+        let mut buf = BytesMut::from(input);
+        let result = RespCodec.decode(&mut buf);
+        (result, buf)
+    }
+
+    fn args(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::from(*arg)).collect()
+    }
+
+    #[test]
+    fn decodes_a_command() {
+        let (result, rest) = decode(b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n*1");
+        assert_eq!(result.unwrap(), Some(args(&["GET", "k"])));
+        assert_eq!(&rest[..], b"*1");
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_command() {
+        for input in [
+            &b"*2\r\n$3\r\nGET\r\n"[..],
+            b"*2\r\n$3\r\nGE",
+            b"*2",
+            b"*2\r\n$3",
+        ] {
+            let (result, rest) = decode(input);
+            assert_eq!(result.unwrap(), None);
+            assert_eq!(&rest[..], input);
+        }
+    }
+
+    #[test]
+    fn bulk_strings_hold_any_bytes() {
+        let (result, _) = decode(b"*1\r\n$4\r\na\r\n\0\r\n");
+        assert_eq!(result.unwrap(), Some(vec![Bytes::from(&b"a\r\n\0"[..])]));
+    }
+
+    #[test]
+    fn skips_empty_commands() {
+        let (result, rest) = decode(b"*0\r\n\r\n*1\r\n$4\r\nPING\r\n");
+        assert_eq!(result.unwrap(), Some(args(&["PING"])));
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn decodes_inline_commands() {
+        let (result, _) = decode(b"SET k \"a b\\n\" 'c'\r\n");
+        assert_eq!(result.unwrap(), Some(args(&["SET", "k", "a b\n", "c"])));
+        let (result, rest) = decode(b"SET k 'v\r\nPING\n");
+        assert!(matches!(result, Err(DecodeError::Skipped(_))));
+        assert_eq!(&rest[..], b"PING\n");
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        for input in [
+            &b"*x\r\n"[..],
+            b"*1\r\n$-2\r\n",
+            b"*1\r\n+OK\r\n",
+            b"*1\r\n$1\r\nab\r\n",
+        ] {
+            assert!(matches!(decode(input).0, Err(DecodeError::Fatal(_))));
+        }
+    }
+
+    #[test]
+    fn rejects_unterminated_lines_past_the_limit() {
+        let mut long = vec![b'1'; MAX_LINE_LEN + 1];
+        for prefix in [&b"*"[..], b"*1\r\n$", b""] {
+            let input = [prefix, &long[..]].concat();
+            assert!(matches!(decode(&input).0, Err(DecodeError::Fatal(_))));
+        }
+        // Up to the limit, it may still be on its way.
+        long.truncate(MAX_LINE_LEN - 8);
+        let input = [&b"*"[..], &long[..]].concat();
+        assert_eq!(decode(&input).0.unwrap(), None);
+    }
+}
@@ -0,0 +1,64 @@
+//! Graceful shutdown on SIGINT and SIGTERM.
+//!
+//! The signal handler only flips a flag; a timer task watches the flag so the
+//! rest of the shutdown runs on the event loop like everything else. Once it
+//! trips, the listener is dropped and every session is told to drain: run
+//! what it has already read, flush its replies and close. The process exits
+//! when the last session is gone, or when the drain deadline passes.
+
+use tokio::prelude::*;
+use tokio::timer::Interval;
+
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::stats::Stats;
+
+/// How often the signal flag and the drain progress are checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    SIGNALLED.store(true, Ordering::SeqCst);
+}
+
+pub fn install_handlers() {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // Safety: the handler only stores to an atomic, which is async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// Resolves once SIGINT or SIGTERM has been received.
+pub fn signalled() -> impl Future<Item = (), Error = ()> {
+    Interval::new_interval(POLL_INTERVAL)
+        .map_err(|e| println!("shutdown timer failed: {}", e))
+        .skip_while(|_| Ok(!SIGNALLED.load(Ordering::SeqCst)))
+        .into_future()
+        .map(|_| ())
+        .map_err(|_| ())
+}
+
+/// Resolves once every session has finished. If that takes longer than
+/// `deadline`, the process exits with whatever is still connected.
+pub fn drain(stats: Arc<Stats>, deadline: Duration) -> impl Future<Item = (), Error = ()> {
+    let give_up = Instant::now() + deadline;
+    let remaining = stats.clone();
+
+    Interval::new_interval(POLL_INTERVAL)
+        .map_err(|e| println!("shutdown timer failed: {}", e))
+        .take_while(move |_| Ok(remaining.connected_clients.load(Ordering::SeqCst) > 0))
+        .for_each(move |now| {
+            if now >= give_up {
+                let left = stats.connected_clients.load(Ordering::SeqCst);
+                println!("Drain deadline reached, dropping {} connections", left);
+                process::exit(0);
+            }
+            Ok(())
+        })
+}
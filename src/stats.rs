@@ -0,0 +1,23 @@
+//! Server-wide counters, shared by every connection.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Default)]
+pub struct Stats {
+    /// Connections turned away, either by the IP allow/deny lists or because
+    /// `maxclients` was reached.
+    pub rejected_connections: AtomicUsize,
+    /// Sessions currently running.
+    pub connected_clients: AtomicUsize,
+}
+
+impl Stats {
+    /// Bumps a counter and returns its new value.
+    pub fn incr(counter: &AtomicUsize) -> usize {
+        counter.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn decr(counter: &AtomicUsize) {
+        counter.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,237 @@
+//! Streams: append-only logs of entries, each a list of field-value pairs
+//! filed under an ID that only ever grows.
+//!
+//! Redis keeps entries in a radix tree of listpacks; a `BTreeMap` gives the
+//! same ordered, append-friendly access with range queries in O(log n).
+
+use bytes::Bytes;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Bound;
+
+/// An entry ID: a unix time in milliseconds, and a sequence number telling
+/// apart entries added in the same millisecond.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// The smallest ID greater than this one, if there is one.
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => self.ms.checked_add(1).map(|ms| StreamId { ms, seq: 0 }),
+        }
+    }
+
+    /// The greatest ID less than this one, if there is one.
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => self
+                .ms
+                .checked_sub(1)
+                .map(|ms| StreamId { ms, seq: u64::MAX }),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// How many entries Redis packs into each node of a stream. Approximate
+/// trimming only removes whole nodes, so only removes entries in multiples
+/// of this.
+pub const NODE_ENTRIES: usize = 100;
+
+/// What to trim a stream to.
+#[derive(Clone, Copy)]
+pub enum Trim {
+    /// At most this many entries.
+    MaxLen(usize),
+    /// No entries with IDs less than this.
+    MinId(StreamId),
+}
+
+/// An entry's contents, in the order they were given.
+pub type Fields = Vec<(Bytes, Bytes)>;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    /// The ID of the last entry added, which new entries' IDs must be
+    /// greater than even once it has been deleted.
+    pub last_id: StreamId,
+    /// How many entries have ever been added.
+    pub entries_added: u64,
+    /// The greatest ID of any entry removed so far.
+    pub max_deleted_id: StreamId,
+    pub groups: BTreeMap<Bytes, Group>,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn first(&self) -> Option<(&StreamId, &Fields)> {
+        self.entries.first_key_value()
+    }
+
+    pub fn last(&self) -> Option<(&StreamId, &Fields)> {
+        self.entries.last_key_value()
+    }
+
+    /// Adds an entry. `id` must be greater than `last_id`.
+    pub fn add(&mut self, id: StreamId, fields: Fields) {
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    pub fn get(&self, id: &StreamId) -> Option<&Fields> {
+        self.entries.get(id)
+    }
+
+    /// Removes the oldest entries until the stream meets `trim`, but no
+    /// more than `limit` of them, and if `approx`, only a multiple of
+    /// `NODE_ENTRIES`. Returns how many were removed.
+    pub fn trim(&mut self, trim: Trim, approx: bool, limit: usize) -> usize {
+        let excess = match trim {
+            Trim::MaxLen(len) => self.len().saturating_sub(len),
+            Trim::MinId(id) => self.entries.range(..id).count(),
+        };
+        let mut n = excess.min(limit);
+        if approx {
+            n -= n % NODE_ENTRIES;
+        }
+        for _ in 0..n {
+            let (id, _) = self.entries.pop_first().unwrap();
+            self.max_deleted_id = id;
+        }
+        n
+    }
+
+    /// The entries with IDs from `start` to `end` inclusive, in order.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &Fields)> {
+        // BTreeMap::range panics on a backwards range.
+        let (start, end) = if start <= end {
+            (Bound::Included(start), Bound::Included(end))
+        } else {
+            (Bound::Included(start), Bound::Excluded(start))
+        };
+        self.entries.range((start, end))
+    }
+
+    /// The entries with IDs greater than `id`, in order.
+    pub fn after(&self, id: StreamId) -> impl DoubleEndedIterator<Item = (&StreamId, &Fields)> {
+        self.entries.range((Bound::Excluded(id), Bound::Unbounded))
+    }
+}
+
+/// A consumer group: a cursor into the stream that its consumers share,
+/// and the entries delivered to them that they have yet to acknowledge.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Group {
+    /// The last entry delivered to any of the group's consumers.
+    pub last_id: StreamId,
+    /// The pending entries list, or PEL.
+    pub pending: BTreeMap<StreamId, Pending>,
+    pub consumers: BTreeMap<Bytes, Consumer>,
+}
+
+/// An entry delivered to a consumer and not yet acknowledged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pending {
+    pub consumer: Bytes,
+    /// When it was last delivered, in unix milliseconds.
+    pub delivered_at: u64,
+    pub deliveries: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Consumer {
+    /// The IDs of the consumer's entries in the group's PEL.
+    pub pending: BTreeSet<StreamId>,
+    /// When it was created or last tried to read or claim entries, in unix
+    /// milliseconds.
+    pub seen_at: u64,
+    /// When it last read or claimed any, if it ever has.
+    pub active_at: Option<u64>,
+}
+
+impl Group {
+    pub fn new(last_id: StreamId) -> Group {
+        Group {
+            last_id,
+            ..Group::default()
+        }
+    }
+
+    /// The named consumer, created if need be, noting that it was seen at
+    /// `now`.
+    pub fn consumer(&mut self, name: &Bytes, now: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.clone()).or_default();
+        consumer.seen_at = now;
+        consumer
+    }
+
+    /// How many of the stream's entries the group has yet to deliver.
+    pub fn lag(&self, stream: &Stream) -> usize {
+        stream.after(self.last_id).count()
+    }
+
+    /// Makes `id` pending for `consumer`, taking it from whichever consumer
+    /// had it before, and returns its PEL entry.
+    pub fn assign(&mut self, id: StreamId, consumer: &Bytes, now: u64) -> &mut Pending {
+        let previous = self.pending.insert(
+            id,
+            Pending {
+                consumer: consumer.clone(),
+                delivered_at: now,
+                deliveries: 0,
+            },
+        );
+        if let Some(previous) = previous {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+            self.pending.get_mut(&id).unwrap().deliveries = previous.deliveries;
+        }
+        self.consumers
+            .entry(consumer.clone())
+            .or_default()
+            .pending
+            .insert(id);
+        self.pending.get_mut(&id).unwrap()
+    }
+
+    /// Removes `id` from the PEL. Returns false if it wasn't there.
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        match self.pending.remove(&id) {
+            Some(pending) => {
+                if let Some(owner) = self.consumers.get_mut(&pending.consumer) {
+                    owner.pending.remove(&id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,630 @@
+//! Sorted sets: a skiplist ordered by score, then member, paired with a map
+//! from member to score.
+//!
+//! The skiplist is Redis' zskiplist: each link also records how many nodes
+//! it skips over (its span), so finding a member's rank, or the member at a
+//! rank, takes O(log n) like any other lookup. Nodes live in a vector and
+//! link to each other by index, with freed slots reused.
+
+use bytes::Bytes;
+use rand::Rng;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Enough levels for 2^64 elements at `P`.
+const MAX_LEVEL: usize = 32;
+
+/// The chance of a node reaching each level above the first.
+const P: f64 = 0.25;
+
+/// The index of no node.
+const NIL: usize = usize::MAX;
+
+/// The index of the header node, which holds no element.
+const HEAD: usize = 0;
+
+#[derive(Clone, Copy)]
+struct Level {
+    forward: usize,
+    /// How many nodes along the bottom level `forward` is from here.
+    span: usize,
+}
+
+#[derive(Clone)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: usize,
+    levels: Vec<Level>,
+}
+
+impl Node {
+    /// Whether this node sorts before (`score`, `member`).
+    fn before(&self, score: f64, member: &[u8]) -> bool {
+        self.score < score || (self.score == score && &self.member[..] < member)
+    }
+}
+
+/// One end of a score range, as ZRANGE BYSCORE takes them.
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreBound {
+    pub score: f64,
+    pub exclusive: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreRange {
+    pub min: ScoreBound,
+    pub max: ScoreBound,
+}
+
+impl ScoreRange {
+    fn above_min(&self, score: f64) -> bool {
+        if self.min.exclusive {
+            score > self.min.score
+        } else {
+            score >= self.min.score
+        }
+    }
+
+    fn below_max(&self, score: f64) -> bool {
+        if self.max.exclusive {
+            score < self.max.score
+        } else {
+            score <= self.max.score
+        }
+    }
+
+    pub fn contains(&self, score: f64) -> bool {
+        self.above_min(score) && self.below_max(score)
+    }
+}
+
+/// One end of a member range, as ZRANGE BYLEX takes them: `-`, `+`, or a
+/// member that is included (`[`) or not (`(`).
+#[derive(Clone, Debug)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+#[derive(Clone, Debug)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl LexRange {
+    fn above_min(&self, member: &[u8]) -> bool {
+        match self.min {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(ref m) => member >= &m[..],
+            LexBound::Exclusive(ref m) => member > &m[..],
+        }
+    }
+
+    fn below_max(&self, member: &[u8]) -> bool {
+        match self.max {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(ref m) => member <= &m[..],
+            LexBound::Exclusive(ref m) => member < &m[..],
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.above_min(member) && self.below_max(member)
+    }
+}
+
+#[derive(Clone)]
+struct SkipList {
+    nodes: Vec<Node>,
+    /// Slots in `nodes` no longer in use.
+    free: Vec<usize>,
+    tail: usize,
+    len: usize,
+    /// How many levels are in use.
+    level: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> SkipList {
+        let head = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            backward: NIL,
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0,
+                };
+                MAX_LEVEL
+            ],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            tail: NIL,
+            len: 0,
+            level: 1,
+        }
+    }
+}
+
+impl SkipList {
+    fn forward(&self, node: usize, level: usize) -> usize {
+        self.nodes[node].levels[level].forward
+    }
+
+    fn random_level() -> usize {
+        let mut rng = rand::thread_rng();
+        let mut level = 1;
+        while level < MAX_LEVEL && rng.gen::<f64>() < P {
+            level += 1;
+        }
+        level
+    }
+
+    /// Adds an element, which must not already be in the list.
+    fn insert(&mut self, score: f64, member: Bytes) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || !self.nodes[next].before(score, &member) {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = SkipList::random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            member,
+            score,
+            backward: if update[0] == HEAD { NIL } else { update[0] },
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0,
+                };
+                level
+            ],
+        };
+        let x = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let prev = self.nodes[update[i]].levels[i];
+            self.nodes[x].levels[i] = Level {
+                forward: prev.forward,
+                span: prev.span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].levels[i] = Level {
+                forward: x,
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, &prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[prev].levels[i].span += 1;
+        }
+
+        match self.forward(x, 0) {
+            NIL => self.tail = x,
+            next => self.nodes[next].backward = x,
+        }
+        self.len += 1;
+    }
+
+    /// Removes an element. Returns false if it wasn't there.
+    fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || !self.nodes[next].before(score, member) {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let x = self.forward(x, 0);
+        if x == NIL || self.nodes[x].score != score || &self.nodes[x].member[..] != member {
+            return false;
+        }
+
+        for (i, &prev) in update.iter().enumerate().take(self.level) {
+            if self.forward(prev, i) == x {
+                let removed = self.nodes[x].levels[i];
+                let prev = &mut self.nodes[prev].levels[i];
+                prev.span += removed.span;
+                prev.span -= 1;
+                prev.forward = removed.forward;
+            } else {
+                self.nodes[prev].levels[i].span -= 1;
+            }
+        }
+        let backward = self.nodes[x].backward;
+        match self.forward(x, 0) {
+            NIL => self.tail = backward,
+            next => self.nodes[next].backward = backward,
+        }
+        while self.level > 1 && self.forward(HEAD, self.level - 1) == NIL {
+            self.level -= 1;
+        }
+        self.len -= 1;
+
+        // Drop the member now rather than whenever the slot is reused.
+        self.nodes[x].member = Bytes::new();
+        self.nodes[x].levels = Vec::new();
+        self.free.push(x);
+        true
+    }
+
+    /// The 0-based rank of an element that is in the list.
+    fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL {
+                    break;
+                }
+                let node = &self.nodes[next];
+                if !(node.before(score, member) || (node.score == score && node.member == member)) {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if x != HEAD && &self.nodes[x].member[..] == member {
+                return Some(rank - 1);
+            }
+        }
+        None
+    }
+
+    /// The node at a 0-based rank, or `NIL` past the end.
+    fn by_rank(&self, rank: usize) -> usize {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || traversed + self.nodes[x].levels[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return x;
+            }
+        }
+        NIL
+    }
+
+    /// The first node for which `past_start` holds, given that it holds for
+    /// every node after it too.
+    fn first_where<F: Fn(&Node) -> bool>(&self, past_start: F) -> usize {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || past_start(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        self.forward(x, 0)
+    }
+
+    /// The last node for which `before_end` holds, given that it holds for
+    /// every node before it too.
+    fn last_where<F: Fn(&Node) -> bool>(&self, before_end: F) -> usize {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || !before_end(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        if x == HEAD {
+            NIL
+        } else {
+            x
+        }
+    }
+}
+
+/// A sorted set.
+#[derive(Clone, Default)]
+pub struct ZSet {
+    scores: HashMap<Bytes, f64>,
+    list: SkipList,
+}
+
+impl fmt::Debug for ZSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter(0, false)).finish()
+    }
+}
+
+impl PartialEq for ZSet {
+    fn eq(&self, other: &ZSet) -> bool {
+        self.scores == other.scores
+    }
+}
+
+impl ZSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).cloned()
+    }
+
+    /// Sets a member's score, adding it if need be. Returns its old score.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        let old = self.scores.insert(member.clone(), score);
+        match old {
+            Some(old) if old == score => {}
+            Some(old) => {
+                self.list.remove(old, &member);
+                self.list.insert(score, member);
+            }
+            None => self.list.insert(score, member),
+        }
+        old
+    }
+
+    /// Removes a member, returning its score.
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.list.remove(score, member);
+        Some(score)
+    }
+
+    /// Removes the member with the lowest score, or with `max` the highest,
+    /// and returns it with its score.
+    pub fn pop(&mut self, max: bool) -> Option<(Bytes, f64)> {
+        let (member, score) = self.iter(0, max).next()?;
+        let member = member.clone();
+        self.remove(&member);
+        Some((member, score))
+    }
+
+    /// A member's 0-based rank, counting from the highest score if `rev`.
+    pub fn rank(&self, member: &[u8], rev: bool) -> Option<usize> {
+        let score = self.score(member)?;
+        let rank = self.list.rank(score, member)?;
+        Some(if rev { self.len() - 1 - rank } else { rank })
+    }
+
+    /// Elements in order from the one at `rank`, towards higher scores, or
+    /// with `rev`, from the one at `rank` counting from the highest score
+    /// towards lower ones.
+    pub fn iter(&self, rank: usize, rev: bool) -> Iter<'_> {
+        let node = match rank < self.len() {
+            true if rev => self.list.by_rank(self.len() - 1 - rank),
+            true => self.list.by_rank(rank),
+            false => NIL,
+        };
+        Iter {
+            list: &self.list,
+            node,
+            rev,
+        }
+    }
+
+    /// Elements in order from the first within `range`, or with `rev`, from
+    /// the last within it backwards. The iterator doesn't stop at the other
+    /// end of the range.
+    pub fn iter_from_score(&self, range: &ScoreRange, rev: bool) -> Iter<'_> {
+        let node = if rev {
+            self.list.last_where(|n| range.below_max(n.score))
+        } else {
+            self.list.first_where(|n| range.above_min(n.score))
+        };
+        Iter {
+            list: &self.list,
+            node,
+            rev,
+        }
+    }
+
+    /// Like `iter_from_score`, by member. Only meaningful when all the
+    /// elements have the same score, as ZRANGE BYLEX requires.
+    pub fn iter_from_lex(&self, range: &LexRange, rev: bool) -> Iter<'_> {
+        let node = if rev {
+            self.list.last_where(|n| range.below_max(&n.member))
+        } else {
+            self.list.first_where(|n| range.above_min(&n.member))
+        };
+        Iter {
+            list: &self.list,
+            node,
+            rev,
+        }
+    }
+}
+
+/// Walks a sorted set's elements in one direction.
+pub struct Iter<'a> {
+    list: &'a SkipList,
+    node: usize,
+    rev: bool,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a Bytes, f64);
+
+    fn next(&mut self) -> Option<(&'a Bytes, f64)> {
+        if self.node == NIL {
+            return None;
+        }
+        let node = &self.list.nodes[self.node];
+        self.node = if self.rev {
+            node.backward
+        } else {
+            node.levels[0].forward
+        };
+        Some((&node.member, node.score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The elements in the order the skiplist should keep them in.
+    fn sorted(model: &HashMap<Bytes, f64>) -> Vec<(Bytes, f64)> {
+        let mut elements: Vec<_> = model.iter().map(|(m, &s)| (m.clone(), s)).collect();
+        elements.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        elements
+    }
+
+    fn check(zset: &ZSet, model: &HashMap<Bytes, f64>) {
+        let expected = sorted(model);
+        let forward: Vec<_> = zset.iter(0, false).map(|(m, s)| (m.clone(), s)).collect();
+        assert_eq!(forward, expected);
+        let mut backward: Vec<_> = zset.iter(0, true).map(|(m, s)| (m.clone(), s)).collect();
+        backward.reverse();
+        assert_eq!(backward, expected);
+        assert_eq!(zset.len(), expected.len());
+
+        for (rank, (member, _)) in expected.iter().enumerate() {
+            assert_eq!(zset.rank(member, false), Some(rank));
+            assert_eq!(zset.rank(member, true), Some(expected.len() - 1 - rank));
+            assert_eq!(zset.iter(rank, false).next().unwrap().0, member);
+            assert_eq!(
+                zset.iter(expected.len() - 1 - rank, true).next().unwrap().0,
+                member
+            );
+        }
+        assert!(zset.iter(expected.len(), false).next().is_none());
+        assert!(zset.iter(expected.len(), true).next().is_none());
+    }
+
+    #[test]
+    fn skiplist_matches_a_sorted_model() {
+        let mut rng = rand::thread_rng();
+        let mut zset = ZSet::default();
+        let mut model = HashMap::new();
+        for round in 0..2000 {
+            let member = Bytes::from(format!("m{}", rng.gen_range(0, 60)));
+            // Few distinct scores, so that ties are ordered by member.
+            let score = f64::from(rng.gen_range(-5, 5));
+            if rng.gen::<f64>() < 0.3 {
+                assert_eq!(zset.remove(&member), model.remove(&member));
+            } else {
+                assert_eq!(
+                    zset.insert(member.clone(), score),
+                    model.insert(member, score)
+                );
+            }
+            if round % 50 == 0 {
+                check(&zset, &model);
+            }
+        }
+        check(&zset, &model);
+
+        while let Some((member, score)) = zset.pop(false) {
+            let lowest = sorted(&model).remove(0);
+            assert_eq!((member.clone(), score), lowest);
+            model.remove(&member);
+        }
+        assert!(model.is_empty());
+        check(&zset, &model);
+    }
+
+    #[test]
+    fn score_ranges_respect_exclusive_bounds() {
+        let mut zset = ZSet::default();
+        for (member, score) in &[("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0)] {
+            zset.insert(Bytes::from(*member), *score);
+        }
+        let range = |min, min_ex, max, max_ex| ScoreRange {
+            min: ScoreBound {
+                score: min,
+                exclusive: min_ex,
+            },
+            max: ScoreBound {
+                score: max,
+                exclusive: max_ex,
+            },
+        };
+        let first = |range: &ScoreRange, rev| {
+            zset.iter_from_score(range, rev)
+                .next()
+                .map(|(m, _)| m.clone())
+        };
+
+        assert_eq!(
+            first(&range(2.0, false, 3.0, false), false),
+            Some(Bytes::from("b"))
+        );
+        assert_eq!(
+            first(&range(2.0, true, 3.0, false), false),
+            Some(Bytes::from("d"))
+        );
+        assert_eq!(
+            first(&range(1.0, false, 2.0, false), true),
+            Some(Bytes::from("c"))
+        );
+        assert_eq!(
+            first(&range(1.0, false, 2.0, true), true),
+            Some(Bytes::from("a"))
+        );
+        assert_eq!(first(&range(3.0, true, 4.0, false), false), None);
+        assert_eq!(first(&range(0.0, false, 1.0, true), true), None);
+
+        let within: Vec<_> = zset
+            .iter_from_score(&range(1.0, true, 3.0, true), false)
+            .take_while(|&(_, score)| range(1.0, true, 3.0, true).contains(score))
+            .map(|(m, _)| m.clone())
+            .collect();
+        assert_eq!(within, vec![Bytes::from("b"), Bytes::from("c")]);
+    }
+}